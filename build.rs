@@ -0,0 +1,617 @@
+//! Generates typed Rust bindings from the solc output of the contracts.
+//!
+//! The contract sources are compiled with `solc` into `OUT_DIR` on every change,
+//! so the bindings always follow the `.sol` files. Without `solc` the committed
+//! `src/contract/<Contract>.abi` is used together with the `.bin` next to it when
+//! there is one; the bindings then have no bytecode and can't deploy.
+
+use serde_json::Value;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tiny_keccak::keccak256;
+
+const CONTRACT_DIR: &str = "src/contract";
+
+/// (source file, contract name) pairs the bindings are generated for
+const CONTRACTS: &[(&str, &str)] = &[
+    ("priceoracle.sol", "PriceOracle"),
+    ("offchainpriceoracle.sol", "OffchainPriceOracle"),
+    ("offchainpriceoracle.sol", "OffchainPriceGateway"),
+];
+
+fn main() {
+    if let Err(e) = generate() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn generate() -> Result<(), String> {
+    let mut out = String::from("// Generated by build.rs from the contract ABIs, do not edit.\n");
+
+    for (source, contract) in CONTRACTS {
+        let source_path = Path::new(CONTRACT_DIR).join(source);
+        println!("cargo:rerun-if-changed={}", source_path.display());
+
+        let (abi_path, bin_path) = compile(&source_path, contract)?;
+
+        let abi: Value = serde_json::from_str(&read(&abi_path)?)
+            .map_err(|e| format!("{} is not valid json: {}", abi_path.display(), e))?;
+        let bytecode = match bin_path {
+            Some(path) => read(&path)?,
+            None => String::new(),
+        };
+
+        out += &contract_module(contract, &abi, bytecode.trim())?;
+    }
+
+    let dest = PathBuf::from(env::var("OUT_DIR").unwrap()).join("bindings.rs");
+    fs::write(&dest, out).map_err(|e| format!("can't write {}: {}", dest.display(), e))
+}
+
+fn artifact(dir: &Path, contract: &str, ext: &str) -> PathBuf {
+    dir.join(format!("{}.{}", contract, ext))
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))
+}
+
+/// Returns the `.abi` and, unless only the committed ABI is available, the `.bin`
+/// path of the contract.
+fn compile(source: &Path, contract: &str) -> Result<(PathBuf, Option<PathBuf>), String> {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("contract");
+    match Command::new("solc")
+        .args(["--overwrite", "--abi", "--bin", "-o"])
+        .arg(&out_dir)
+        .arg(source)
+        .status()
+    {
+        Ok(status) if status.success() => {
+            return Ok((
+                artifact(&out_dir, contract, "abi"),
+                Some(artifact(&out_dir, contract, "bin")),
+            ))
+        }
+        Ok(_) => return Err(format!("solc failed to compile {}", source.display())),
+        Err(e) => println!(
+            "cargo:warning=solc can't be run ({}), using {}",
+            e, CONTRACT_DIR
+        ),
+    }
+
+    let dir = Path::new(CONTRACT_DIR);
+    let (abi_path, bin_path) = (
+        artifact(dir, contract, "abi"),
+        artifact(dir, contract, "bin"),
+    );
+    println!("cargo:rerun-if-changed={}", abi_path.display());
+    println!("cargo:rerun-if-changed={}", bin_path.display());
+    if !abi_path.exists() {
+        return Err(format!(
+            "{} is missing and solc can't be run, install solc or put the solc --abi output \
+             of {} to {}",
+            abi_path.display(),
+            source.display(),
+            CONTRACT_DIR
+        ));
+    }
+    for path in &[&abi_path, &bin_path] {
+        if path.exists() && modified(path) < modified(source) {
+            println!(
+                "cargo:warning={} is older than {}, install solc or regenerate it with solc --abi --bin",
+                path.display(),
+                source.display()
+            );
+        }
+    }
+    if !bin_path.exists() {
+        println!(
+            "cargo:warning={} is missing, the {} bindings can't deploy the contract",
+            bin_path.display(),
+            contract
+        );
+        return Ok((abi_path, None));
+    }
+    Ok((abi_path, Some(bin_path)))
+}
+
+/// `None` when the file can't be stat'ed, which sorts before any time.
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn contract_module(contract: &str, abi: &Value, bytecode: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let items = abi
+        .as_array()
+        .ok_or_else(|| format!("the {} abi is not a json array", contract))?;
+
+    writeln!(out, "\n/// Bindings of the `{}` contract.", contract).unwrap();
+    writeln!(out, "pub mod {} {{", snake_case(contract)).unwrap();
+    writeln!(
+        out,
+        "    /// Creation bytecode, empty when built without solc and `{}.bin`.",
+        contract
+    )
+    .unwrap();
+    writeln!(out, "    pub const BYTECODE: &str = \"{}\";", bytecode).unwrap();
+
+    let constructor = items.iter().find(|item| item["type"] == "constructor");
+    out += &constructor_fn(contract, constructor)?;
+
+    writeln!(out, "\n    pub mod functions {{").unwrap();
+    let mut names = Vec::new();
+    for item in items.iter().filter(|item| item["type"] == "function") {
+        let name = item["name"].as_str().unwrap();
+        if names.contains(&name) {
+            return Err(format!(
+                "function {}.{} is overloaded, overloads are not supported by the bindings",
+                contract, name
+            ));
+        }
+        names.push(name);
+        out += &function_module(contract, item)?;
+    }
+    writeln!(out, "    }}").unwrap();
+
+    writeln!(out, "\n    pub mod events {{").unwrap();
+    for item in items.iter().filter(|item| item["type"] == "event") {
+        out += &event_struct(contract, item)?;
+    }
+    writeln!(out, "    }}").unwrap();
+
+    writeln!(out, "}}").unwrap();
+    Ok(out)
+}
+
+fn constructor_fn(contract: &str, constructor: Option<&Value>) -> Result<String, String> {
+    let inputs = params(
+        &format!("{}.constructor", contract),
+        constructor.map_or(&Value::Null, |c| &c["inputs"]),
+    )?;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "\n    /// Creation bytecode with the ABI-encoded constructor arguments."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    pub fn deploy_data({}) -> Vec<u8> {{",
+        args_decl(&inputs)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        let mut data = hex::decode(BYTECODE).expect(\"solc output is hex\");"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        data.extend(ethabi::encode(&[{}]));",
+        tokens(&inputs)
+    )
+    .unwrap();
+    writeln!(out, "        data").unwrap();
+    writeln!(out, "    }}").unwrap();
+    Ok(out)
+}
+
+fn function_module(contract: &str, item: &Value) -> Result<String, String> {
+    let name = item["name"].as_str().unwrap();
+    let function = format!("{}.{}", contract, name);
+    let inputs = params(&function, &item["inputs"])?;
+    let outputs = params(&function, &item["outputs"])?;
+    let signature = signature(name, &inputs);
+    let selector = &keccak256(signature.as_bytes())[..4];
+
+    let mut out = String::new();
+    writeln!(out, "        /// `{}`", signature).unwrap();
+    writeln!(out, "        pub mod {} {{", snake_case(name)).unwrap();
+    writeln!(
+        out,
+        "            pub const SIGNATURE: &str = \"{}\";",
+        signature
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "            pub const SELECTOR: [u8; 4] = {:?};",
+        selector
+    )
+    .unwrap();
+
+    writeln!(
+        out,
+        "\n            pub fn encode_input({}) -> Vec<u8> {{",
+        args_decl(&inputs)
+    )
+    .unwrap();
+    writeln!(out, "                let mut data = SELECTOR.to_vec();").unwrap();
+    writeln!(
+        out,
+        "                data.extend(ethabi::encode(&[{}]));",
+        tokens(&inputs)
+    )
+    .unwrap();
+    writeln!(out, "                data").unwrap();
+    writeln!(out, "            }}").unwrap();
+
+    if !outputs.is_empty() {
+        let output_type = match outputs.len() {
+            1 => outputs[0].kind.rust_type(),
+            _ => format!(
+                "({})",
+                outputs
+                    .iter()
+                    .map(|p| p.kind.rust_type())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        writeln!(
+            out,
+            "\n            pub fn decode_output(output: &[u8]) -> Result<{}, ethabi::Error> {{",
+            output_type
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "                let mut tokens = ethabi::decode(&[{}], output)?.into_iter();",
+            param_types(&outputs)
+        )
+        .unwrap();
+        let values = outputs
+            .iter()
+            .map(|p| {
+                format!(
+                    "{{ let t = tokens.next().ok_or_else(crate::bindings::invalid_data)?; {} }}",
+                    p.kind.token_value("t")
+                )
+            })
+            .collect::<Vec<_>>();
+        match values.len() {
+            1 => writeln!(out, "                Ok({})", values[0]).unwrap(),
+            _ => writeln!(out, "                Ok(({}))", values.join(", ")).unwrap(),
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+
+    writeln!(out, "        }}").unwrap();
+    Ok(out)
+}
+
+fn event_struct(contract: &str, item: &Value) -> Result<String, String> {
+    let name = item["name"].as_str().unwrap();
+    let inputs = params(&format!("{}.{}", contract, name), &item["inputs"])?;
+    let signature = signature(name, &inputs);
+    let topic = keccak256(signature.as_bytes());
+
+    let mut out = String::new();
+    writeln!(out, "        /// `{}`", signature).unwrap();
+    writeln!(out, "        #[derive(Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "        pub struct {} {{", name).unwrap();
+    for p in &inputs {
+        writeln!(out, "            pub {}: {},", p.name, p.event_type()).unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+
+    writeln!(out, "\n        impl {} {{", name).unwrap();
+    writeln!(
+        out,
+        "            pub const SIGNATURE: &str = \"{}\";",
+        signature
+    )
+    .unwrap();
+    writeln!(out, "\n            pub fn topic() -> web3::types::H256 {{").unwrap();
+    writeln!(out, "                web3::types::H256::from({:?})", topic).unwrap();
+    writeln!(out, "            }}").unwrap();
+
+    writeln!(
+        out,
+        "\n            pub fn decode(log: &web3::types::Log) -> Result<Self, ethabi::Error> {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "                if log.topics.first() != Some(&Self::topic()) {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "                    return Err(crate::bindings::invalid_data());"
+    )
+    .unwrap();
+    writeln!(out, "                }}").unwrap();
+
+    let data: Vec<&Param> = inputs.iter().filter(|p| !p.indexed).collect();
+    writeln!(
+        out,
+        "                let mut data = ethabi::decode(&[{}], &log.data.0)?.into_iter();",
+        data.iter()
+            .map(|p| p.kind.param_type())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "                let mut topics = log.topics.iter().skip(1);"
+    )
+    .unwrap();
+    writeln!(out, "                Ok({} {{", name).unwrap();
+    for p in &inputs {
+        let value = if !p.indexed {
+            format!(
+                "{{ let t = data.next().ok_or_else(crate::bindings::invalid_data)?; {} }}",
+                p.kind.token_value("t")
+            )
+        } else if p.kind.is_dynamic() {
+            "*topics.next().ok_or_else(crate::bindings::invalid_data)?".to_string()
+        } else {
+            format!(
+                "{{ let topic = topics.next().ok_or_else(crate::bindings::invalid_data)?; \
+                 let t = ethabi::decode(&[{}], &topic.0)?.remove(0); {} }}",
+                p.kind.param_type(),
+                p.kind.token_value("t")
+            )
+        };
+        writeln!(out, "                    {}: {},", p.name, value).unwrap();
+    }
+    writeln!(out, "                }})").unwrap();
+    writeln!(out, "            }}").unwrap();
+    writeln!(out, "        }}").unwrap();
+    Ok(out)
+}
+
+struct Param {
+    name: String,
+    kind: Kind,
+    indexed: bool,
+}
+
+impl Param {
+    /// Indexed dynamic values are stored as their keccak256 hash in the topic.
+    fn event_type(&self) -> String {
+        if self.indexed && self.kind.is_dynamic() {
+            "web3::types::H256".to_string()
+        } else {
+            self.kind.rust_type()
+        }
+    }
+}
+
+enum Kind {
+    Uint(usize),
+    Int(usize),
+    Address,
+    Bool,
+    String,
+    Bytes,
+    FixedBytes(usize),
+    Array(Box<Kind>),
+    FixedArray(Box<Kind>, usize),
+}
+
+impl Kind {
+    /// `None` for the types the bindings don't support, tuples among them.
+    fn parse(ty: &str) -> Option<Kind> {
+        if ty.ends_with(']') {
+            let open = ty.rfind('[')?;
+            let inner = Box::new(Kind::parse(&ty[..open])?);
+            let len = &ty[open + 1..ty.len() - 1];
+            return if len.is_empty() {
+                Some(Kind::Array(inner))
+            } else {
+                Some(Kind::FixedArray(inner, len.parse().ok()?))
+            };
+        }
+        Some(match ty {
+            "address" => Kind::Address,
+            "bool" => Kind::Bool,
+            "string" => Kind::String,
+            "bytes" => Kind::Bytes,
+            "uint" => Kind::Uint(256),
+            "int" => Kind::Int(256),
+            _ if ty.starts_with("uint") => Kind::Uint(ty[4..].parse().ok()?),
+            _ if ty.starts_with("int") => Kind::Int(ty[3..].parse().ok()?),
+            _ if ty.starts_with("bytes") => Kind::FixedBytes(ty[5..].parse().ok()?),
+            _ => return None,
+        })
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Kind::String | Kind::Bytes | Kind::Array(_) => true,
+            Kind::FixedArray(inner, _) => inner.is_dynamic(),
+            _ => false,
+        }
+    }
+
+    fn signature(&self) -> String {
+        match self {
+            Kind::Uint(size) => format!("uint{}", size),
+            Kind::Int(size) => format!("int{}", size),
+            Kind::Address => "address".to_string(),
+            Kind::Bool => "bool".to_string(),
+            Kind::String => "string".to_string(),
+            Kind::Bytes => "bytes".to_string(),
+            Kind::FixedBytes(size) => format!("bytes{}", size),
+            Kind::Array(inner) => format!("{}[]", inner.signature()),
+            Kind::FixedArray(inner, len) => format!("{}[{}]", inner.signature(), len),
+        }
+    }
+
+    fn rust_type(&self) -> String {
+        match self {
+            Kind::Uint(_) | Kind::Int(_) => "web3::types::U256".to_string(),
+            Kind::Address => "web3::types::Address".to_string(),
+            Kind::Bool => "bool".to_string(),
+            Kind::String => "String".to_string(),
+            Kind::Bytes | Kind::FixedBytes(_) => "Vec<u8>".to_string(),
+            Kind::Array(inner) | Kind::FixedArray(inner, _) => {
+                format!("Vec<{}>", inner.rust_type())
+            }
+        }
+    }
+
+    fn param_type(&self) -> String {
+        match self {
+            Kind::Uint(size) => format!("ethabi::ParamType::Uint({})", size),
+            Kind::Int(size) => format!("ethabi::ParamType::Int({})", size),
+            Kind::Address => "ethabi::ParamType::Address".to_string(),
+            Kind::Bool => "ethabi::ParamType::Bool".to_string(),
+            Kind::String => "ethabi::ParamType::String".to_string(),
+            Kind::Bytes => "ethabi::ParamType::Bytes".to_string(),
+            Kind::FixedBytes(size) => format!("ethabi::ParamType::FixedBytes({})", size),
+            Kind::Array(inner) => {
+                format!("ethabi::ParamType::Array(Box::new({}))", inner.param_type())
+            }
+            Kind::FixedArray(inner, len) => format!(
+                "ethabi::ParamType::FixedArray(Box::new({}), {})",
+                inner.param_type(),
+                len
+            ),
+        }
+    }
+
+    /// Expression turning the rust value `v` into an `ethabi::Token`.
+    fn to_token(&self, v: &str) -> String {
+        match self {
+            Kind::Uint(_) => format!("ethabi::Token::Uint({})", v),
+            Kind::Int(_) => format!("ethabi::Token::Int({})", v),
+            Kind::Address => format!("ethabi::Token::Address({})", v),
+            Kind::Bool => format!("ethabi::Token::Bool({})", v),
+            Kind::String => format!("ethabi::Token::String({})", v),
+            Kind::Bytes => format!("ethabi::Token::Bytes({})", v),
+            Kind::FixedBytes(_) => format!("ethabi::Token::FixedBytes({})", v),
+            Kind::Array(inner) => format!(
+                "ethabi::Token::Array({}.into_iter().map(|v| {}).collect())",
+                v,
+                inner.to_token("v")
+            ),
+            Kind::FixedArray(inner, _) => format!(
+                "ethabi::Token::FixedArray({}.into_iter().map(|v| {}).collect())",
+                v,
+                inner.to_token("v")
+            ),
+        }
+    }
+
+    /// Expression turning the `ethabi::Token` `t` into the rust value, `?` on mismatch.
+    fn token_value(&self, t: &str) -> String {
+        let method = match self {
+            Kind::Uint(_) => "to_uint",
+            Kind::Int(_) => "to_int",
+            Kind::Address => "to_address",
+            Kind::Bool => "to_bool",
+            Kind::String => "to_string",
+            Kind::Bytes => "to_bytes",
+            Kind::FixedBytes(_) => "to_fixed_bytes",
+            Kind::Array(inner) => {
+                return format!(
+                    "{}.to_array().ok_or_else(crate::bindings::invalid_data)?.into_iter()\
+                     .map(|t| Ok({})).collect::<Result<Vec<_>, ethabi::Error>>()?",
+                    t,
+                    inner.token_value("t")
+                )
+            }
+            Kind::FixedArray(inner, _) => {
+                return format!(
+                    "{}.to_fixed_array().ok_or_else(crate::bindings::invalid_data)?.into_iter()\
+                     .map(|t| Ok({})).collect::<Result<Vec<_>, ethabi::Error>>()?",
+                    t,
+                    inner.token_value("t")
+                )
+            }
+        };
+        format!(
+            "{}.{}().ok_or_else(crate::bindings::invalid_data)?",
+            t, method
+        )
+    }
+}
+
+/// Parameters of `item`, the `Contract.function` or `Contract.Event` named in errors.
+fn params(item: &str, value: &Value) -> Result<Vec<Param>, String> {
+    value
+        .as_array()
+        .map_or(&[][..], |params| params.as_slice())
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let ty = p["type"].as_str().unwrap_or_default();
+            let kind = Kind::parse(ty).ok_or_else(|| {
+                format!(
+                    "{} takes or returns the abi type {}, which is not supported by the bindings",
+                    item, ty
+                )
+            })?;
+            let name = match p["name"].as_str() {
+                Some(name) if !name.is_empty() => snake_case(name),
+                _ => format!("arg{}", i),
+            };
+            Ok(Param {
+                name,
+                kind,
+                indexed: p["indexed"].as_bool().unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+fn signature(name: &str, params: &[Param]) -> String {
+    let types: Vec<String> = params.iter().map(|p| p.kind.signature()).collect();
+    format!("{}({})", name, types.join(","))
+}
+
+fn args_decl(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|p| format!("{}: {}", p.name, p.kind.rust_type()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn tokens(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|p| p.kind.to_token(&p.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn param_types(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|p| p.kind.param_type())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `updatePrice` -> `update_price`, `_newPrice` -> `new_price`, `ETHPrice` -> `eth_price`
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.trim_start_matches('_').chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    match out.as_str() {
+        "type" | "match" | "ref" | "self" | "fn" | "mod" | "move" | "loop" => out + "_",
+        _ => out,
+    }
+}
@@ -0,0 +1,229 @@
+//! Admin API of the service: bearer token authentication, the actions queued
+//! for the poll loop and the audit log.
+//!
+//! The txs of the actions are sent by the loop itself between the polls, so
+//! they share the nonces and the leader lease with the price updates.
+
+use crate::args;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, Notify};
+use web3::types::{Address, TransactionReceipt, U256};
+
+/// Action the poll loop runs for the admin API.
+#[derive(Debug)]
+pub enum Action {
+    /// Pushes the price now, the answer overrides the price of the source
+    Update { answer: Option<U256> },
+    /// Calls `setAdmin`
+    SetAdmin { admin: Address, value: bool },
+}
+
+/// Queued action with the channel of its result, `None` when no tx was needed.
+pub struct Request {
+    pub action: Action,
+    pub reply: oneshot::Sender<Result<Option<TransactionReceipt>>>,
+}
+
+/// State of the poll loop shared with the admin API.
+#[derive(Default)]
+pub struct Control {
+    paused: AtomicBool,
+    queue: Mutex<VecDeque<Request>>,
+    wake: Notify,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Control::default()
+    }
+
+    /// Paused loop keeps running the admin actions but doesn't push prices.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Returns the previous state.
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.paused.swap(paused, Ordering::SeqCst)
+    }
+
+    /// Queues the action and wakes the loop up.
+    pub fn submit(&self, action: Action) -> oneshot::Receiver<Result<Option<TransactionReceipt>>> {
+        let (reply, receiver) = oneshot::channel();
+        self.lock_queue().push_back(Request { action, reply });
+        self.wake.notify();
+        receiver
+    }
+
+    /// Next queued action.
+    pub fn take(&self) -> Option<Request> {
+        self.lock_queue().pop_front()
+    }
+
+    /// Completes when an action is submitted.
+    pub async fn woken(&self) {
+        self.wake.notified().await
+    }
+
+    fn lock_queue(&self) -> std::sync::MutexGuard<'_, VecDeque<Request>> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Entry of the audit log.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    time: u64,
+    /// Name of the token, `None` when the request was not authenticated
+    principal: Option<&'a str>,
+    action: &'a str,
+    params: &'a serde_json::Value,
+    /// `ok` or the error
+    result: &'a str,
+}
+
+/// Authenticates and audits the admin requests.
+pub struct Admin {
+    control: Arc<Control>,
+    /// Name and token of every operator
+    tokens: Vec<(String, String)>,
+    audit: Mutex<File>,
+}
+
+impl Admin {
+    pub fn new(
+        control: Arc<Control>,
+        tokens: Vec<(String, String)>,
+        audit_log: &str,
+    ) -> Result<Self> {
+        let audit = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(audit_log)
+            .map_err(|e| Error::Storage(format!("{}: {}", audit_log, e)))?;
+
+        Ok(Admin {
+            control,
+            tokens,
+            audit: Mutex::new(audit),
+        })
+    }
+
+    /// Admin API of `--admin_tokens` and `--admin_audit_log`.
+    pub fn from_args(control: Arc<Control>, arg: &ArgMatches) -> Result<Self> {
+        let tokens = args::list(arg, "admin_tokens")
+            .into_iter()
+            .map(|entry| {
+                let mut parts = entry.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(token)) if !name.is_empty() && !token.is_empty() => {
+                        Ok((name.to_string(), token.to_string()))
+                    }
+                    _ => Err(Error::invalid(
+                        "admin_tokens",
+                        format!("{} is not name:token", entry),
+                    )),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if tokens.is_empty() {
+            return Err(Error::missing("admin_tokens"));
+        }
+
+        Admin::new(control, tokens, args::required(arg, "admin_audit_log")?)
+    }
+
+    pub fn control(&self) -> &Control {
+        &self.control
+    }
+
+    /// Name of the operator whose token is in `Authorization: Bearer <token>`.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Option<&str> {
+        let token = headers
+            .get(AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?
+            .trim();
+        self.tokens
+            .iter()
+            .find(|(_, known)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Appends the action to the audit log and logs it.
+    pub fn audit(
+        &self,
+        logger: &slog::Logger,
+        principal: Option<&str>,
+        action: &str,
+        params: &serde_json::Value,
+        result: &str,
+    ) {
+        info!(
+            logger,
+            "admin {} by {} with {}: {}",
+            action,
+            principal.unwrap_or("unauthenticated"),
+            params,
+            result
+        );
+
+        let entry = AuditEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            principal,
+            action,
+            params,
+            result,
+        };
+        let line = serde_json::to_string(&entry).unwrap_or_default();
+        let mut audit = self
+            .audit
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = writeln!(audit, "{}", line).and_then(|()| audit.flush()) {
+            error!(logger, "audit log failed: {}", e);
+        }
+    }
+}
+
+/// Compares the tokens without leaking the length of the matching prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Admin API arguments of `service`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("admin_bind")
+            .env("PO_ADMIN_BIND")
+            .long("admin_bind")
+            .default_value("127.0.0.1:9200")
+            .help("address:port of the /admin endpoints, plain http so loopback by default"),
+        Arg::with_name("admin_tokens")
+            .env("PO_ADMIN_TOKENS")
+            .long("admin_tokens")
+            .help(
+                "comma separated name:token bearer tokens of the operators, enables the admin API",
+            ),
+        Arg::with_name("admin_audit_log")
+            .env("PO_ADMIN_AUDIT_LOG")
+            .long("admin_audit_log")
+            .default_value("admin_audit.log")
+            .help("file the admin actions are appended to as JSON lines"),
+    ]
+}
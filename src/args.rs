@@ -0,0 +1,123 @@
+use crate::client::Signer;
+use crate::error::{Error, Result};
+use clap::ArgMatches;
+use std::fmt::Display;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use web3::types::{Address, H256, U256};
+
+pub fn required<'a>(arg: &'a ArgMatches, name: &'static str) -> Result<&'a str> {
+    arg.value_of(name).ok_or_else(|| Error::missing(name))
+}
+
+pub fn parse<T>(arg: &ArgMatches, name: &'static str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    required(arg, name)?
+        .parse()
+        .map_err(|e| Error::invalid(name, e))
+}
+
+pub fn parse_opt<T>(arg: &ArgMatches, name: &'static str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match arg.value_of(name) {
+        Some(val) => val.parse().map(Some).map_err(|e| Error::invalid(name, e)),
+        None => Ok(None),
+    }
+}
+
+pub fn address(arg: &ArgMatches, name: &'static str) -> Result<Address> {
+    let val = required(arg, name)?;
+    val.trim_start_matches("0x")
+        .parse()
+        .map_err(|_| Error::invalid(name, format!("{} is not an ethereum address", val)))
+}
+
+pub fn address_opt(arg: &ArgMatches, name: &'static str) -> Result<Option<Address>> {
+    match arg.value_of(name) {
+        Some(_) => address(arg, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Resolves the `address:port` value of the argument.
+pub fn socket_addr(arg: &ArgMatches, name: &'static str) -> Result<SocketAddr> {
+    required(arg, name)?
+        .to_socket_addrs()
+        .map_err(|e| Error::invalid(name, e))?
+        .next()
+        .ok_or_else(|| Error::invalid(name, "address is not resolved"))
+}
+
+pub fn socket_addr_opt(arg: &ArgMatches, name: &'static str) -> Result<Option<SocketAddr>> {
+    match arg.value_of(name) {
+        Some(_) => socket_addr(arg, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Comma separated values, empty when the argument is not set.
+pub fn list(arg: &ArgMatches, name: &'static str) -> Vec<String> {
+    arg.value_of(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|val| val.trim().to_string())
+        .filter(|val| !val.is_empty())
+        .collect()
+}
+
+/// Comma separated urls, the trailing `/` is trimmed.
+pub fn urls(arg: &ArgMatches, name: &'static str) -> Vec<String> {
+    list(arg, name)
+        .into_iter()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .collect()
+}
+
+pub fn addresses(arg: &ArgMatches, name: &'static str) -> Result<Vec<Address>> {
+    list(arg, name)
+        .iter()
+        .map(|val| {
+            val.trim_start_matches("0x")
+                .parse()
+                .map_err(|_| Error::invalid(name, format!("{} is not an ethereum address", val)))
+        })
+        .collect()
+}
+
+pub fn u256(arg: &ArgMatches, name: &'static str) -> Result<U256> {
+    let val = required(arg, name)?;
+    U256::from_dec_str(val).map_err(|_| Error::invalid(name, format!("{} is not a uint256", val)))
+}
+
+pub fn u256_opt(arg: &ArgMatches, name: &'static str) -> Result<Option<U256>> {
+    match arg.value_of(name) {
+        Some(_) => u256(arg, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn private_key(arg: &ArgMatches, name: &'static str) -> Result<H256> {
+    let val = required(arg, name)?;
+    let bytes = hex::decode(val.trim_start_matches("0x").as_bytes())
+        .map_err(|e| Error::invalid(name, e))?;
+    ethtxsign::pvt_key_from_slice(bytes.as_slice())
+        .ok_or_else(|| Error::invalid(name, "private key must be 32 bytes"))
+}
+
+/// Local tx signer, `None` when `from_addr` is not set and the node accounts are used.
+pub fn signer(arg: &ArgMatches) -> Result<Option<Signer>> {
+    match address_opt(arg, "from_addr")? {
+        Some(address) => Ok(Some(Signer {
+            address,
+            private_key: private_key(arg, "private_key")?,
+            chain_id: parse(arg, "chain_id")?,
+        })),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,14 @@
+//! Typed bindings of the contracts generated by `build.rs` from the solc output.
+//!
+//! Every contract gets a module with the creation bytecode, `functions::<name>::encode_input`
+//! (and `decode_output` for the functions returning values) and a struct per event
+//! with `topic()` and `decode(&Log)`. A change of the ABI changes these signatures,
+//! so the callers fail to compile until they are updated.
+
+#![allow(unused_mut, unused_variables)]
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+pub(crate) fn invalid_data() -> ethabi::Error {
+    ethabi::ErrorKind::InvalidData.into()
+}
@@ -0,0 +1,217 @@
+//! EIP-3668 CCIP-Read gateway of the prices signed by the oracle key.
+//!
+//! `price` of the `OffchainPriceOracle` contract reverts with
+//! `OffchainLookup`, the client sends the `callData` of the revert to the
+//! gateway and calls `priceWithProof` of the contract with the answer, which
+//! accepts the round only when it is signed by the gateway key.
+//!
+//! The request comes from the bindings of the contract. The digest is computed
+//! like `responseDigest` of the contract and signed only for the contracts of
+//! `--ccip_senders`: a hash returned by the sender is never signed, any
+//! contract could return the digest of a forged answer.
+
+use crate::args;
+use crate::bindings::offchain_price_gateway::functions as gateway;
+use crate::client::Round;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use ethtxsign::abi::{self, Signature, Token};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use web3::types::{Address, H256, U256};
+
+/// Round of the pair requested by the contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceRequest {
+    pub pair: String,
+    /// Zero for the latest round
+    pub round_id: U256,
+}
+
+impl PriceRequest {
+    pub fn decode(call_data: &[u8]) -> Result<Self> {
+        let tokens = Signature::parse(gateway::price::SIGNATURE)
+            .and_then(|signature| signature.decode_call(call_data))
+            .map_err(|e| {
+                Error::Abi(format!(
+                    "callData is not {}: {}",
+                    gateway::price::SIGNATURE,
+                    e
+                ))
+            })?;
+        match tokens.as_slice() {
+            [Token::String(pair), Token::Uint(round_id)] => Ok(PriceRequest {
+                pair: pair.clone(),
+                round_id: *round_id,
+            }),
+            _ => Err(Error::Abi(format!(
+                "callData is not {}",
+                gateway::price::SIGNATURE
+            ))),
+        }
+    }
+}
+
+/// `responseDigest(request, roundId, answer, updatedAt, expires)` of the
+/// `OffchainPriceOracle` at `sender`, where `request` is the hash of the
+/// `callData`: `keccak256(abi.encode(sender, request, roundId, answer,
+/// updatedAt, expires))`.
+pub fn response_digest(sender: Address, request: H256, round: &Round, expires: u64) -> H256 {
+    let encoded = abi::encode(&[
+        Token::Address(sender),
+        Token::FixedBytes(request.as_bytes().to_vec()),
+        Token::Uint(round.round_id),
+        Token::Uint(round.answer),
+        Token::Uint(round.updated_at),
+        Token::Uint(expires.into()),
+    ]);
+    H256::from_slice(&ethtxsign::keccak256_hash(&encoded))
+}
+
+/// Signs the rounds for the contracts with the gateway key.
+pub struct Gateway {
+    address: Address,
+    private_key: H256,
+    /// Time the contracts accept the answer for
+    ttl: Duration,
+    /// `OffchainPriceOracle` contracts the answers are signed for
+    senders: HashSet<Address>,
+}
+
+impl Gateway {
+    pub fn new(private_key: H256, ttl: Duration, senders: HashSet<Address>) -> Result<Self> {
+        Ok(Gateway {
+            address: ethtxsign::private_key_address(&private_key)?,
+            private_key,
+            ttl,
+            senders,
+        })
+    }
+
+    /// Gateway of `--ccip_private_key`, `None` when it is not set.
+    pub fn from_args(arg: &ArgMatches) -> Result<Option<Self>> {
+        if arg.value_of("ccip_private_key").is_none() {
+            return Ok(None);
+        }
+        let senders: HashSet<Address> = args::addresses(arg, "ccip_senders")?.into_iter().collect();
+        if senders.is_empty() {
+            return Err(Error::missing("ccip_senders"));
+        }
+        Gateway::new(
+            args::private_key(arg, "ccip_private_key")?,
+            Duration::from_secs(args::parse(arg, "ccip_ttl")?),
+            senders,
+        )
+        .map(Some)
+    }
+
+    /// Address the contracts check the signatures against.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The answers are signed for the contract.
+    pub fn allows(&self, sender: Address) -> bool {
+        self.senders.contains(&sender)
+    }
+
+    /// `(uint80 roundId, uint256 answer, uint256 updatedAt, uint64 expires,
+    /// bytes signature)` answer to the `callData` of the contract at `sender`.
+    pub fn sign(&self, sender: Address, call_data: &[u8], round: &Round) -> Result<Vec<u8>> {
+        if !self.allows(sender) {
+            return Err(Error::Signing(format!(
+                "{:?} is not in --ccip_senders",
+                sender
+            )));
+        }
+        let expires = (SystemTime::now() + self.ttl)
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let request = H256::from_slice(&ethtxsign::keccak256_hash(call_data));
+        let digest = response_digest(sender, request, round, expires);
+        let signature = ethtxsign::sign_hash(&digest, &self.private_key)?;
+
+        Ok(abi::encode(&[
+            Token::Uint(round.round_id),
+            Token::Uint(round.answer),
+            Token::Uint(round.updated_at),
+            Token::Uint(expires.into()),
+            Token::Bytes(signature),
+        ]))
+    }
+}
+
+/// Gateway arguments of `server`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("ccip_private_key")
+            .env("PO_CCIP_PRIVATE_KEY")
+            .long("ccip_private_key")
+            .help("key signing the CCIP-Read answers, enables /v1/ccip; the signer of OffchainPriceOracle"),
+        Arg::with_name("ccip_senders")
+            .env("PO_CCIP_SENDERS")
+            .long("ccip_senders")
+            .help("comma separated OffchainPriceOracle addresses the answers are signed for, required by the gateway"),
+        Arg::with_name("ccip_ttl")
+            .env("PO_CCIP_TTL")
+            .long("ccip_ttl")
+            .default_value("300")
+            .help("seconds the contracts accept a signed answer for"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_digest_is_the_abi_encode_of_the_contract() {
+        let sender: Address = "00000000000000000000000000000000000000aa".parse().unwrap();
+        let request = H256::from_low_u64_be(0xbb);
+        let round = Round {
+            round_id: 3u64.into(),
+            answer: 0x0de0b6b3a7640000u64.into(),
+            updated_at: 1_600_000_000u64.into(),
+            answered_in_round: 3u64.into(),
+        };
+
+        // abi.encode(address, bytes32, uint80, uint256, uint256, uint64), one word each
+        let encoded = hex::decode(
+            "00000000000000000000000000000000000000000000000000000000000000aa\
+             00000000000000000000000000000000000000000000000000000000000000bb\
+             0000000000000000000000000000000000000000000000000000000000000003\
+             0000000000000000000000000000000000000000000000000de0b6b3a7640000\
+             000000000000000000000000000000000000000000000000000000005f5e1000\
+             000000000000000000000000000000000000000000000000000000005f5e112c",
+        )
+        .unwrap();
+        assert_eq!(
+            response_digest(sender, request, &round, 1_600_000_300),
+            H256::from_slice(&ethtxsign::keccak256_hash(&encoded))
+        );
+    }
+
+    #[test]
+    fn only_the_configured_senders_are_signed_for() {
+        let allowed: Address = "00000000000000000000000000000000000000aa".parse().unwrap();
+        let private_key = H256::from_low_u64_be(1);
+        let gateway = Gateway::new(
+            private_key,
+            Duration::from_secs(300),
+            vec![allowed].into_iter().collect(),
+        )
+        .unwrap();
+        let round = Round {
+            round_id: 1u64.into(),
+            answer: 1u64.into(),
+            updated_at: 1u64.into(),
+            answered_in_round: 1u64.into(),
+        };
+
+        assert!(gateway.sign(allowed, b"call", &round).is_ok());
+        assert!(gateway
+            .sign(Address::from_low_u64_be(0xcc), b"call", &round)
+            .is_err());
+    }
+}
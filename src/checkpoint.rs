@@ -0,0 +1,54 @@
+//! Last fully processed block persisted between the runs.
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Block number stored as text in a file, replaced atomically on save.
+pub struct Checkpoint {
+    path: PathBuf,
+    last: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Reads the stored block, a missing file means nothing was processed yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Checkpoint { path, last: None })
+            }
+            Err(e) => return Err(Error::Storage(format!("{}: {}", path.display(), e))),
+        };
+        let last = content
+            .trim()
+            .parse()
+            .map_err(|e| Error::Storage(format!("{} is corrupted: {}", path.display(), e)))?;
+
+        Ok(Checkpoint {
+            path,
+            last: Some(last),
+        })
+    }
+
+    /// Last fully processed block.
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Stores the block when it is past the stored one.
+    pub fn save(&mut self, block: u64) -> Result<()> {
+        if self.last.map_or(false, |last| last >= block) {
+            return Ok(());
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n", block))
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .map_err(|e| Error::Storage(format!("{}: {}", self.path.display(), e)))?;
+        self.last = Some(block);
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Argument helpers of the commands kept in the binary, the library parses its
+//! own arguments in the `from_args` of its types.
+
+use clap::ArgMatches;
+use priceoracle::error::{Error, Result};
+use std::fmt::Display;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use web3::types::{Address, U256};
+
+pub fn required<'a>(arg: &'a ArgMatches, name: &'static str) -> Result<&'a str> {
+    arg.value_of(name).ok_or_else(|| Error::missing(name))
+}
+
+pub fn parse<T>(arg: &ArgMatches, name: &'static str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    required(arg, name)?
+        .parse()
+        .map_err(|e| Error::invalid(name, e))
+}
+
+pub fn address(arg: &ArgMatches, name: &'static str) -> Result<Address> {
+    let val = required(arg, name)?;
+    val.trim_start_matches("0x")
+        .parse()
+        .map_err(|_| Error::invalid(name, format!("{} is not an ethereum address", val)))
+}
+
+pub fn address_opt(arg: &ArgMatches, name: &'static str) -> Result<Option<Address>> {
+    match arg.value_of(name) {
+        Some(_) => address(arg, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn u256_opt(arg: &ArgMatches, name: &'static str) -> Result<Option<U256>> {
+    match arg.value_of(name) {
+        Some(val) => U256::from_dec_str(val)
+            .map(Some)
+            .map_err(|_| Error::invalid(name, format!("{} is not a uint256", val))),
+        None => Ok(None),
+    }
+}
+
+/// Resolves the `address:port` value of the argument.
+pub fn socket_addr(arg: &ArgMatches, name: &'static str) -> Result<SocketAddr> {
+    required(arg, name)?
+        .to_socket_addrs()
+        .map_err(|e| Error::invalid(name, e))?
+        .next()
+        .ok_or_else(|| Error::invalid(name, "address is not resolved"))
+}
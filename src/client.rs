@@ -0,0 +1,429 @@
+use crate::args;
+use crate::bindings::price_oracle::{events, functions};
+use crate::error::{Error, Result};
+use crate::gasprice::GasConfig;
+use crate::web3util;
+use clap::ArgMatches;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use web3::api::SubscriptionStream;
+use web3::futures::Future;
+use web3::types::{
+    Address, BlockNumber, Bytes, CallRequest, Filter, FilterBuilder, Log, TransactionReceipt,
+    TransactionRequest, H256, U256,
+};
+use web3::{DuplexTransport, Transport};
+
+/// The contract keeps the price multiplied by 10^18.
+pub const PRICE_DECIMALS: usize = 18;
+
+/// Interval of the receipt polls of a sent tx.
+pub const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Key used to sign txs locally instead of the accounts of the node.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    pub address: Address,
+    pub private_key: H256,
+    pub chain_id: u8,
+}
+
+impl Signer {
+    /// Signer of `--from_addr`, `--private_key` and `--chain_id`, `None` when
+    /// `--from_addr` is not set and the node accounts are used.
+    pub fn from_args(arg: &ArgMatches) -> Result<Option<Self>> {
+        args::signer(arg)
+    }
+}
+
+/// Price round stored by the contract and emitted in `PriceChanged`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Round {
+    pub round_id: U256,
+    /// BTC for 1 ETH multiplied by 10^18
+    pub answer: U256,
+    /// Unix timestamp of the block the round was stored in
+    pub updated_at: U256,
+    pub answered_in_round: U256,
+}
+
+impl Round {
+    pub fn from_log(log: &Log) -> Result<Self> {
+        Ok(events::PriceChanged::decode(log)?.into())
+    }
+
+    /// Round of the `PriceChanged` event emitted by the tx.
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Option<Self> {
+        receipt
+            .logs
+            .iter()
+            .find_map(|log| Round::from_log(log).ok())
+    }
+
+    /// Seconds passed since the round was stored.
+    pub fn age(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        now.saturating_sub(self.updated_at.low_u64())
+    }
+}
+
+/// `(roundId, answer, startedAt, updatedAt, answeredInRound)` of `AggregatorV3Interface`
+type RoundData = (U256, U256, U256, U256, U256);
+
+impl From<RoundData> for Round {
+    fn from((round_id, answer, _started_at, updated_at, answered_in_round): RoundData) -> Self {
+        Round {
+            round_id,
+            answer,
+            updated_at,
+            answered_in_round,
+        }
+    }
+}
+
+impl From<events::PriceChanged> for Round {
+    fn from(event: events::PriceChanged) -> Self {
+        Round {
+            round_id: event.round_id,
+            answer: event.answer,
+            updated_at: event.updated_at,
+            answered_in_round: event.answered_in_round,
+        }
+    }
+}
+
+/// Client of the deployed `PriceOracle` contract.
+///
+/// Txs are signed by the [`Signer`] when it is set, otherwise they are sent
+/// from the first account of the node.
+pub struct PriceOracle<T: Transport> {
+    web3: web3::Web3<T>,
+    address: Address,
+    signer: Option<Signer>,
+    gas: GasConfig,
+    confirmations: usize,
+}
+
+impl<T: Transport> PriceOracle<T> {
+    pub fn new(
+        web3: web3::Web3<T>,
+        address: Address,
+        signer: Option<Signer>,
+        gas: GasConfig,
+    ) -> Self {
+        PriceOracle {
+            web3,
+            address,
+            signer,
+            gas,
+            confirmations: 1,
+        }
+    }
+
+    /// Number of blocks to wait for after the tx is mined, 1 by default.
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn web3(&self) -> &web3::Web3<T> {
+        &self.web3
+    }
+
+    /// Returns the latest round of the contract, `None` when the price was never set.
+    pub fn latest_round(&self) -> Result<Option<Round>> {
+        let output = self.call(functions::latest_round_data::encode_input())?;
+        let round = Round::from(functions::latest_round_data::decode_output(&output)?);
+
+        Ok(if round.round_id.is_zero() {
+            None
+        } else {
+            Some(round)
+        })
+    }
+
+    /// Returns the round with the id, reverts for the unknown rounds.
+    pub fn round(&self, round_id: U256) -> Result<Round> {
+        let output = self.call(functions::get_round_data::encode_input(round_id))?;
+        Ok(functions::get_round_data::decode_output(&output)?.into())
+    }
+
+    /// Returns the `decimals` and the `description` of the feed.
+    pub fn feed_info(&self) -> Result<(u8, String)> {
+        let output = self.call(functions::decimals::encode_input())?;
+        let decimals = functions::decimals::decode_output(&output)?.low_u32() as u8;
+        let output = self.call(functions::description::encode_input())?;
+        let description = functions::description::decode_output(&output)?;
+
+        Ok((decimals, description))
+    }
+
+    /// Returns the answer of the latest round, `None` when the price was never set.
+    pub fn price(&self) -> Result<Option<U256>> {
+        Ok(self.latest_round()?.map(|round| round.answer))
+    }
+
+    /// Returns the last `PriceChanged` event, `None` when the price was never set.
+    pub fn last_price_changed(&self) -> Result<Option<Log>> {
+        let mut logs = self
+            .web3
+            .eth()
+            .logs(price_changed_filter(self.address, BlockNumber::Earliest))
+            .wait()?;
+
+        Ok(logs.pop())
+    }
+
+    /// Returns the current admins replayed from the `AdminChanged` events.
+    pub fn admins(&self) -> Result<Vec<Address>> {
+        let filter = event_filter(
+            self.address,
+            events::AdminChanged::topic(),
+            BlockNumber::Earliest,
+        );
+        let logs = self.web3.eth().logs(filter).wait()?;
+
+        let mut admins = Vec::new();
+        for log in &logs {
+            let event = events::AdminChanged::decode(log)?;
+            admins.retain(|admin| *admin != event.admin);
+            if event.value {
+                admins.push(event.admin);
+            }
+        }
+
+        Ok(admins)
+    }
+
+    /// Returns the keccak256 hash of the deployed contract code.
+    pub fn code_hash(&self) -> Result<H256> {
+        let code = self.web3.eth().code(self.address, None).wait()?;
+        Ok(web3util::h256_topic(ethtxsign::keccak256_hash(&code.0)))
+    }
+
+    /// Returns whether the address is allowed to update the price.
+    pub fn is_admin(&self, address: Address) -> Result<bool> {
+        let output = self.call(functions::is_admin::encode_input(address))?;
+        Ok(functions::is_admin::decode_output(&output)?)
+    }
+
+    /// Sends `updatePrice` and waits for the receipt.
+    pub fn update_price(
+        &self,
+        logger: &slog::Logger,
+        new_price: U256,
+    ) -> Result<TransactionReceipt> {
+        self.send(logger, functions::update_price::encode_input(new_price))
+    }
+
+    /// Sends `setAdmin` and waits for the receipt.
+    pub fn set_admin(
+        &self,
+        logger: &slog::Logger,
+        admin: Address,
+        value: bool,
+    ) -> Result<TransactionReceipt> {
+        self.send(logger, functions::set_admin::encode_input(admin, value))
+    }
+
+    /// Returns the number of reporter signatures a report needs, 0 when the
+    /// admins update the price directly.
+    pub fn quorum(&self) -> Result<U256> {
+        let output = self.call(functions::quorum::encode_input())?;
+        Ok(functions::quorum::decode_output(&output)?)
+    }
+
+    /// Returns whether the address is a registered reporter.
+    pub fn is_reporter(&self, address: Address) -> Result<bool> {
+        let output = self.call(functions::is_reporter::encode_input(address))?;
+        Ok(functions::is_reporter::decode_output(&output)?)
+    }
+
+    /// Sends `setReporter` and waits for the receipt.
+    pub fn set_reporter(
+        &self,
+        logger: &slog::Logger,
+        reporter: Address,
+        value: bool,
+    ) -> Result<TransactionReceipt> {
+        self.send(
+            logger,
+            functions::set_reporter::encode_input(reporter, value),
+        )
+    }
+
+    /// Sends `setQuorum` and waits for the receipt.
+    pub fn set_quorum(&self, logger: &slog::Logger, quorum: U256) -> Result<TransactionReceipt> {
+        self.send(logger, functions::set_quorum::encode_input(quorum))
+    }
+
+    /// Sends `submitReport` with the signatures concatenated in the reporter order
+    /// and waits for the receipt.
+    pub fn submit_report(
+        &self,
+        logger: &slog::Logger,
+        round_id: U256,
+        answer: U256,
+        signatures: Vec<u8>,
+    ) -> Result<TransactionReceipt> {
+        self.send(
+            logger,
+            functions::submit_report::encode_input(round_id, answer, signatures),
+        )
+    }
+
+    /// Returns the gas limit and the gas price the tx with the calldata would be sent with.
+    pub fn estimate(&self, logger: &slog::Logger, data: &[u8]) -> Result<(U256, U256)> {
+        let from = self.from_addr()?;
+        let gas_price = self.gas.gas_price(logger, &self.web3)?;
+        let gas_limit =
+            self.gas
+                .gas_limit(logger, &self.web3, Some(from), Some(self.address), data)?;
+
+        Ok((gas_limit, gas_price))
+    }
+
+    /// Executes the calldata with `eth_call` against the latest block.
+    pub fn call(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let request = CallRequest {
+            from: None,
+            to: self.address,
+            gas: None,
+            gas_price: None,
+            value: None,
+            data: Some(Bytes(data)),
+        };
+
+        Ok(self.web3.eth().call(request, None).wait()?.0)
+    }
+
+    /// Sends the tx with the calldata to the contract and waits for the confirmations.
+    pub fn send(&self, logger: &slog::Logger, data: Vec<u8>) -> Result<TransactionReceipt> {
+        let (hash, gas_price) = self.broadcast(logger, data)?;
+        loop {
+            std::thread::sleep(RECEIPT_POLL_INTERVAL);
+            if let Some(receipt) = self.confirmed_receipt(hash)? {
+                web3util::log_tx_cost(logger, &receipt, gas_price);
+                return Ok(receipt);
+            }
+        }
+    }
+
+    /// Signs and broadcasts the tx with the calldata to the contract without
+    /// waiting for it, returns its hash and gas price.
+    pub fn broadcast(&self, logger: &slog::Logger, data: Vec<u8>) -> Result<(H256, U256)> {
+        let (gas_limit, gas_price) = self.estimate(logger, &data)?;
+
+        let hash = match self.signer {
+            Some(ref signer) => {
+                let nonce_cnt = web3util::nonce(signer.address, &self.web3)?;
+
+                let tx_request = ethtxsign::RawTransaction {
+                    to: Some(self.address),
+                    gas: gas_limit,
+                    gas_price: gas_price.into(),
+                    value: 0.into(),
+                    data,
+                    nonce: nonce_cnt,
+                };
+
+                let tx = tx_request.sign(&signer.private_key, &signer.chain_id)?;
+
+                self.web3.eth().send_raw_transaction(tx.into()).wait()?
+            }
+            None => {
+                let tx_request = TransactionRequest {
+                    from: self.from_addr()?,
+                    to: Some(self.address),
+                    gas: Some(gas_limit),
+                    gas_price: Some(gas_price),
+                    value: None,
+                    data: Some(data.into()),
+                    nonce: None,
+                    condition: None,
+                };
+
+                self.web3.eth().send_transaction(tx_request).wait()?
+            }
+        };
+
+        Ok((hash, gas_price))
+    }
+
+    /// Returns the receipt of the tx once it has the confirmations on top of
+    /// its block, `None` before.
+    pub fn confirmed_receipt(&self, hash: H256) -> Result<Option<TransactionReceipt>> {
+        let receipt = match self.web3.eth().transaction_receipt(hash).wait()? {
+            Some(receipt) => receipt,
+            None => return Ok(None),
+        };
+        let mined = match receipt.block_number {
+            Some(mined) => mined,
+            None => return Ok(None),
+        };
+        let head = self.web3.eth().block_number().wait()?;
+        if head + 1 < mined + U256::from(self.confirmations) {
+            return Ok(None);
+        }
+        Ok(Some(receipt))
+    }
+
+    /// Address the txs are sent from.
+    pub fn from_addr(&self) -> Result<Address> {
+        match self.signer {
+            Some(ref signer) => Ok(signer.address),
+            None => {
+                let accounts = self.web3.eth().accounts().wait()?;
+                accounts.first().cloned().ok_or(Error::NoAccounts)
+            }
+        }
+    }
+}
+
+impl<T: DuplexTransport> PriceOracle<T> {
+    /// Subscribes to the `PriceChanged` events starting from the block.
+    pub fn subscribe_price_changed(
+        &self,
+        from_block: u64,
+    ) -> impl Future<Item = SubscriptionStream<T, Log>, Error = web3::Error> {
+        self.web3
+            .eth_subscribe()
+            .subscribe_logs(price_changed_filter(self.address, from_block.into()))
+    }
+}
+
+pub fn price_changed_filter(address: Address, from_block: BlockNumber) -> Filter {
+    event_filter(address, events::PriceChanged::topic(), from_block)
+}
+
+/// Filter of the contract events with any of the topics in the block range,
+/// both ends included.
+pub fn contract_events_filter(
+    address: Address,
+    topics: Vec<H256>,
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+) -> Filter {
+    FilterBuilder::default()
+        .address(vec![address])
+        .topics(Some(topics), None, None, None)
+        .from_block(from_block)
+        .to_block(to_block)
+        .build()
+}
+
+/// Filter of the contract events with the topic up to the latest block.
+pub fn event_filter(address: Address, topic: H256, from_block: BlockNumber) -> Filter {
+    FilterBuilder::default()
+        .address(vec![address])
+        .topics(Some(vec![topic]), None, None, None)
+        .from_block(from_block)
+        .to_block(BlockNumber::Latest)
+        .build()
+}
@@ -0,0 +1,144 @@
+pragma solidity ^0.6.0;
+pragma experimental ABIEncoderV2;
+
+
+//SPDX-License-Identifier: MIT License
+// Request the clients send to the gateway, the callData of OffchainLookup.
+// The answer is the abi encoded (roundId, answer, updatedAt, expires, signature).
+interface OffchainPriceGateway {
+    function price(string calldata _pair, uint80 _roundId) external view returns (bytes memory);
+}
+
+
+// EIP-3668 CCIP-Read verifier of the prices signed by the oracle gateway.
+// `price` reverts with OffchainLookup, the client fetches the signed round
+// from one of the gateway urls and calls `priceWithProof` with the answer.
+contract OffchainPriceOracle {
+
+    // error OffchainLookup(address sender, string[] urls, bytes callData, bytes4 callbackFunction, bytes extraData)
+    bytes4 constant OFFCHAIN_LOOKUP = bytes4(keccak256("OffchainLookup(address,string[],bytes,bytes4,bytes)"));
+
+    mapping (address => bool) admins;
+
+    // description of the PriceOracle feed, such as "ETH / BTC"
+    string public pair;
+    // gateway urls with the {sender} and {data} templates of EIP-3668
+    string[] urls;
+    // key of the gateway signing the answers
+    address public signer;
+
+    event AdminChanged(address indexed admin, bool value);
+    event SignerChanged(address signer);
+    event UrlsChanged(string[] urls);
+
+    constructor(string memory _pair, string[] memory _urls, address _signer) public {
+        pair = _pair;
+        for (uint256 i = 0; i < _urls.length; i++) {
+            urls.push(_urls[i]);
+        }
+        signer = _signer;
+        admins[msg.sender] = true;
+        emit AdminChanged(msg.sender, true);
+        emit UrlsChanged(_urls);
+        emit SignerChanged(_signer);
+    }
+
+    // Always reverts with OffchainLookup, 0 is the latest round.
+    function price(uint80 _roundId) external view returns (uint80 roundId, uint256 answer, uint256 updatedAt) {
+        bytes memory callData = lookupCallData(_roundId);
+        string[] memory gatewayUrls = urls;
+        bytes memory lookup = abi.encodeWithSelector(
+            OFFCHAIN_LOOKUP,
+            address(this),
+            gatewayUrls,
+            callData,
+            this.priceWithProof.selector,
+            callData
+        );
+        assembly {
+            revert(add(lookup, 32), mload(lookup))
+        }
+    }
+
+    // Callback of OffchainLookup, the response is the abi encoded
+    // (roundId, answer, updatedAt, expires, signature) of the gateway and the
+    // extra data is the callData of the lookup.
+    function priceWithProof(bytes calldata _response, bytes calldata _extraData)
+        external
+        view
+        returns (uint80 roundId, uint256 answer, uint256 updatedAt)
+    {
+        uint64 expires;
+        bytes memory signature;
+        (roundId, answer, updatedAt, expires, signature) =
+            abi.decode(_response, (uint80, uint256, uint256, uint64, bytes));
+        require(expires >= block.timestamp, "signed answer expired");
+
+        // the lookup of the pair asked for this round or for the latest one
+        bytes32 request = keccak256(_extraData);
+        require(
+            request == keccak256(lookupCallData(roundId)) || request == keccak256(lookupCallData(0)),
+            "answer is for another request"
+        );
+
+        bytes32 digest = responseDigest(request, roundId, answer, updatedAt, expires);
+        require(recoverSigner(digest, signature) == signer, "answer is not signed by the gateway");
+    }
+
+    function lookupCallData(uint80 _roundId) internal view returns (bytes memory) {
+        return abi.encodeWithSelector(OffchainPriceGateway(address(0)).price.selector, pair, _roundId);
+    }
+
+    // Hash the gateway signs, it computes the same for the contracts it serves.
+    function responseDigest(bytes32 _request, uint80 _roundId, uint256 _answer, uint256 _updatedAt, uint64 _expires)
+        public
+        view
+        returns (bytes32)
+    {
+        return keccak256(abi.encode(address(this), _request, _roundId, _answer, _updatedAt, _expires));
+    }
+
+    function recoverSigner(bytes32 _digest, bytes memory _signature) internal pure returns (address) {
+        require(_signature.length == 65, "malformed signature");
+        bytes32 r;
+        bytes32 s;
+        uint8 v;
+        assembly {
+            r := mload(add(_signature, 32))
+            s := mload(add(_signature, 64))
+            v := byte(0, mload(add(_signature, 96)))
+        }
+        address recovered = ecrecover(_digest, v, r, s);
+        require(recovered != address(0), "invalid signature");
+        return recovered;
+    }
+
+    function setSigner(address _signer) public {
+        require(admins[msg.sender] == true, "u must be admin to set signer");
+        signer = _signer;
+        emit SignerChanged(_signer);
+    }
+
+    function setUrls(string[] memory _urls) public {
+        require(admins[msg.sender] == true, "u must be admin to set urls");
+        delete urls;
+        for (uint256 i = 0; i < _urls.length; i++) {
+            urls.push(_urls[i]);
+        }
+        emit UrlsChanged(_urls);
+    }
+
+    function getUrls() public view returns (string[] memory) {
+        return urls;
+    }
+
+    function setAdmin(address _newAdmin, bool _value) public {
+        require(admins[msg.sender] == true, "u must be admin to set admin");
+        admins[_newAdmin] = _value;
+        emit AdminChanged(_newAdmin, _value);
+    }
+
+    function isAdmin(address _addr) public view returns (bool) {
+        return admins[_addr];
+    }
+}
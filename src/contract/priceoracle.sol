@@ -1,29 +1,189 @@
 pragma solidity ^0.6.0;
 
 
+// Chainlink feed interface, the oracle is a drop-in replacement for the
+// consumers of the Chainlink price feeds.
+interface AggregatorV3Interface {
+
+    function decimals() external view returns (uint8);
+
+    function description() external view returns (string memory);
+
+    function version() external view returns (uint256);
+
+    function getRoundData(uint80 _roundId)
+        external
+        view
+        returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound);
+
+    function latestRoundData()
+        external
+        view
+        returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound);
+}
+
+
 //SPDX-License-Identifier: MIT License
-contract PriceOracle {
+contract PriceOracle is AggregatorV3Interface {
+
+    struct Round {
+        uint80 roundId;
+        // How much BTC you get for 1 ETH, multiplied by 10^18
+        uint256 answer;
+        uint256 updatedAt;
+        uint80 answeredInRound;
+    }
+
+    uint8 constant DECIMALS = 18;
+    uint256 constant VERSION = 1;
 
     mapping (address => bool) admins;
 
-    // How much BTC you get for 1 ETH, multiplied by 10^18
-    uint256 ETHPrice;
+    // reporters signing the reports, a report needs quorum signatures
+    mapping (address => bool) reporters;
+    uint256 public reportersCount;
+    uint256 public quorum;
 
-    event PriceChanged(uint256 newPrice);
+    mapping (uint80 => Round) rounds;
+    uint80 latestRoundId;
 
-    constructor() public {
+    string feedDescription;
+
+    event PriceChanged(uint80 indexed roundId, uint256 answer, uint256 updatedAt, uint80 answeredInRound);
+    event AdminChanged(address indexed admin, bool value);
+    event ReporterChanged(address indexed reporter, bool value);
+    event QuorumChanged(uint256 quorum);
+
+    constructor(string memory _description) public {
+        feedDescription = _description;
         admins[msg.sender] = true;
+        emit AdminChanged(msg.sender, true);
     }
 
     function updatePrice(uint256 _newPrice) public {
-        require(_newPrice > 0, "new price must be > 0");
         require(admins[msg.sender] == true, "u are not admin");
-        ETHPrice = _newPrice;
-        emit PriceChanged(_newPrice);
+        require(quorum == 0, "quorum is set, use submitReport");
+        storeRound(_newPrice);
+    }
+
+    // Stores the answer signed by at least quorum reporters. The signatures are
+    // concatenated 65 bytes r, s, v sorted by the reporter address.
+    function submitReport(uint80 _roundId, uint256 _answer, bytes memory _signatures) public {
+        require(quorum > 0, "quorum is not set");
+        require(_roundId == latestRoundId + 1, "unexpected round");
+        require(_signatures.length % 65 == 0, "malformed signatures");
+        require(_signatures.length / 65 >= quorum, "not enough signatures");
+
+        bytes32 digest = reportDigest(_roundId, _answer);
+        address prev = address(0);
+        for (uint256 i = 0; i < _signatures.length / 65; i++) {
+            address signer = recoverSigner(digest, _signatures, i);
+            require(signer > prev, "signers must be unique and sorted");
+            require(reporters[signer] == true, "signer is not a reporter");
+            prev = signer;
+        }
+
+        storeRound(_answer);
+    }
+
+    function reportDigest(uint80 _roundId, uint256 _answer) public view returns (bytes32) {
+        return keccak256(abi.encode(address(this), _roundId, _answer));
+    }
+
+    function storeRound(uint256 _newPrice) internal {
+        require(_newPrice > 0, "new price must be > 0");
+        require(_newPrice < 2**255, "new price must fit int256");
+        latestRoundId++;
+        rounds[latestRoundId] = Round(latestRoundId, _newPrice, block.timestamp, latestRoundId);
+        emit PriceChanged(latestRoundId, _newPrice, block.timestamp, latestRoundId);
+    }
+
+    function recoverSigner(bytes32 _digest, bytes memory _signatures, uint256 _index)
+        internal
+        pure
+        returns (address)
+    {
+        bytes32 r;
+        bytes32 s;
+        uint8 v;
+        uint256 offset = _index * 65;
+        assembly {
+            r := mload(add(_signatures, add(32, offset)))
+            s := mload(add(_signatures, add(64, offset)))
+            v := byte(0, mload(add(_signatures, add(96, offset))))
+        }
+        address signer = ecrecover(_digest, v, r, s);
+        require(signer != address(0), "invalid signature");
+        return signer;
+    }
+
+    function decimals() external view override returns (uint8) {
+        return DECIMALS;
+    }
+
+    function description() external view override returns (string memory) {
+        return feedDescription;
+    }
+
+    function version() external view override returns (uint256) {
+        return VERSION;
+    }
+
+    function getRoundData(uint80 _roundId)
+        external
+        view
+        override
+        returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+    {
+        Round memory round = rounds[_roundId];
+        // same as the Chainlink feeds, consumers expect a revert for the unknown rounds
+        require(round.updatedAt > 0, "No data present");
+        return (round.roundId, int256(round.answer), round.updatedAt, round.updatedAt, round.answeredInRound);
+    }
+
+    function latestRoundData()
+        external
+        view
+        override
+        returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+    {
+        // zeros until the first update, consumers must check updatedAt
+        Round memory round = rounds[latestRoundId];
+        return (round.roundId, int256(round.answer), round.updatedAt, round.updatedAt, round.answeredInRound);
     }
 
     function setAdmin(address _newAdmin, bool _value) public {
         require(admins[msg.sender] == true, "u must be admin to set admin");
         admins[_newAdmin] = _value;
+        emit AdminChanged(_newAdmin, _value);
+    }
+
+    function isAdmin(address _addr) public view returns (bool) {
+        return admins[_addr];
+    }
+
+    function setReporter(address _reporter, bool _value) public {
+        require(admins[msg.sender] == true, "u must be admin to set reporter");
+        if (reporters[_reporter] != _value) {
+            reporters[_reporter] = _value;
+            if (_value) {
+                reportersCount++;
+            } else {
+                reportersCount--;
+            }
+            emit ReporterChanged(_reporter, _value);
+        }
+        require(quorum <= reportersCount, "quorum must be <= reporters");
+    }
+
+    function setQuorum(uint256 _quorum) public {
+        require(admins[msg.sender] == true, "u must be admin to set quorum");
+        require(_quorum <= reportersCount, "quorum must be <= reporters");
+        quorum = _quorum;
+        emit QuorumChanged(_quorum);
+    }
+
+    function isReporter(address _addr) public view returns (bool) {
+        return reporters[_addr];
     }
 }
@@ -0,0 +1,228 @@
+//! CORS policy of `server`, so the browser dApps call the API directly.
+
+use crate::args;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ORIGIN,
+    VARY,
+};
+use hyper::{Body, Response, StatusCode};
+
+/// Methods of the public API.
+const ALLOW_METHODS: &str = "GET, POST, OPTIONS";
+/// Request headers the API reads.
+const ALLOW_HEADERS: &str = "X-Api-Key, Last-Event-ID, Content-Type";
+/// Response headers the scripts may read.
+const EXPOSE_HEADERS: &str = "Retry-After";
+
+/// Origins allowed to read the responses.
+pub struct Cors {
+    /// `None` allows any origin
+    origins: Option<Vec<String>>,
+    /// Seconds the browsers cache the preflight
+    max_age: u64,
+}
+
+impl Cors {
+    pub fn new(origins: Option<Vec<String>>, max_age: u64) -> Self {
+        Cors { origins, max_age }
+    }
+
+    /// Policy of `--cors_origins`, `None` when it is not set.
+    pub fn from_args(arg: &ArgMatches) -> Result<Option<Self>> {
+        let origins = args::list(arg, "cors_origins");
+        if origins.is_empty() {
+            return Ok(None);
+        }
+        let origins = if origins.iter().any(|origin| origin == "*") {
+            None
+        } else {
+            if let Some(origin) = origins.iter().find(|origin| origin.ends_with('/')) {
+                return Err(Error::invalid(
+                    "cors_origins",
+                    format!("{} is not scheme://host[:port]", origin),
+                ));
+            }
+            Some(origins)
+        };
+        Ok(Some(Cors::new(origins, args::parse(arg, "cors_max_age")?)))
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        match &self.origins {
+            None => true,
+            Some(origins) => origins
+                .iter()
+                .any(|allowed| allowed.as_bytes() == origin.as_bytes()),
+        }
+    }
+
+    /// Answer to the `OPTIONS` preflight of the browser, [`Cors::apply`] adds
+    /// the allowed origin to it like to the other responses.
+    pub fn preflight(&self, headers: &HeaderMap) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        match headers.get(ORIGIN) {
+            Some(origin) if self.allows(origin) => {
+                *response.status_mut() = StatusCode::NO_CONTENT;
+                let headers = response.headers_mut();
+                headers.insert(
+                    ACCESS_CONTROL_ALLOW_METHODS,
+                    HeaderValue::from_static(ALLOW_METHODS),
+                );
+                headers.insert(
+                    ACCESS_CONTROL_ALLOW_HEADERS,
+                    HeaderValue::from_static(ALLOW_HEADERS),
+                );
+                headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(self.max_age));
+            }
+            _ => *response.status_mut() = StatusCode::FORBIDDEN,
+        }
+        response
+    }
+
+    /// Adds the CORS headers for the origin of the request if it is allowed.
+    pub fn apply(&self, origin: Option<&HeaderValue>, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        match (&self.origins, origin) {
+            (None, _) => {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+            }
+            (Some(_), Some(origin)) if self.allows(origin) => {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+            }
+            (Some(_), _) => {
+                // the answer depends on the origin even when it is refused
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+                return;
+            }
+        }
+        headers.insert(
+            ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(EXPOSE_HEADERS),
+        );
+    }
+}
+
+/// CORS arguments of `server` and of the stream of `service`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("cors_origins")
+            .env("PO_CORS_ORIGINS")
+            .long("cors_origins")
+            .help("comma separated origins such as https://app.example.com allowed to call the api, * for any"),
+        Arg::with_name("cors_max_age")
+            .env("PO_CORS_MAX_AGE")
+            .long("cors_max_age")
+            .default_value("600")
+            .help("seconds the browsers cache the preflight response"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::App;
+
+    const APP: &str = "https://app.example.com";
+
+    fn allowlist() -> Cors {
+        Cors::new(Some(vec![APP.to_string()]), 600)
+    }
+
+    fn origin(origin: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ORIGIN, HeaderValue::from_str(origin).unwrap());
+        headers
+    }
+
+    fn applied(cors: &Cors, origin: Option<&str>) -> Response<Body> {
+        let origin = origin.map(|origin| HeaderValue::from_str(origin).unwrap());
+        let mut response = Response::new(Body::empty());
+        cors.apply(origin.as_ref(), &mut response);
+        response
+    }
+
+    fn from_args(origins: &str) -> Result<Option<Cors>> {
+        let matches = App::new("test").args(&args()).get_matches_from(vec![
+            "test",
+            "--cors_origins",
+            origins,
+        ]);
+        Cors::from_args(&matches)
+    }
+
+    #[test]
+    fn allows_the_listed_origins_exactly() {
+        let cors = allowlist();
+        assert!(cors.allows(&HeaderValue::from_static(APP)));
+        assert!(!cors.allows(&HeaderValue::from_static("https://app.example.com:8443")));
+        assert!(!cors.allows(&HeaderValue::from_static("http://app.example.com")));
+        assert!(!cors.allows(&HeaderValue::from_static("https://evil.example.com")));
+        assert!(Cors::new(None, 600).allows(&HeaderValue::from_static("https://evil.example.com")));
+    }
+
+    #[test]
+    fn echoes_an_allowed_origin() {
+        let response = applied(&allowlist(), Some(APP));
+        let headers = response.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], APP);
+        assert_eq!(headers[VARY], "Origin");
+        assert_eq!(headers[ACCESS_CONTROL_EXPOSE_HEADERS], EXPOSE_HEADERS);
+    }
+
+    #[test]
+    fn varies_by_the_refused_or_missing_origin() {
+        for origin in &[Some("https://evil.example.com"), None] {
+            let response = applied(&allowlist(), *origin);
+            let headers = response.headers();
+            assert!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+            assert!(headers.get(ACCESS_CONTROL_EXPOSE_HEADERS).is_none());
+            assert_eq!(headers[VARY], "Origin");
+        }
+    }
+
+    #[test]
+    fn allows_any_origin_with_the_wildcard() {
+        let cors = from_args("https://a.example.com,*").unwrap().unwrap();
+        for origin in &[Some(APP), None] {
+            let response = applied(&cors, *origin);
+            let headers = response.headers();
+            assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+            assert!(headers.get(VARY).is_none());
+        }
+    }
+
+    #[test]
+    fn answers_the_preflight_of_an_allowed_origin() {
+        let response = allowlist().preflight(&origin(APP));
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], ALLOW_METHODS);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], ALLOW_HEADERS);
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+    }
+
+    #[test]
+    fn forbids_the_preflight_of_a_refused_or_missing_origin() {
+        let cors = allowlist();
+        let refused = cors.preflight(&origin("https://evil.example.com"));
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        assert!(refused
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_METHODS)
+            .is_none());
+        assert_eq!(
+            cors.preflight(&HeaderMap::new()).status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn rejects_an_origin_with_a_path() {
+        assert!(from_args("https://app.example.com/").is_err());
+        assert!(from_args(APP).unwrap().is_some());
+    }
+}
@@ -1,35 +1,31 @@
 use clap::ArgMatches;
 use std::time::Duration;
-use web3::contract::{Contract, Options};
 use web3::futures::Future;
-use web3::types::{Address, H256, U256};
+use web3::types::{Address, TransactionRequest, U256};
 
+use crate::args;
+use crate::bindings::{offchain_price_oracle, price_oracle};
+use crate::client::Signer;
+use crate::error::{Error, Result};
+use crate::gasprice::{self, GasConfig};
 use crate::web3util;
-use std::convert::TryFrom;
 use web3::Transport;
 
-#[derive(RustEmbed)]
-#[folder = "src/contract/"]
-struct Asset;
-
-pub fn run_with_http(
-    logger: slog::Logger,
-    arg: &ArgMatches,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new(arg);
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
     info!(
         logger,
-        "deploy called to the {} network with {:?}", config.net, config.from_addr
+        "deploy called to the {} network with {:?}", config.net, config.signer
     );
 
-    let (eloop, ethan) = web3::transports::Http::new(&config.net).unwrap();
+    let (eloop, ethan) = web3::transports::Http::new(&config.net)?;
     eloop.into_remote();
 
     let web3 = web3::Web3::new(ethan);
 
-    let contract_address = match config.from_addr {
-        None => with_own_eth_node(web3, &logger, config),
-        Some(_) => with_existing_wallet(web3, &logger, config),
+    let contract_address = match config.signer {
+        None => with_own_eth_node(web3, &logger, config)?,
+        Some(_) => with_existing_wallet(web3, &logger, config)?,
     };
 
     info!(logger, "contract address: {:?}", contract_address);
@@ -37,27 +33,24 @@ pub fn run_with_http(
     Ok(())
 }
 
-pub fn run_with_ws(
-    logger: slog::Logger,
-    arg: &ArgMatches,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new(arg);
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
     info!(
         logger,
-        "deploy called to the {:?} network with {:?}", config.net, config.from_addr
+        "deploy called to the {:?} network with {:?}", config.net, config.signer
     );
 
-    let (eloop, ethan) = web3::transports::WebSocket::new(&config.net).unwrap();
+    let (eloop, ethan) = web3::transports::WebSocket::new(&config.net)?;
     eloop.into_remote();
 
     let web3 = web3::Web3::new(ethan);
 
-    let contract_address = match config.from_addr {
-        None => with_own_eth_node(web3, &logger, config),
-        Some(_) => with_existing_wallet(web3, &logger, config),
+    let contract_address = match config.signer {
+        None => with_own_eth_node(web3, &logger, config)?,
+        Some(_) => with_existing_wallet(web3, &logger, config)?,
     };
 
-    info!(logger, "contract address: {:?}", contract_address.unwrap());
+    info!(logger, "contract address: {:?}", contract_address);
 
     Ok(())
 }
@@ -66,122 +59,150 @@ fn with_existing_wallet(
     eth_client: web3::Web3<impl Transport>,
     logger: &slog::Logger,
     conf: Config,
-) -> Result<Address, Box<dyn std::error::Error>> {
-    let gas_price = match eth_client.eth().gas_price().wait() {
-        Ok(gas_price) => gas_price,
-        Err(e) => return Err(Box::try_from(e).unwrap()),
-    };
+) -> Result<Option<Address>> {
+    let signer = conf.signer.ok_or_else(|| Error::missing("from_addr"))?;
+    let gas_price = conf.gas.gas_price(logger, &eth_client)?;
+    let gas_limit = conf.gas.gas_limit(
+        logger,
+        &eth_client,
+        Some(signer.address),
+        None,
+        &conf.contract_bytecode,
+    )?;
 
     info!(
         logger,
-        "deploy contract from {:?} with suggested gas_price: {:?}", conf.from_addr, gas_price
+        "deploy contract from {:?} with gas_price: {:?}", signer.address, gas_price
     );
 
-    let nonce_cnt = web3util::nonce(conf.from_addr.unwrap(), &eth_client).unwrap();
+    if conf.dry_run {
+        gasprice::print_dry_run(gas_limit, gas_price);
+        return Ok(None);
+    }
+
+    let nonce_cnt = web3util::nonce(signer.address, &eth_client)?;
 
     let tx_request = ethtxsign::RawTransaction {
         to: None,
-        gas: conf.gas_limit,
+        gas: gas_limit,
         gas_price: gas_price.into(),
         value: 0.into(),
         data: conf.contract_bytecode,
         nonce: nonce_cnt,
     };
 
-    let tx = tx_request.sign(&conf.pvt_key, &conf.chain_id);
+    let tx = tx_request.sign(&signer.private_key, &signer.chain_id)?;
 
-    let result =
-        eth_client.send_raw_transaction_with_confirmation(tx.into(), Duration::from_secs(1), 1);
-
-    let receipt = match result.wait() {
-        Ok(receipt) => receipt,
-        Err(e) => return Err(Box::try_from(e).unwrap()),
-    };
+    let receipt = eth_client
+        .send_raw_transaction_with_confirmation(tx.into(), Duration::from_secs(1), 1)
+        .wait()?;
 
     info!(logger, "tx {} created", receipt.transaction_hash);
+    web3util::log_tx_cost(logger, &receipt, gas_price);
 
-    Ok(receipt.contract_address.unwrap())
+    Ok(receipt.contract_address)
 }
 
 fn with_own_eth_node(
     eth_client: web3::Web3<impl Transport>,
     logger: &slog::Logger,
     conf: Config,
-) -> Result<Address, Box<dyn std::error::Error>> {
-    let accounts = eth_client.eth().accounts().wait().unwrap();
+) -> Result<Option<Address>> {
+    let accounts = eth_client.eth().accounts().wait()?;
 
     if accounts.len() == 0 {
-        return Err(
-            Box::try_from(String::from("there is no any accounts for contract deploy")).unwrap(),
-        );
+        return Err(Error::NoAccounts);
     }
 
-    let contract_abi = Asset::get("PriceOracle.abi").unwrap();
-    info!(logger, "{:?}", std::str::from_utf8(contract_abi.as_ref()));
-
-    let contract_bytecode = Asset::get("PriceOracle.bin").unwrap();
-
     info!(logger, "Accounts: {:?}", accounts);
-    let gas_price: U256 = eth_client.eth().gas_price().wait().unwrap();
+    let gas_price: U256 = conf.gas.gas_price(logger, &eth_client)?;
+    let gas_limit = conf.gas.gas_limit(
+        logger,
+        &eth_client,
+        Some(accounts[0]),
+        None,
+        &conf.contract_bytecode,
+    )?;
+
+    info!(logger, "gas_price: {:?}", gas_price);
 
-    info!(logger, "suggested gas_price: {:?}", gas_price);
+    if conf.dry_run {
+        gasprice::print_dry_run(gas_limit, gas_price);
+        return Ok(None);
+    }
 
-    let bc = std::str::from_utf8(contract_bytecode.as_ref()).unwrap();
+    let tx_request = TransactionRequest {
+        from: accounts[0],
+        to: None,
+        gas: Some(gas_limit),
+        gas_price: Some(gas_price),
+        value: Some(0.into()),
+        data: Some(conf.contract_bytecode.into()),
+        nonce: None,
+        condition: None,
+    };
 
-    let contract = Contract::deploy(eth_client.eth(), contract_abi.as_ref())
-        .unwrap()
-        .confirmations(0)
-        .options(Options::with(|opt| {
-            opt.value = Some(0.into());
-            opt.gas_price = Some(gas_price);
-            opt.gas = Some(conf.gas_limit);
-        }))
-        .execute(bc, (), accounts[0])
-        .expect("Correct parameters are passed to the constructor.")
-        .wait()
-        .unwrap();
+    let receipt = web3::confirm::send_transaction_with_confirmation(
+        eth_client.transport().clone(),
+        tx_request,
+        Duration::from_secs(1),
+        1,
+    )
+    .wait()?;
 
-    let contract_address = contract.address();
+    info!(logger, "tx {} created", receipt.transaction_hash);
+    web3util::log_tx_cost(logger, &receipt, gas_price);
 
-    Ok(contract_address)
+    Ok(receipt.contract_address)
 }
 
 struct Config {
-    from_addr: Option<Address>,
-    pvt_key: H256,
-    gas_limit: U256,
+    signer: Option<Signer>,
+    gas: GasConfig,
     contract_bytecode: Vec<u8>,
-    chain_id: u8,
     net: String,
+    dry_run: bool,
 }
 
 impl Config {
-    fn new(arg: &ArgMatches) -> Self {
-        let net = arg.value_of("net").unwrap().to_string();
-
-        let my_account = arg.value_of("from_addr").unwrap();
-        let fr: Address = my_account.parse().unwrap();
-
-        let pk = arg.value_of("private_key").unwrap();
-        let pvt_key =
-            ethtxsign::pvt_key_from_slice(hex::decode(pk.as_bytes()).unwrap().as_slice()).unwrap();
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        let net = args::required(arg, "net")?.to_string();
 
-        let gl = arg.value_of("gas_limit").unwrap();
-        let gas_limit: U256 = U256::from_dec_str(gl).unwrap();
+        let signer = args::signer(arg)?;
 
-        let cid = arg.value_of("chain_id").unwrap();
-        let chain_id = cid.parse::<u8>().unwrap();
+        let gas = GasConfig::new(arg)?;
 
-        let cb = Asset::get("PriceOracle.bin").unwrap();
-        let contract_bytecode = hex::decode(cb.as_ref()).unwrap();
-
-        Config {
-            from_addr: Some(fr),
-            pvt_key,
-            gas_limit,
-            contract_bytecode,
-            chain_id,
+        Ok(Config {
+            signer,
+            gas,
+            contract_bytecode: deploy_data(arg)?,
             net,
-        }
+            dry_run: arg.is_present("dry_run"),
+        })
+    }
+}
+
+/// `PriceOracle`, or the `OffchainPriceOracle` of the gateway with `--ccip_urls`.
+fn deploy_data(arg: &ArgMatches) -> Result<Vec<u8>> {
+    let description = args::required(arg, "description")?.to_string();
+    let urls = args::list(arg, "ccip_urls");
+    let (contract, bytecode) = if urls.is_empty() {
+        ("PriceOracle", price_oracle::BYTECODE)
+    } else {
+        ("OffchainPriceOracle", offchain_price_oracle::BYTECODE)
+    };
+    if bytecode.is_empty() {
+        return Err(Error::Abi(format!(
+            "built without solc and src/contract/{}.bin, the bytecode to deploy is missing",
+            contract
+        )));
+    }
+    if urls.is_empty() {
+        return Ok(price_oracle::deploy_data(description));
     }
+    Ok(offchain_price_oracle::deploy_data(
+        description,
+        urls,
+        args::address(arg, "ccip_signer")?,
+    ))
 }
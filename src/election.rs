@@ -0,0 +1,267 @@
+//! Leader election between redundant `service` instances.
+//!
+//! The instances share one [`Lease`]. The leader renews it on every poll and
+//! is the only instance sending transactions, the standbys try to acquire it
+//! and take over once the leader stops renewing for `ttl`.
+
+use crate::error::{Error, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Backend of the leader lease.
+pub trait Lease: Send + Sync {
+    /// Name of the backend used in the logs.
+    fn name(&self) -> &str;
+
+    /// Acquires or renews the lease for `ttl`, returns `false` while another
+    /// holder has it.
+    fn acquire(&self, holder: &str, ttl: Duration) -> Result<bool>;
+}
+
+/// Lease file on a volume shared by the instances, it holds the holder and the
+/// expiry time in milliseconds. It is read and written under a lock of
+/// `<path>.lock`.
+pub struct FileLease {
+    path: PathBuf,
+}
+
+impl FileLease {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileLease { path: path.into() }
+    }
+
+    fn read(&self) -> io::Result<Option<(String, u64)>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut parts = content.split_whitespace();
+        let holder = parts.next().unwrap_or_default().to_string();
+        // a torn or foreign file counts as expired
+        let expires_at = parts.next().and_then(|e| e.parse().ok()).unwrap_or(0);
+        Ok(Some((holder, expires_at)))
+    }
+
+    /// Locks `<path>.lock` until the returned file is dropped. The kernel
+    /// drops the lock of a crashed instance, so it is never left behind.
+    fn lock(&self) -> io::Result<File> {
+        // beside the lease, which is replaced by the writes
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        // fcntl locks work on NFS too, unlike flock
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = libc::F_WRLCK as libc::c_short;
+        lock.l_whence = libc::SEEK_SET as libc::c_short;
+        loop {
+            if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLKW, &lock) } == 0 {
+                return Ok(file);
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Reads and replaces the lease under the lock, so of the instances
+    /// racing for an expired lease only the first one takes it over.
+    fn acquire_file(&self, holder: &str, ttl: Duration) -> io::Result<bool> {
+        let _lock = self.lock()?;
+        let now = now_millis();
+        let expires_at = now + ttl.as_millis() as u64;
+
+        match self.read()? {
+            Some((ref current, current_expiry)) if current != holder && current_expiry > now => {
+                Ok(false)
+            }
+            _ => {
+                // a crash while writing leaves the previous lease, not a torn one
+                let tmp = self.path.with_extension(format!("{}.tmp", holder));
+                fs::write(&tmp, format!("{} {}\n", holder, expires_at))?;
+                fs::rename(&tmp, &self.path)?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Lease for FileLease {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn acquire(&self, holder: &str, ttl: Duration) -> Result<bool> {
+        self.acquire_file(holder, ttl)
+            .map_err(|e| Error::Lease(format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+/// Key with an expiry in redis or any store speaking its protocol.
+pub struct RedisLease {
+    addr: String,
+    key: String,
+}
+
+/// Renews the key only when it is still held by the holder.
+const RENEW_SCRIPT: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then \
+                            return redis.call('pexpire', KEYS[1], ARGV[2]) else return 0 end";
+
+/// Reply of the redis protocol, bulk and array replies are not needed.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<String>),
+}
+
+impl RedisLease {
+    pub fn new(addr: &str, key: &str) -> Self {
+        RedisLease {
+            addr: addr.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    fn command(&self, args: &[&str]) -> io::Result<Reply> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request += &format!("${}\r\n{}\r\n", arg.len(), arg);
+        }
+        stream.write_all(request.as_bytes())?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+
+        match line.chars().next() {
+            Some('+') => Ok(Reply::Status(line[1..].to_string())),
+            Some('-') => Err(invalid(line[1..].to_string())),
+            Some(':') => line[1..]
+                .parse()
+                .map(Reply::Integer)
+                .map_err(|e| invalid(format!("{}", e))),
+            Some('$') => {
+                let len: i64 = line[1..].parse().map_err(|e| invalid(format!("{}", e)))?;
+                if len < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                let mut value = String::new();
+                reader.read_line(&mut value)?;
+                Ok(Reply::Bulk(Some(value.trim_end().to_string())))
+            }
+            _ => Err(invalid(format!("unexpected reply {:?}", line))),
+        }
+    }
+
+    fn acquire_key(&self, holder: &str, ttl: Duration) -> io::Result<bool> {
+        let ttl = ttl.as_millis().to_string();
+        let set = self.command(&["SET", &self.key, holder, "NX", "PX", &ttl])?;
+        if set == Reply::Status("OK".to_string()) {
+            return Ok(true);
+        }
+
+        let renew = self.command(&["EVAL", RENEW_SCRIPT, "1", &self.key, holder, &ttl])?;
+        Ok(renew == Reply::Integer(1))
+    }
+}
+
+impl Lease for RedisLease {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    fn acquire(&self, holder: &str, ttl: Duration) -> Result<bool> {
+        self.acquire_key(holder, ttl)
+            .map_err(|e| Error::Lease(format!("{}/{}: {}", self.addr, self.key, e)))
+    }
+}
+
+/// Parses `--lease`: `file:///shared/priceoracle.lock` or
+/// `redis://127.0.0.1:6379/priceoracle-leader`.
+pub fn lease(url: &str) -> Result<Box<dyn Lease>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(Box::new(FileLease::new(path)));
+    }
+    if let Some(rest) = url.strip_prefix("redis://") {
+        let mut parts = rest.splitn(2, '/');
+        let addr = parts.next().unwrap_or_default();
+        let key = parts.next().unwrap_or("priceoracle-leader");
+        if addr.is_empty() || key.is_empty() {
+            return Err(Error::invalid("lease", "expected redis://host:port/key"));
+        }
+        return Ok(Box::new(RedisLease::new(addr, key)));
+    }
+    Err(Error::invalid(
+        "lease",
+        format!("{} is neither a file:// nor a redis:// url", url),
+    ))
+}
+
+/// Tracks whether this instance holds the lease.
+pub struct Election {
+    lease: Box<dyn Lease>,
+    holder: String,
+    ttl: Duration,
+    leader: AtomicBool,
+}
+
+impl Election {
+    /// `holder` must be unique per instance, the standby takes over at most
+    /// `ttl` plus one poll interval after the leader stops.
+    pub fn new(lease: Box<dyn Lease>, holder: String, ttl: Duration) -> Self {
+        Election {
+            lease,
+            holder,
+            ttl,
+            leader: AtomicBool::new(false),
+        }
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// Acquires or renews the lease. A failing backend counts as a lost lease,
+    /// so two instances never send at once.
+    pub fn renew(&self, logger: &slog::Logger) -> bool {
+        let leader = match self.lease.acquire(&self.holder, self.ttl) {
+            Ok(leader) => leader,
+            Err(e) => {
+                error!(logger, "{} lease error: {}", self.lease.name(), e);
+                false
+            }
+        };
+
+        let was_leader = self.leader.swap(leader, Ordering::SeqCst);
+        if leader && !was_leader {
+            info!(logger, "{} became the leader", self.holder);
+        } else if !leader && was_leader {
+            warn!(logger, "{} lost the leadership", self.holder);
+        }
+        leader
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
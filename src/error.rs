@@ -0,0 +1,164 @@
+use core::fmt;
+use web3::types::U256;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Missing or malformed command line argument or its `PO_*` env var
+    Config { arg: &'static str, reason: String },
+    /// Ethereum node request failed
+    Rpc(web3::Error),
+    /// The node has no unlocked accounts to send the tx from
+    NoAccounts,
+    /// Gas price is above the configured cap
+    GasPriceCap {
+        gas_price: U256,
+        max_gas_price: U256,
+    },
+    /// Tx could not be signed
+    Signing(String),
+    /// Contract ABI could not be loaded or data could not be encoded/decoded
+    Abi(String),
+    /// External price api is unreachable or returned unexpected data
+    PriceSource(String),
+    /// HTTP server failure
+    Server(hyper::Error),
+    /// Reporters could not agree on a report signed by the quorum
+    Quorum(String),
+    /// Quotes of a peer node are unreachable, untrusted or stale
+    Peer(String),
+    /// Leader lease backend is unreachable or failed
+    Lease(String),
+    /// Local file or database could not be read or written
+    Storage(String),
+    /// Event record could not be delivered to the sink
+    Sink(String),
+    /// Admin API action is not possible in the current mode of the service
+    Admin(String),
+}
+
+impl Error {
+    pub fn missing(arg: &'static str) -> Self {
+        Error::Config {
+            arg,
+            reason: "is required".to_string(),
+        }
+    }
+
+    pub fn invalid(arg: &'static str, reason: impl fmt::Display) -> Self {
+        Error::Config {
+            arg,
+            reason: format!("is invalid: {}", reason),
+        }
+    }
+
+    /// Transient failures are worth retrying, the others need an operator. A
+    /// gas price over the cap is not retried, the service skips the tx until
+    /// the next poll.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Rpc(_)
+            | Error::PriceSource(_)
+            | Error::Quorum(_)
+            | Error::Peer(_)
+            | Error::Lease(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config { arg, reason } => write!(
+                f,
+                "--{} {}, pass it on the command line or set the corresponding PO_* env var",
+                arg, reason
+            ),
+            Error::Rpc(e) => write!(
+                f,
+                "ethereum node request failed: {}, check --net and that the node is synced",
+                e
+            ),
+            Error::NoAccounts => write!(
+                f,
+                "the ethereum node has no accounts, pass --from_addr and --private_key to sign \
+                 the tx locally"
+            ),
+            Error::GasPriceCap {
+                gas_price,
+                max_gas_price,
+            } => write!(
+                f,
+                "gas price {} is above the cap {}, tx is skipped",
+                gas_price, max_gas_price
+            ),
+            Error::Signing(e) => write!(f, "tx signing failed: {}, check --private_key", e),
+            Error::Abi(e) => write!(
+                f,
+                "contract abi error: {}, check that the contract is compiled and deployed",
+                e
+            ),
+            Error::PriceSource(e) => write!(
+                f,
+                "price source failed: {}, check --api_endpoint and --api_key",
+                e
+            ),
+            Error::Server(e) => write!(f, "http server failed: {}", e),
+            Error::Quorum(e) => write!(
+                f,
+                "quorum report failed: {}, check --peers and that the reporters are registered",
+                e
+            ),
+            Error::Peer(e) => write!(
+                f,
+                "peer exchange failed: {}, check --node_peers and --trusted_nodes",
+                e
+            ),
+            Error::Lease(e) => write!(f, "leader lease failed: {}, check --lease", e),
+            Error::Storage(e) => write!(
+                f,
+                "storage failed: {}, check that the path exists and is writable",
+                e
+            ),
+            Error::Sink(e) => write!(f, "event sink failed: {}, check --sink", e),
+            Error::Admin(e) => write!(f, "admin action refused: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<web3::Error> for Error {
+    fn from(e: web3::Error) -> Self {
+        Error::Rpc(e)
+    }
+}
+
+impl From<web3::contract::Error> for Error {
+    fn from(e: web3::contract::Error) -> Self {
+        match e {
+            web3::contract::Error::Api(e) => Error::Rpc(e),
+            e => Error::Abi(e.to_string()),
+        }
+    }
+}
+
+impl From<ethabi::Error> for Error {
+    fn from(e: ethabi::Error) -> Self {
+        Error::Abi(e.to_string())
+    }
+}
+
+impl From<ethtxsign::SigningError> for Error {
+    fn from(e: ethtxsign::SigningError) -> Self {
+        Error::Signing(e.to_string())
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Server(e)
+    }
+}
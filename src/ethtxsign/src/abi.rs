@@ -0,0 +1,738 @@
+//! Solidity ABI encoding and decoding.
+//!
+//! Encodes calldata for offline-signed transactions and decodes call results
+//! and event data without the web3 stack.
+
+use ethereum_types::{H160, H256, U256};
+use std::error;
+use std::fmt;
+use tiny_keccak::keccak256;
+
+/// Solidity type of a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    Address,
+    Bool,
+    /// `uint<M>`, M is the size in bits
+    Uint(usize),
+    /// `int<M>`, M is the size in bits
+    Int(usize),
+    /// `bytes<M>`, M is the size in bytes
+    FixedBytes(usize),
+    Bytes,
+    String,
+    /// `T[]`
+    Array(Box<ParamType>),
+    /// `T[k]`
+    FixedArray(Box<ParamType>, usize),
+    /// `(T1,T2,...)`
+    Tuple(Vec<ParamType>),
+}
+
+/// Value of a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Address(H160),
+    Bool(bool),
+    Uint(U256),
+    /// Two's complement representation of the signed value
+    Int(U256),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<Token>),
+    FixedArray(Vec<Token>),
+    Tuple(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Type or human-readable signature could not be parsed
+    InvalidSignature(String),
+    /// Encoded data is truncated or malformed
+    InvalidData,
+    /// Tokens do not match the types of the signature
+    TypeMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidSignature(ref s) => write!(f, "invalid abi signature: {}", s),
+            Error::InvalidData => write!(f, "invalid abi encoded data"),
+            Error::TypeMismatch => write!(f, "tokens do not match the abi types"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl ParamType {
+    /// Parses a type like `uint256`, `bytes32[]` or `(address,uint256)[2]`.
+    /// Parameter names and `indexed`/`memory` keywords inside tuples are ignored.
+    pub fn parse(ty: &str) -> Result<ParamType, Error> {
+        let ty = ty.trim();
+        let invalid = || Error::InvalidSignature(ty.to_string());
+
+        if ty.ends_with(']') {
+            let open = ty.rfind('[').ok_or_else(invalid)?;
+            let inner = Box::new(ParamType::parse(&ty[..open])?);
+            let len = &ty[open + 1..ty.len() - 1];
+            return if len.is_empty() {
+                Ok(ParamType::Array(inner))
+            } else {
+                let len = len.parse().map_err(|_| invalid())?;
+                Ok(ParamType::FixedArray(inner, len))
+            };
+        }
+
+        let tuple = if ty.starts_with("tuple(") {
+            Some(&ty[5..])
+        } else if ty.starts_with('(') {
+            Some(ty)
+        } else {
+            None
+        };
+        if let Some(tuple) = tuple {
+            if !tuple.ends_with(')') {
+                return Err(invalid());
+            }
+            let types = split_params(&tuple[1..tuple.len() - 1])?
+                .into_iter()
+                .map(|param| ParamType::parse(param_type(param)))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(ParamType::Tuple(types));
+        }
+
+        let size = |prefix: &str, default: usize| -> Result<usize, Error> {
+            match &ty[prefix.len()..] {
+                "" => Ok(default),
+                s => s.parse().map_err(|_| invalid()),
+            }
+        };
+
+        let param = match ty {
+            "address" => ParamType::Address,
+            "bool" => ParamType::Bool,
+            "string" => ParamType::String,
+            "bytes" => ParamType::Bytes,
+            "byte" => ParamType::FixedBytes(1),
+            _ if ty.starts_with("uint") => ParamType::Uint(size("uint", 256)?),
+            _ if ty.starts_with("int") => ParamType::Int(size("int", 256)?),
+            _ if ty.starts_with("bytes") => ParamType::FixedBytes(size("bytes", 0)?),
+            _ => return Err(invalid()),
+        };
+
+        match param {
+            ParamType::Uint(bits) | ParamType::Int(bits)
+                if bits == 0 || bits > 256 || bits % 8 != 0 =>
+            {
+                Err(invalid())
+            }
+            ParamType::FixedBytes(len) if len == 0 || len > 32 => Err(invalid()),
+            param => Ok(param),
+        }
+    }
+
+    /// Canonical form used in the function and event signatures.
+    pub fn canonical(&self) -> String {
+        match *self {
+            ParamType::Address => "address".to_string(),
+            ParamType::Bool => "bool".to_string(),
+            ParamType::Uint(bits) => format!("uint{}", bits),
+            ParamType::Int(bits) => format!("int{}", bits),
+            ParamType::FixedBytes(len) => format!("bytes{}", len),
+            ParamType::Bytes => "bytes".to_string(),
+            ParamType::String => "string".to_string(),
+            ParamType::Array(ref inner) => format!("{}[]", inner.canonical()),
+            ParamType::FixedArray(ref inner, len) => format!("{}[{}]", inner.canonical(), len),
+            ParamType::Tuple(ref types) => format!(
+                "({})",
+                types
+                    .iter()
+                    .map(|t| t.canonical())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        match *self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(ref inner, len) => len > 0 && inner.is_dynamic(),
+            ParamType::Tuple(ref types) => types.iter().any(|t| t.is_dynamic()),
+            _ => false,
+        }
+    }
+
+    /// Size of the static encoding in the head.
+    fn head_size(&self) -> usize {
+        if self.is_dynamic() {
+            return 32;
+        }
+        match *self {
+            ParamType::FixedArray(ref inner, len) => inner.head_size() * len,
+            ParamType::Tuple(ref types) => types.iter().map(|t| t.head_size()).sum(),
+            _ => 32,
+        }
+    }
+}
+
+impl Token {
+    /// Checks that the token is a value of the type.
+    pub fn type_check(&self, ty: &ParamType) -> bool {
+        match (self, ty) {
+            (Token::Address(_), ParamType::Address)
+            | (Token::Bool(_), ParamType::Bool)
+            | (Token::Bytes(_), ParamType::Bytes)
+            | (Token::String(_), ParamType::String) => true,
+            (Token::Uint(v), ParamType::Uint(bits)) => *bits >= 256 || v.bits() <= *bits,
+            (Token::Int(v), ParamType::Int(bits)) => {
+                // the two's complement must be sign extended from the bit width
+                let magnitude = if v.bit(255) { !*v } else { *v };
+                *bits >= 256 || magnitude.bits() < *bits
+            }
+            (Token::FixedBytes(b), ParamType::FixedBytes(len)) => b.len() == *len,
+            (Token::Array(tokens), ParamType::Array(inner)) => {
+                tokens.iter().all(|t| t.type_check(inner))
+            }
+            (Token::FixedArray(tokens), ParamType::FixedArray(inner, len)) => {
+                tokens.len() == *len && tokens.iter().all(|t| t.type_check(inner))
+            }
+            (Token::Tuple(tokens), ParamType::Tuple(types)) => {
+                tokens.len() == types.len()
+                    && tokens
+                        .iter()
+                        .zip(types.iter())
+                        .all(|(t, ty)| t.type_check(ty))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match *self {
+            Token::Bytes(_) | Token::String(_) | Token::Array(_) => true,
+            Token::FixedArray(ref tokens) | Token::Tuple(ref tokens) => {
+                tokens.iter().any(|t| t.is_dynamic())
+            }
+            _ => false,
+        }
+    }
+
+    fn head_size(&self) -> usize {
+        if self.is_dynamic() {
+            return 32;
+        }
+        match *self {
+            Token::FixedArray(ref tokens) | Token::Tuple(ref tokens) => {
+                tokens.iter().map(|t| t.head_size()).sum()
+            }
+            _ => 32,
+        }
+    }
+}
+
+/// Human-readable function or event signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub inputs: Vec<ParamType>,
+}
+
+impl Signature {
+    /// Parses `transfer(address,uint256)` as well as the human-readable form
+    /// `function transfer(address to, uint256 amount) returns (bool)` or
+    /// `event Transfer(address indexed from, address indexed to, uint256 value)`.
+    pub fn parse(signature: &str) -> Result<Signature, Error> {
+        let invalid = || Error::InvalidSignature(signature.to_string());
+
+        let mut s = signature.trim();
+        for keyword in &["function ", "event ", "error "] {
+            if s.starts_with(keyword) {
+                s = s[keyword.len()..].trim_start();
+            }
+        }
+
+        let open = s.find('(').ok_or_else(invalid)?;
+        let name = s[..open].trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            return Err(invalid());
+        }
+
+        let close = matching_paren(s, open).ok_or_else(invalid)?;
+        let inputs = split_params(&s[open + 1..close])?
+            .into_iter()
+            .map(|param| ParamType::parse(param_type(param)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Signature {
+            name: name.to_string(),
+            inputs,
+        })
+    }
+
+    /// `name(type1,type2)` form the selector and the topic are hashed from.
+    pub fn canonical(&self) -> String {
+        format!(
+            "{}({})",
+            self.name,
+            self.inputs
+                .iter()
+                .map(|t| t.canonical())
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&keccak256(self.canonical().as_bytes())[..4]);
+        selector
+    }
+
+    pub fn topic(&self) -> H256 {
+        H256::from(keccak256(self.canonical().as_bytes()))
+    }
+
+    /// Selector followed by the encoded arguments, checked against the inputs.
+    pub fn encode_call(&self, tokens: &[Token]) -> Result<Vec<u8>, Error> {
+        if tokens.len() != self.inputs.len()
+            || !tokens
+                .iter()
+                .zip(self.inputs.iter())
+                .all(|(t, ty)| t.type_check(ty))
+        {
+            return Err(Error::TypeMismatch);
+        }
+        let mut data = self.selector().to_vec();
+        data.extend(encode(tokens));
+        Ok(data)
+    }
+
+    /// Decodes the arguments of the calldata with the selector of the signature.
+    pub fn decode_call(&self, data: &[u8]) -> Result<Vec<Token>, Error> {
+        if data.len() < 4 || data[..4] != self.selector() {
+            return Err(Error::InvalidData);
+        }
+        decode(&self.inputs, &data[4..])
+    }
+}
+
+/// 4 bytes function selector of the signature.
+pub fn function_selector(signature: &str) -> Result<[u8; 4], Error> {
+    Ok(Signature::parse(signature)?.selector())
+}
+
+/// Topic of the event signature.
+pub fn event_topic(signature: &str) -> Result<H256, Error> {
+    Ok(Signature::parse(signature)?.topic())
+}
+
+/// Encodes the tokens as the arguments of a function call.
+pub fn encode(tokens: &[Token]) -> Vec<u8> {
+    let head_size: usize = tokens.iter().map(|t| t.head_size()).sum();
+    let mut head = Vec::with_capacity(head_size);
+    let mut tail = Vec::new();
+
+    for token in tokens {
+        if token.is_dynamic() {
+            head.extend_from_slice(&word(U256::from(head_size + tail.len())));
+            tail.extend(encode_token(token));
+        } else {
+            head.extend(encode_token(token));
+        }
+    }
+
+    head.extend(tail);
+    head
+}
+
+fn encode_token(token: &Token) -> Vec<u8> {
+    match *token {
+        Token::Address(ref address) => {
+            let mut out = vec![0u8; 12];
+            out.extend_from_slice(address.as_bytes());
+            out
+        }
+        Token::Bool(b) => word(U256::from(b as u8)).to_vec(),
+        Token::Uint(ref v) | Token::Int(ref v) => word(*v).to_vec(),
+        Token::FixedBytes(ref bytes) => padded(bytes),
+        Token::Bytes(ref bytes) => {
+            let mut out = word(U256::from(bytes.len())).to_vec();
+            out.extend(padded(bytes));
+            out
+        }
+        Token::String(ref s) => encode_token(&Token::Bytes(s.as_bytes().to_vec())),
+        Token::Array(ref tokens) => {
+            let mut out = word(U256::from(tokens.len())).to_vec();
+            out.extend(encode(tokens));
+            out
+        }
+        Token::FixedArray(ref tokens) | Token::Tuple(ref tokens) => encode(tokens),
+    }
+}
+
+/// Decodes the arguments of the types from the encoded data.
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::with_capacity(types.len());
+    let mut offset = 0;
+
+    for ty in types {
+        let token = if ty.is_dynamic() {
+            let tail = read_usize(data, offset)?;
+            if tail > data.len() {
+                return Err(Error::InvalidData);
+            }
+            decode_param(ty, &data[tail..])?
+        } else {
+            if offset > data.len() {
+                return Err(Error::InvalidData);
+            }
+            decode_param(ty, &data[offset..])?
+        };
+        tokens.push(token);
+        offset += ty.head_size();
+    }
+
+    Ok(tokens)
+}
+
+fn decode_param(ty: &ParamType, data: &[u8]) -> Result<Token, Error> {
+    match *ty {
+        ParamType::Address => {
+            let w = read_word(data, 0)?;
+            if w[..12].iter().any(|b| *b != 0) {
+                return Err(Error::InvalidData);
+            }
+            Ok(Token::Address(H160::from_slice(&w[12..])))
+        }
+        ParamType::Bool => match U256::from_big_endian(read_word(data, 0)?) {
+            ref v if v.is_zero() => Ok(Token::Bool(false)),
+            ref v if *v == U256::one() => Ok(Token::Bool(true)),
+            _ => Err(Error::InvalidData),
+        },
+        ParamType::Uint(bits) => {
+            let v = U256::from_big_endian(read_word(data, 0)?);
+            if bits < 256 && v.bits() > bits {
+                return Err(Error::InvalidData);
+            }
+            Ok(Token::Uint(v))
+        }
+        ParamType::Int(bits) => {
+            let w = read_word(data, 0)?;
+            // the value must be sign extended to the full word
+            let sign = if w[32 - bits / 8] & 0x80 != 0 {
+                0xff
+            } else {
+                0
+            };
+            if w[..32 - bits / 8].iter().any(|b| *b != sign) {
+                return Err(Error::InvalidData);
+            }
+            Ok(Token::Int(U256::from_big_endian(w)))
+        }
+        ParamType::FixedBytes(len) => {
+            let w = read_word(data, 0)?;
+            // the value is right padded with zeros
+            if w[len..].iter().any(|b| *b != 0) {
+                return Err(Error::InvalidData);
+            }
+            Ok(Token::FixedBytes(w[..len].to_vec()))
+        }
+        ParamType::Bytes => {
+            let len = read_usize(data, 0)?;
+            let end = 32usize.checked_add(len).ok_or(Error::InvalidData)?;
+            if end > data.len() {
+                return Err(Error::InvalidData);
+            }
+            Ok(Token::Bytes(data[32..end].to_vec()))
+        }
+        ParamType::String => match decode_param(&ParamType::Bytes, data)? {
+            Token::Bytes(bytes) => String::from_utf8(bytes)
+                .map(Token::String)
+                .map_err(|_| Error::InvalidData),
+            _ => Err(Error::InvalidData),
+        },
+        ParamType::Array(ref inner) => {
+            let len = read_usize(data, 0)?;
+            // every element takes at least one word, don't allocate for garbage lengths
+            if len > (data.len() - 32) / 32 {
+                return Err(Error::InvalidData);
+            }
+            let types = vec![(**inner).clone(); len];
+            Ok(Token::Array(decode(&types, &data[32..])?))
+        }
+        ParamType::FixedArray(ref inner, len) => {
+            let types = vec![(**inner).clone(); len];
+            Ok(Token::FixedArray(decode(&types, data)?))
+        }
+        ParamType::Tuple(ref types) => Ok(Token::Tuple(decode(types, data)?)),
+    }
+}
+
+fn word(v: U256) -> [u8; 32] {
+    let mut w = [0u8; 32];
+    v.to_big_endian(&mut w);
+    w
+}
+
+fn padded(bytes: &[u8]) -> Vec<u8> {
+    let mut out = bytes.to_vec();
+    out.resize(bytes.len() + (32 - bytes.len() % 32) % 32, 0);
+    out
+}
+
+fn read_word(data: &[u8], offset: usize) -> Result<&[u8], Error> {
+    match offset.checked_add(32) {
+        Some(end) if end <= data.len() => Ok(&data[offset..end]),
+        _ => Err(Error::InvalidData),
+    }
+}
+
+fn read_usize(data: &[u8], offset: usize) -> Result<usize, Error> {
+    let w = read_word(data, offset)?;
+    if w[..24].iter().any(|b| *b != 0) {
+        return Err(Error::InvalidData);
+    }
+    Ok(U256::from_big_endian(w).low_u64() as usize)
+}
+
+/// Index of the `)` closing the `(` at `open`.
+fn matching_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the parameter list on the top level commas.
+fn split_params(s: &str) -> Result<Vec<&str>, Error> {
+    let mut params = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                params.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        if depth < 0 {
+            return Err(Error::InvalidSignature(s.to_string()));
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() || !params.is_empty() {
+        params.push(last);
+    }
+    if depth != 0 || params.iter().any(|p| p.is_empty()) {
+        return Err(Error::InvalidSignature(s.to_string()));
+    }
+    Ok(params)
+}
+
+/// Type part of a parameter like `uint256 indexed amount` or `(address a, bool b)[] list`.
+fn param_type(param: &str) -> &str {
+    let mut depth = 0;
+    for (i, c) in param.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => return &param[..i],
+            _ => {}
+        }
+    }
+    param
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes of the hex words, the whitespace is ignored.
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<u8> = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_digit(16).unwrap() as u8)
+            .collect();
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect()
+    }
+
+    fn uint(v: u64) -> Token {
+        Token::Uint(U256::from(v))
+    }
+
+    /// Encodes the call and decodes it back, the data must be the solc encoding.
+    fn round_trip(signature: &str, tokens: Vec<Token>, expected: &str) {
+        let signature = Signature::parse(signature).unwrap();
+        let data = signature.encode_call(&tokens).unwrap();
+        assert_eq!(data, hex(expected));
+        assert_eq!(signature.decode_call(&data).unwrap(), tokens);
+    }
+
+    #[test]
+    fn bytes_and_dynamic_array() {
+        round_trip(
+            "sam(bytes,bool,uint256[])",
+            vec![
+                Token::Bytes(b"dave".to_vec()),
+                Token::Bool(true),
+                Token::Array(vec![uint(1), uint(2), uint(3)]),
+            ],
+            "a5643bf2
+            0000000000000000000000000000000000000000000000000000000000000060
+            0000000000000000000000000000000000000000000000000000000000000001
+            00000000000000000000000000000000000000000000000000000000000000a0
+            0000000000000000000000000000000000000000000000000000000000000004
+            6461766500000000000000000000000000000000000000000000000000000000
+            0000000000000000000000000000000000000000000000000000000000000003
+            0000000000000000000000000000000000000000000000000000000000000001
+            0000000000000000000000000000000000000000000000000000000000000002
+            0000000000000000000000000000000000000000000000000000000000000003",
+        );
+    }
+
+    #[test]
+    fn fixed_bytes_between_dynamic_params() {
+        round_trip(
+            "f(uint256,uint32[],bytes10,bytes)",
+            vec![
+                uint(0x123),
+                Token::Array(vec![uint(0x456), uint(0x789)]),
+                Token::FixedBytes(b"1234567890".to_vec()),
+                Token::Bytes(b"Hello, world!".to_vec()),
+            ],
+            "8be65246
+            0000000000000000000000000000000000000000000000000000000000000123
+            0000000000000000000000000000000000000000000000000000000000000080
+            3132333435363738393000000000000000000000000000000000000000000000
+            00000000000000000000000000000000000000000000000000000000000000e0
+            0000000000000000000000000000000000000000000000000000000000000002
+            0000000000000000000000000000000000000000000000000000000000000456
+            0000000000000000000000000000000000000000000000000000000000000789
+            000000000000000000000000000000000000000000000000000000000000000d
+            48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        );
+    }
+
+    #[test]
+    fn nested_arrays_and_strings() {
+        round_trip(
+            "g(uint256[][],string[])",
+            vec![
+                Token::Array(vec![
+                    Token::Array(vec![uint(1), uint(2)]),
+                    Token::Array(vec![uint(3)]),
+                ]),
+                Token::Array(vec![
+                    Token::String("one".to_string()),
+                    Token::String("two".to_string()),
+                    Token::String("three".to_string()),
+                ]),
+            ],
+            "2289b18c
+            0000000000000000000000000000000000000000000000000000000000000040
+            0000000000000000000000000000000000000000000000000000000000000140
+            0000000000000000000000000000000000000000000000000000000000000002
+            0000000000000000000000000000000000000000000000000000000000000040
+            00000000000000000000000000000000000000000000000000000000000000a0
+            0000000000000000000000000000000000000000000000000000000000000002
+            0000000000000000000000000000000000000000000000000000000000000001
+            0000000000000000000000000000000000000000000000000000000000000002
+            0000000000000000000000000000000000000000000000000000000000000001
+            0000000000000000000000000000000000000000000000000000000000000003
+            0000000000000000000000000000000000000000000000000000000000000003
+            0000000000000000000000000000000000000000000000000000000000000060
+            00000000000000000000000000000000000000000000000000000000000000a0
+            00000000000000000000000000000000000000000000000000000000000000e0
+            0000000000000000000000000000000000000000000000000000000000000003
+            6f6e650000000000000000000000000000000000000000000000000000000000
+            0000000000000000000000000000000000000000000000000000000000000003
+            74776f0000000000000000000000000000000000000000000000000000000000
+            0000000000000000000000000000000000000000000000000000000000000005
+            7468726565000000000000000000000000000000000000000000000000000000",
+        );
+    }
+
+    #[test]
+    fn static_and_dynamic_tuples() {
+        round_trip(
+            "h((address,bool),(uint256,string)[])",
+            vec![
+                Token::Tuple(vec![
+                    Token::Address(H160::from_low_u64_be(0xabcd)),
+                    Token::Bool(true),
+                ]),
+                Token::Array(vec![Token::Tuple(vec![
+                    uint(7),
+                    Token::String("abc".to_string()),
+                ])]),
+            ],
+            "9235c7e5
+            000000000000000000000000000000000000000000000000000000000000abcd
+            0000000000000000000000000000000000000000000000000000000000000001
+            0000000000000000000000000000000000000000000000000000000000000060
+            0000000000000000000000000000000000000000000000000000000000000001
+            0000000000000000000000000000000000000000000000000000000000000020
+            0000000000000000000000000000000000000000000000000000000000000007
+            0000000000000000000000000000000000000000000000000000000000000040
+            0000000000000000000000000000000000000000000000000000000000000003
+            6162630000000000000000000000000000000000000000000000000000000000",
+        );
+    }
+
+    #[test]
+    fn fixed_bytes_padding_is_rejected() {
+        let types = [ParamType::FixedBytes(2)];
+        let padded = hex("abcd000000000000000000000000000000000000000000000000000000000000");
+        assert_eq!(
+            decode(&types, &padded),
+            Ok(vec![Token::FixedBytes(vec![0xab, 0xcd])])
+        );
+        let dirty = hex("abcd000000000000000000000000000000000000000000000000000000000001");
+        assert_eq!(decode(&types, &dirty), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn integers_over_the_bit_width_are_rejected() {
+        let encode = |signature: &str, token: Token| {
+            Signature::parse(signature).unwrap().encode_call(&[token])
+        };
+        assert!(encode("f(uint8)", uint(255)).is_ok());
+        assert_eq!(encode("f(uint8)", uint(256)), Err(Error::TypeMismatch));
+        assert!(encode("f(uint256)", Token::Uint(U256::max_value())).is_ok());
+
+        let int = |v: i64| {
+            let magnitude = U256::from(v.unsigned_abs());
+            Token::Int(if v < 0 { !magnitude + 1 } else { magnitude })
+        };
+        assert!(encode("f(int8)", int(127)).is_ok());
+        assert!(encode("f(int8)", int(-128)).is_ok());
+        assert_eq!(encode("f(int8)", int(128)), Err(Error::TypeMismatch));
+        assert_eq!(encode("f(int8)", int(-129)), Err(Error::TypeMismatch));
+        assert!(encode("f(int256)", int(-1)).is_ok());
+    }
+}
@@ -8,8 +8,12 @@ extern crate tiny_keccak;
 extern crate secp256k1;
 extern crate rlp;
 
+pub mod abi;
 mod raw_transaction;
+mod signature;
 
 pub use self::raw_transaction::RawTransaction;
 pub use self::raw_transaction::keccak256_hash;
 pub use self::raw_transaction::pvt_key_from_slice;
+pub use self::signature::{private_key_address, recover_address, sign_hash};
+pub use secp256k1::Error as SigningError;
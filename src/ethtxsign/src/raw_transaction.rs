@@ -1,6 +1,7 @@
 use ethereum_types::{H160, H256, U256};
 use rlp::RlpStream;
 use secp256k1::key::SecretKey;
+use secp256k1::Error;
 use secp256k1::Message;
 use secp256k1::Secp256k1;
 use tiny_keccak::keccak256;
@@ -25,9 +26,9 @@ pub struct RawTransaction {
 
 impl RawTransaction {
     /// Signs and returns the RLP-encoded transaction
-    pub fn sign(&self, private_key: &H256, chain_id: &u8) -> Vec<u8> {
+    pub fn sign(&self, private_key: &H256, chain_id: &u8) -> Result<Vec<u8>, Error> {
         let hash = self.hash(*chain_id);
-        let sig = ecdsa_sign(&hash, &private_key.0, &chain_id);
+        let sig = ecdsa_sign(&hash, &private_key.0, &chain_id)?;
         let mut tx = RlpStream::new();
         tx.begin_unbounded_list();
         self.encode(&mut tx);
@@ -35,7 +36,7 @@ impl RawTransaction {
         tx.append(&sig.r);
         tx.append(&sig.s);
         tx.complete_unbounded_list();
-        tx.out()
+        Ok(tx.out())
     }
 
     fn hash(&self, chain_id: u8) -> Vec<u8> {
@@ -76,18 +77,17 @@ pub fn pvt_key_from_slice(key: &[u8]) -> Option<H256> {
     Some(h)
 }
 
-fn ecdsa_sign(hash: &[u8], private_key: &[u8], chain_id: &u8) -> EcdsaSig {
+fn ecdsa_sign(hash: &[u8], private_key: &[u8], chain_id: &u8) -> Result<EcdsaSig, Error> {
     let s = Secp256k1::new();
-    let msg = Message::from_slice(hash).unwrap();
-    let key = SecretKey::from_slice(&s, private_key).unwrap();
-    let result = s.sign_recoverable(&msg, &key);
-    let recoverable_sig = result.unwrap();
+    let msg = Message::from_slice(hash)?;
+    let key = SecretKey::from_slice(&s, private_key)?;
+    let recoverable_sig = s.sign_recoverable(&msg, &key)?;
     let (recovery_id, sig_bytes) = recoverable_sig.serialize_compact(&s);
-    EcdsaSig {
+    Ok(EcdsaSig {
         v: vec![recovery_id.to_i32() as u8 + chain_id * 2 + 35],
         r: sig_bytes[0..32].to_vec(),
         s: sig_bytes[32..64].to_vec(),
-    }
+    })
 }
 
 pub struct EcdsaSig {
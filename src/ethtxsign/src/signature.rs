@@ -0,0 +1,41 @@
+use ethereum_types::{H160, H256};
+use secp256k1::key::{PublicKey, SecretKey};
+use secp256k1::{Error, Message, RecoverableSignature, RecoveryId, Secp256k1};
+use tiny_keccak::keccak256;
+
+/// Signs the 32 bytes hash, the signature is `r || s || v` with `v` 27 or 28
+/// as `ecrecover` expects.
+pub fn sign_hash(hash: &H256, private_key: &H256) -> Result<Vec<u8>, Error> {
+    let s = Secp256k1::new();
+    let msg = Message::from_slice(&hash.0)?;
+    let key = SecretKey::from_slice(&s, &private_key.0)?;
+    let (recovery_id, sig_bytes) = s.sign_recoverable(&msg, &key)?.serialize_compact(&s);
+    let mut sig = sig_bytes.to_vec();
+    sig.push(recovery_id.to_i32() as u8 + 27);
+    Ok(sig)
+}
+
+/// Recovers the address which signed the hash with `sign_hash`.
+pub fn recover_address(hash: &H256, signature: &[u8]) -> Result<H160, Error> {
+    if signature.len() != 65 || signature[64] < 27 {
+        return Err(Error::InvalidSignature);
+    }
+    let s = Secp256k1::new();
+    let msg = Message::from_slice(&hash.0)?;
+    let recovery_id = RecoveryId::from_i32(i32::from(signature[64] - 27))?;
+    let sig = RecoverableSignature::from_compact(&s, &signature[..64], recovery_id)?;
+    Ok(public_key_address(&s, &s.recover(&msg, &sig)?))
+}
+
+/// Ethereum address of the private key.
+pub fn private_key_address(private_key: &H256) -> Result<H160, Error> {
+    let s = Secp256k1::new();
+    let key = SecretKey::from_slice(&s, &private_key.0)?;
+    Ok(public_key_address(&s, &PublicKey::from_secret_key(&s, &key)?))
+}
+
+fn public_key_address(s: &Secp256k1, key: &PublicKey) -> H160 {
+    // uncompressed key without the 0x04 prefix
+    let serialized = key.serialize_vec(s, false);
+    H160::from_slice(&keccak256(&serialized[1..])[12..])
+}
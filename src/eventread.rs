@@ -1,72 +1,418 @@
-use crate::web3util;
+use crate::args;
+use crate::checkpoint::Checkpoint;
+use crate::client;
+use crate::error::{Error, Result};
+use crate::notify::Notifier;
+use crate::record::{self, EventRecord, Status};
+use crate::reorg::{Change, ReorgBuffer};
+use crate::sink::{self, Sink};
 use clap::ArgMatches;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::{thread, time};
 use web3::futures::{Future, Stream};
-use web3::types::{Address, BlockNumber, FilterBuilder};
+use web3::types::{Address, BlockHeader, BlockId, BlockNumber, Log, H256};
+use web3::Transport;
 extern crate tokio_core;
+use tokio_core::reactor::Core;
 
-pub fn run_with_ws(
-    logger: slog::Logger,
-    arg: &ArgMatches,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new(arg);
-    let mut eloop = tokio_core::reactor::Core::new().unwrap();
-    let web3 = web3::Web3::new(
-        web3::transports::WebSocket::with_event_loop(&config.net, &eloop.handle()).unwrap(),
-    );
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+    let mut eloop = event_loop()?;
+    let web3 = web3::Web3::new(web3::transports::WebSocket::with_event_loop(
+        &config.net,
+        &eloop.handle(),
+    )?);
+    let mut reader = Reader::open(&logger, &config)?;
 
-    let topic = web3util::h256_topic(ethtxsign::keccak256_hash("PriceChanged(uint256)".as_ref()));
+    // subscribed before the backfill, so the blocks mined meanwhile are not missed
+    let filter = client::contract_events_filter(
+        config.contract_addr,
+        record::topics(),
+        reader.from_block().into(),
+        BlockNumber::Latest,
+    );
+    let (logs, heads) = eloop.run(
+        web3.eth_subscribe()
+            .subscribe_logs(filter)
+            .join(web3.eth_subscribe().subscribe_new_heads()),
+    )?;
 
+    let latest = latest_block(&mut eloop, &web3)?;
+    reader.push_head(&mut eloop, &web3, latest)?;
+    reader.backfill(&mut eloop, &web3, reader.from_block()..=latest.number)?;
+    // the subscription delivers the logs of the later blocks
     info!(
         logger,
-        "readevent runs on the {} network with contractaddr {} with topic {} from blocknum {}",
-        config.net,
-        config.contract_addr.unwrap(),
-        topic.unwrap(),
-        config.block_num
+        "backfilled up to block {}, following the new blocks", latest.number
     );
 
-    let filter = FilterBuilder::default()
-        .address(vec![config.contract_addr.unwrap()])
-        .topics(Some(vec![topic.unwrap()]), None, None, None)
-        .from_block(BlockNumber::from(config.block_num))
-        .to_block(BlockNumber::Latest)
-        .build();
-
-    info!(logger, "filter {:?}", filter);
-
-    eloop
-        .run(web3.eth_subscribe().subscribe_logs(filter).then(|sub| {
-            sub.unwrap()
-                .for_each(|log| {
-                    info!(logger, "got event: {:?}", log);
-                    Ok(())
-                })
-                .map_err(|_| ())
-        }))
-        .unwrap();
-
-    Ok(())
+    // one update at a time, so the parents of a head can be fetched between them
+    let mut updates = logs
+        .map(Update::Log)
+        .select(heads.map(Update::Head))
+        .map_err(Error::from);
+    loop {
+        let (update, rest) = eloop.run(updates.into_future()).map_err(|(e, _)| e)?;
+        updates = rest;
+        match update {
+            Some(Update::Log(log)) => {
+                let changes = reader.buffer.push_log(log);
+                reader.apply(changes)?;
+            }
+            Some(Update::Head(head)) => {
+                if let (Some(number), Some(hash)) = (head.number, head.hash) {
+                    let head = Block {
+                        number: number.as_u64(),
+                        hash,
+                        parent_hash: head.parent_hash,
+                        timestamp: head.timestamp.low_u64(),
+                    };
+                    reader.read_to = reader.read_to.max(Some(head.number));
+                    reader.push_head(&mut eloop, &web3, head)?;
+                }
+            }
+            None => return Ok(()),
+        }
+    }
 }
 
-struct Config {
-    contract_addr: Option<Address>,
-    net: String,
-    block_num: u64,
+/// Polls `eth_getLogs` for the providers without subscriptions. Every poll
+/// walks the parents of the head down to the blocks of the previous poll, so
+/// a reorg is seen by the changed block hashes, also below the confirmed logs.
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+    let mut eloop = event_loop()?;
+    let web3 = web3::Web3::new(web3::transports::Http::with_event_loop(
+        &config.net,
+        &eloop.handle(),
+        1,
+    )?);
+    let mut reader = Reader::open(&logger, &config)?;
+
+    loop {
+        if let Err(e) = reader.poll(&mut eloop, &web3) {
+            if !e.is_transient() {
+                return Err(e);
+            }
+            error!(logger, "poll failed: {}", e);
+        }
+        thread::sleep(time::Duration::from_secs(config.poll_interval));
+    }
 }
 
-impl Config {
-    fn new(arg: &ArgMatches) -> Self {
-        let net = arg.value_of("net").unwrap().to_string();
+fn event_loop() -> Result<Core> {
+    Core::new().map_err(|e| Error::Rpc(web3::Error::Transport(e.to_string())))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    number: u64,
+    hash: H256,
+    parent_hash: H256,
+    timestamp: u64,
+}
+
+fn latest_block<T: Transport>(eloop: &mut Core, web3: &web3::Web3<T>) -> Result<Block> {
+    fetch_block(eloop, web3, BlockId::Number(BlockNumber::Latest))?
+        .ok_or_else(|| Error::Rpc(web3::Error::InvalidResponse("no latest block".into())))
+}
+
+fn fetch_block<T: Transport>(
+    eloop: &mut Core,
+    web3: &web3::Web3<T>,
+    id: BlockId,
+) -> Result<Option<Block>> {
+    Ok(eloop.run(web3.eth().block(id))?.and_then(|block| {
+        Some(Block {
+            number: block.number?.as_u64(),
+            hash: block.hash?,
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp.low_u64(),
+        })
+    }))
+}
 
-        let ca = arg.value_of("contractaddr").unwrap();
-        let contract_address: Address = ca.parse().unwrap();
+/// Confirmed stream and its checkpoint, shared by the ws and http modes.
+struct Reader<'a> {
+    logger: &'a slog::Logger,
+    config: &'a Config,
+    buffer: ReorgBuffer,
+    checkpoint: Checkpoint,
+    sinks: Vec<Box<dyn Sink>>,
+    /// Hash and timestamp of the recent blocks by number
+    timestamps: BTreeMap<u64, (H256, u64)>,
+    /// Last block whose logs were read in this run, by the backfill or the
+    /// subscription, all the blocks from the checkpoint up to it are read
+    read_to: Option<u64>,
+}
 
-        let block_num: u64 = arg.value_of("blocknum").unwrap().parse().unwrap();
+impl<'a> Reader<'a> {
+    fn open(logger: &'a slog::Logger, config: &'a Config) -> Result<Self> {
+        info!(
+            logger,
+            "readevent runs on the {} network with contractaddr {} to sinks {:?}",
+            config.net,
+            config.contract_addr,
+            config.sinks
+        );
 
-        Config {
-            contract_addr: Some(contract_address),
-            net,
-            block_num,
+        let mut sinks = config
+            .sinks
+            .iter()
+            .map(|spec| sink::sink(spec))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(notifier) = config.notifier.clone() {
+            notifier.clone().spawn(logger.clone());
+            sinks.push(Box::new(sink::Notify::new(notifier)));
         }
+        let reader = Reader {
+            logger,
+            config,
+            buffer: ReorgBuffer::new(config.confirmations, config.reorg_window),
+            checkpoint: Checkpoint::open(&config.checkpoint)?,
+            sinks,
+            timestamps: BTreeMap::new(),
+            read_to: None,
+        };
+        info!(
+            logger,
+            "starting from block {} with {} confirmations, checkpoint {} at {:?}",
+            reader.from_block(),
+            config.confirmations,
+            config.checkpoint,
+            reader.checkpoint.last()
+        );
+        Ok(reader)
+    }
+
+    /// First block that is not fully processed.
+    fn from_block(&self) -> u64 {
+        self.checkpoint
+            .last()
+            .map_or(self.config.block_num, |last| last + 1)
+    }
+
+    /// Adds the head and fetches its parents down to the known chain, a block
+    /// the node no longer has is left to the walk of the next head.
+    fn push_head<T: Transport>(
+        &mut self,
+        eloop: &mut Core,
+        web3: &web3::Web3<T>,
+        head: Block,
+    ) -> Result<()> {
+        self.timestamps
+            .insert(head.number, (head.hash, head.timestamp));
+        let oldest = head.number.saturating_sub(self.config.reorg_window);
+        self.timestamps = self.timestamps.split_off(&oldest);
+
+        let changes = self
+            .buffer
+            .push_head(head.number, head.hash, head.parent_hash);
+        self.apply(changes)?;
+
+        while let Some((number, hash)) = self.buffer.unchecked_block() {
+            let block = match fetch_block(eloop, web3, BlockId::Hash(hash))? {
+                Some(block) => block,
+                None => break,
+            };
+            self.timestamps
+                .insert(block.number, (block.hash, block.timestamp));
+            let changes = self.buffer.push_block(number, hash, block.parent_hash);
+            self.apply(changes)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the changes to the sinks and moves the checkpoint. A failed sink
+    /// stops the reader before the checkpoint, so the records are written at
+    /// least once.
+    fn apply(&mut self, changes: Vec<Change>) -> Result<()> {
+        for change in changes {
+            self.emit(change)?;
+        }
+        self.save_checkpoint()
+    }
+
+    /// Moves the checkpoint to the highest block whose logs are all read and
+    /// emitted, a restart after a failed backfill or sink reads the blocks
+    /// above it again.
+    fn save_checkpoint(&mut self) -> Result<()> {
+        match (self.buffer.emitted_block(), self.read_to) {
+            (Some(emitted), Some(read_to)) => self.checkpoint.save(emitted.min(read_to)),
+            _ => Ok(()),
+        }
+    }
+
+    fn emit(&mut self, change: Change) -> Result<()> {
+        let (status, log) = match change {
+            Change::Confirmed(log) => (Status::Confirmed, log),
+            Change::Retracted(log) => (Status::Retracted, log),
+        };
+        let timestamp =
+            log.block_number
+                .and_then(|number| match self.timestamps.get(&number.as_u64()) {
+                    Some(&(hash, timestamp)) if Some(hash) == log.block_hash => Some(timestamp),
+                    _ => None,
+                });
+
+        let record = match EventRecord::new(status, &log, timestamp) {
+            Ok(Some(record)) => record,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!(self.logger, "undecodable event {:?}: {}", log, e);
+                return Ok(());
+            }
+        };
+        info!(
+            self.logger,
+            "{} {} {:?} in block {}",
+            status.as_str(),
+            record.event,
+            record.fields,
+            record.block_number
+        );
+
+        for sink in &mut self.sinks {
+            sink.write(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the logs of the blocks in chunks of `eth_getLogs`. The chunk
+    /// halves when the node refuses the range and grows back to
+    /// `--chunk_size` after a success.
+    fn backfill<T: Transport>(
+        &mut self,
+        eloop: &mut Core,
+        web3: &web3::Web3<T>,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<()> {
+        let mut chunk = self.config.chunk_size;
+        let (mut start, last) = blocks.into_inner();
+
+        while start <= last {
+            let end = last.min(start + chunk - 1);
+            let filter = client::contract_events_filter(
+                self.config.contract_addr,
+                record::topics(),
+                start.into(),
+                end.into(),
+            );
+            match eloop.run(web3.eth().logs(filter)) {
+                Ok(logs) => {
+                    debug!(
+                        self.logger,
+                        "{} logs in blocks {}..={}",
+                        logs.len(),
+                        start,
+                        end
+                    );
+                    self.fetch_timestamps(eloop, web3, &logs)?;
+                    for log in logs {
+                        let changes = self.buffer.push_log(log);
+                        for change in changes {
+                            self.emit(change)?;
+                        }
+                    }
+                    self.read_to = self.read_to.max(Some(end));
+                    self.save_checkpoint()?;
+                    start = end + 1;
+                    chunk = self.config.chunk_size.min(chunk * 2);
+                }
+                Err(e) if chunk > 1 => {
+                    chunk /= 2;
+                    warn!(
+                        self.logger,
+                        "blocks {}..={} refused: {}, retry in chunks of {}", start, end, e, chunk
+                    );
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the timestamps of the blocks of the logs that are not known yet.
+    fn fetch_timestamps<T: Transport>(
+        &mut self,
+        eloop: &mut Core,
+        web3: &web3::Web3<T>,
+        logs: &[Log],
+    ) -> Result<()> {
+        for log in logs {
+            let (number, hash) = match (log.block_number, log.block_hash) {
+                (Some(number), Some(hash)) => (number.as_u64(), hash),
+                _ => continue,
+            };
+            if self.timestamps.get(&number).map(|&(known, _)| known) == Some(hash) {
+                continue;
+            }
+            if let Some(block) = fetch_block(eloop, web3, BlockId::Hash(hash))? {
+                self.timestamps
+                    .insert(block.number, (block.hash, block.timestamp));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the head with its parents, which replaces the reorged blocks, then
+    /// reads the logs from the block after the checkpoint up to the head. The
+    /// checkpoint is below the blocks a failed backfill didn't read and the
+    /// unconfirmed ones, so the next poll reads them again.
+    fn poll<T: Transport>(&mut self, eloop: &mut Core, web3: &web3::Web3<T>) -> Result<()> {
+        let head = latest_block(eloop, web3)?;
+        let from_block = self.from_block();
+
+        self.push_head(eloop, web3, head)?;
+        self.backfill(eloop, web3, from_block..=head.number)
+    }
+}
+
+enum Update {
+    Log(Log),
+    Head(BlockHeader),
+}
+
+struct Config {
+    contract_addr: Address,
+    net: String,
+    block_num: u64,
+    confirmations: u64,
+    reorg_window: u64,
+    /// File of the last fully processed block
+    checkpoint: String,
+    /// Max blocks of one `eth_getLogs` query
+    chunk_size: u64,
+    /// Seconds between the polls of the http mode
+    poll_interval: u64,
+    /// `--sink` values, see [`sink::sink`]
+    sinks: Vec<String>,
+    /// Queue of the `PriceChanged` notifications
+    notifier: Option<Arc<Notifier>>,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            contract_addr: args::address(arg, "contractaddr")?,
+            net: args::required(arg, "net")?.to_string(),
+            block_num: args::parse(arg, "blocknum")?,
+            confirmations: args::parse(arg, "confirmations")?,
+            reorg_window: args::parse(arg, "reorg_window")?,
+            checkpoint: args::required(arg, "checkpoint")?.to_string(),
+            chunk_size: match args::parse(arg, "chunk_size")? {
+                0 => return Err(Error::invalid("chunk_size", "must be at least 1")),
+                size => size,
+            },
+            poll_interval: args::parse(arg, "poll_interval")?,
+            sinks: arg
+                .values_of("sink")
+                .map(|sinks| sinks.map(str::to_string).collect())
+                .unwrap_or_default(),
+            notifier: Notifier::from_args(arg)?.map(Arc::new),
+        })
     }
 }
@@ -0,0 +1,392 @@
+//! Push feed of the price updates served at `/v1/stream` and `/v1/events`.
+//!
+//! WebSocket clients subscribe per pair with
+//! `{"op":"subscribe","pair":"ETH / BTC"}` and receive the [`Message`]s of the
+//! pair as JSON text frames. The pair of a feed is the `description()` of its
+//! contract. Every message is stored in the history under an increasing id, so
+//! the Server-Sent Events clients resume after their `Last-Event-ID`.
+
+use crate::args;
+use crate::bindings::price_oracle::events;
+use crate::client::{self, PriceOracle, Round, PRICE_DECIMALS};
+use crate::error::{Error, Result};
+use crate::web3util;
+use clap::{Arg, ArgMatches};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::delay_for;
+use web3::futures::Future;
+use web3::types::{TransactionReceipt, U256};
+use web3::Transport;
+
+/// Messages kept for the slow clients before they lag.
+const FEED_CAPACITY: usize = 256;
+
+/// Update pushed to the subscribers of the pair.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// Price fetched by the service before it is reported
+    Price {
+        pair: String,
+        source: String,
+        /// Quote currency for 1 base currency
+        price: String,
+        /// `price` multiplied by 10^18 as stored in the contract
+        answer: String,
+    },
+    /// Update tx mined by the service
+    Transaction {
+        pair: String,
+        transaction_hash: String,
+        block_number: Option<u64>,
+        /// `mined` or `reverted`
+        status: &'static str,
+    },
+    /// `PriceChanged` event with the confirmations on top of it
+    PriceChanged {
+        pair: String,
+        round_id: String,
+        price: String,
+        answer: String,
+        updated_at: u64,
+        block_number: Option<u64>,
+        transaction_hash: Option<String>,
+    },
+}
+
+impl Message {
+    pub fn pair(&self) -> &str {
+        match self {
+            Message::Price { pair, .. }
+            | Message::Transaction { pair, .. }
+            | Message::PriceChanged { pair, .. } => pair,
+        }
+    }
+
+    /// Value of the `type` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Price { .. } => "price",
+            Message::Transaction { .. } => "transaction",
+            Message::PriceChanged { .. } => "price_changed",
+        }
+    }
+}
+
+/// Stored [`Message`] as it is sent to the clients.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Increasing id of the message in the history
+    pub id: u64,
+    pub kind: String,
+    pub pair: String,
+    /// JSON of the message
+    pub data: String,
+}
+
+/// Request of the client on the stream.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Command {
+    Subscribe { pair: String },
+    Unsubscribe { pair: String },
+}
+
+/// Answer to a [`Command`] or a notice of the stream.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    Subscribed {
+        pair: String,
+    },
+    Unsubscribed {
+        pair: String,
+    },
+    /// The client was too slow and missed the messages
+    Lagged {
+        skipped: u64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// Position of [`Feed::follow`] in the `PriceChanged` logs.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    /// First block not read yet
+    next_block: Option<u64>,
+    /// Block and log index of the last published log, the logs up to it are
+    /// skipped when a failed poll reads their block again
+    last_log: Option<(u64, U256)>,
+}
+
+/// Broadcasts the updates of one pair to the stream clients.
+pub struct Feed {
+    pair: String,
+    sender: broadcast::Sender<Event>,
+    history: Mutex<History>,
+}
+
+/// Last messages of the feed in the `events` table of a sqlite file.
+struct History {
+    path: String,
+    conn: rusqlite::Connection,
+    /// Messages kept for the resume
+    size: u64,
+}
+
+impl History {
+    fn open(path: &str, size: u64) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path).map_err(|e| storage(path, e))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                pair TEXT NOT NULL,
+                data TEXT NOT NULL
+            )",
+            rusqlite::NO_PARAMS,
+        )
+        .map_err(|e| storage(path, e))?;
+
+        Ok(History {
+            path: path.to_string(),
+            conn,
+            size,
+        })
+    }
+
+    fn append(&self, message: &Message) -> Result<Event> {
+        let data = serde_json::to_string(message).map_err(|e| Error::Storage(e.to_string()))?;
+        self.conn
+            .execute(
+                "INSERT INTO events (kind, pair, data) VALUES (?1, ?2, ?3)",
+                rusqlite::params![message.kind(), message.pair(), data],
+            )
+            .map_err(|e| storage(&self.path, e))?;
+        let id = self.conn.last_insert_rowid() as u64;
+        self.conn
+            .execute(
+                "DELETE FROM events WHERE id <= ?1",
+                rusqlite::params![id.saturating_sub(self.size) as i64],
+            )
+            .map_err(|e| storage(&self.path, e))?;
+
+        Ok(Event {
+            id,
+            kind: message.kind().to_string(),
+            pair: message.pair().to_string(),
+            data,
+        })
+    }
+
+    fn since(&self, last_id: u64, limit: u64) -> Result<Vec<Event>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, kind, pair, data FROM events WHERE id > ?1 ORDER BY id LIMIT ?2")
+            .map_err(|e| storage(&self.path, e))?;
+        let events = stmt
+            .query_map(
+                rusqlite::params![last_id as i64, limit.min(i64::MAX as u64) as i64],
+                |row| {
+                    Ok(Event {
+                        id: row.get::<_, i64>(0)? as u64,
+                        kind: row.get(1)?,
+                        pair: row.get(2)?,
+                        data: row.get(3)?,
+                    })
+                },
+            )
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| storage(&self.path, e))?;
+        Ok(events)
+    }
+}
+
+impl Feed {
+    /// Feed with the history in the sqlite file, keeping the last
+    /// `history_size` messages.
+    pub fn new(pair: impl Into<String>, history: &str, history_size: u64) -> Result<Self> {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Ok(Feed {
+            pair: pair.into(),
+            sender,
+            history: Mutex::new(History::open(history, history_size)?),
+        })
+    }
+
+    /// Feed of the pair named by the `description()` of the contract with the
+    /// history of `--stream_history`.
+    pub fn from_args<T: Transport>(oracle: &PriceOracle<T>, arg: &ArgMatches) -> Result<Self> {
+        let (_, description) = oracle.feed_info()?;
+        let pair = if description.is_empty() {
+            format!("{:?}", oracle.address())
+        } else {
+            description
+        };
+        Feed::new(
+            pair,
+            args::required(arg, "stream_history")?,
+            args::parse(arg, "stream_history_size")?,
+        )
+    }
+
+    pub fn pair(&self) -> &str {
+        &self.pair
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Stored events after the id, the ones pruned from the history are lost.
+    pub fn since(&self, last_id: u64) -> Result<Vec<Event>> {
+        self.lock_history().since(last_id, u64::MAX)
+    }
+
+    /// At most `limit` stored events after the id.
+    pub fn page(&self, last_id: u64, limit: u64) -> Result<Vec<Event>> {
+        self.lock_history().since(last_id, limit)
+    }
+
+    /// Stores the message and sends it to the connected clients.
+    pub fn publish(&self, message: Message) -> Result<()> {
+        // locked until the send, so the clients get the events in the id order
+        let history = self.lock_history();
+        let event = history.append(&message)?;
+        let _ = self.sender.send(event);
+        Ok(())
+    }
+
+    fn lock_history(&self) -> std::sync::MutexGuard<'_, History> {
+        self.history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn publish_price(&self, source: &str, answer: U256) -> Result<()> {
+        self.publish(Message::Price {
+            pair: self.pair.clone(),
+            source: source.to_string(),
+            price: web3util::format_units(answer, PRICE_DECIMALS),
+            answer: answer.to_string(),
+        })
+    }
+
+    pub fn publish_transaction(&self, receipt: &TransactionReceipt, reverted: bool) -> Result<()> {
+        self.publish(Message::Transaction {
+            pair: self.pair.clone(),
+            transaction_hash: format!("{:?}", receipt.transaction_hash),
+            block_number: receipt.block_number.map(|n| n.as_u64()),
+            status: if reverted { "reverted" } else { "mined" },
+        })
+    }
+
+    /// Publishes the `PriceChanged` events of the contract once they have
+    /// `confirmations` blocks on top, starting from the current head. The
+    /// blocking web3 calls of a poll run on the blocking threads of tokio.
+    pub async fn follow<T>(
+        self: Arc<Self>,
+        logger: slog::Logger,
+        oracle: Arc<PriceOracle<T>>,
+        confirmations: u64,
+        poll_interval: Duration,
+    ) where
+        T: Transport + Send + Sync + 'static,
+    {
+        let mut cursor = Cursor::default();
+        loop {
+            let (feed, oracle) = (self.clone(), oracle.clone());
+            let polled = tokio::task::spawn_blocking(move || {
+                let polled = feed.poll_confirmed(&oracle, confirmations, &mut cursor);
+                (cursor, polled)
+            })
+            .await;
+            match polled {
+                Ok((polled_cursor, polled)) => {
+                    cursor = polled_cursor;
+                    if let Err(e) = polled {
+                        error!(logger, "reading PriceChanged for the stream failed: {}", e);
+                    }
+                }
+                Err(e) => error!(logger, "reading PriceChanged for the stream failed: {}", e),
+            }
+            delay_for(poll_interval).await;
+        }
+    }
+
+    /// Publishes the confirmed logs after the cursor. A failed publish leaves
+    /// `next_block` at its block, the logs already published are skipped the
+    /// next time.
+    fn poll_confirmed<T: Transport>(
+        &self,
+        oracle: &PriceOracle<T>,
+        confirmations: u64,
+        cursor: &mut Cursor,
+    ) -> Result<()> {
+        let head = oracle.web3().eth().block_number().wait()?.as_u64();
+        let confirmed = match (head + 1).checked_sub(confirmations.max(1)) {
+            Some(confirmed) => confirmed,
+            None => return Ok(()),
+        };
+        let from_block = cursor.next_block.unwrap_or(confirmed + 1);
+        if from_block <= confirmed {
+            let filter = client::contract_events_filter(
+                oracle.address(),
+                vec![events::PriceChanged::topic()],
+                from_block.into(),
+                confirmed.into(),
+            );
+            for log in oracle.web3().eth().logs(filter).wait()? {
+                let block_number = log.block_number.map(|n| n.as_u64());
+                let position = (
+                    block_number.unwrap_or(from_block),
+                    log.log_index.unwrap_or_default(),
+                );
+                if cursor.last_log.map_or(false, |last| position <= last) {
+                    continue;
+                }
+
+                let round = Round::from_log(&log)?;
+                self.publish(Message::PriceChanged {
+                    pair: self.pair.clone(),
+                    round_id: round.round_id.to_string(),
+                    price: web3util::format_units(round.answer, PRICE_DECIMALS),
+                    answer: round.answer.to_string(),
+                    updated_at: round.updated_at.low_u64(),
+                    block_number,
+                    transaction_hash: log.transaction_hash.map(|hash| format!("{:?}", hash)),
+                })?;
+                cursor.last_log = Some(position);
+            }
+        }
+        cursor.next_block = Some(confirmed + 1);
+        Ok(())
+    }
+}
+
+/// History arguments of the feed shared by `server` and `service`, each
+/// process writes a history of its own at `default_history`.
+pub fn args(default_history: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("stream_history")
+            .env("PO_STREAM_HISTORY")
+            .long("stream_history")
+            .default_value(default_history)
+            .help("sqlite file of the streamed messages the /v1/events clients resume from"),
+        Arg::with_name("stream_history_size")
+            .env("PO_STREAM_HISTORY_SIZE")
+            .long("stream_history_size")
+            .default_value("10000")
+            .help("number of the last streamed messages kept in the history"),
+    ]
+}
+
+fn storage(path: &str, e: rusqlite::Error) -> Error {
+    Error::Storage(format!("{}: {}", path, e))
+}
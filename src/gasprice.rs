@@ -0,0 +1,363 @@
+use crate::args;
+use crate::error::{Error, Result};
+use crate::web3util;
+use clap::{Arg, ArgMatches};
+use serde::Deserialize;
+use web3::futures::Future;
+use web3::types::{Address, U256};
+use web3::Transport;
+
+/// How the gas price of outgoing transactions is chosen.
+#[derive(Debug, Clone)]
+pub enum GasStrategy {
+    /// `eth_gasPrice` suggestion of the node scaled by `multiplier`
+    Node { multiplier: f64 },
+    /// Fixed gas price in wei
+    Fixed(U256),
+    /// `percentile` of the priority fees paid in the last `blocks` blocks
+    /// on top of the next block base fee, taken from `eth_feeHistory`
+    Percentile { blocks: u64, percentile: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct GasConfig {
+    pub strategy: GasStrategy,
+    /// Updates are skipped and an alert is raised above this gas price
+    pub max_gas_price: Option<U256>,
+    /// Explicit gas limit, overrides the `eth_estimateGas` estimation
+    pub gas_limit: Option<U256>,
+    /// Safety margin in percent added on top of the estimation
+    pub gas_margin: u64,
+}
+
+impl Default for GasConfig {
+    /// Same as the defaults of the gas arguments.
+    fn default() -> Self {
+        GasConfig {
+            strategy: GasStrategy::Node { multiplier: 1.0 },
+            max_gas_price: None,
+            gas_limit: None,
+            gas_margin: 20,
+        }
+    }
+}
+
+impl GasConfig {
+    pub fn new(arg: &ArgMatches) -> Result<Self> {
+        let strategy = match arg.value_of("gas_strategy").unwrap_or("node") {
+            "fixed" => GasStrategy::Fixed(args::u256(arg, "gas_price")?),
+            "percentile" => GasStrategy::Percentile {
+                blocks: args::parse(arg, "gas_blocks")?,
+                percentile: args::parse(arg, "gas_percentile")?,
+            },
+            _ => GasStrategy::Node {
+                multiplier: match args::parse(arg, "gas_multiplier")? {
+                    multiplier if multiplier > 0.0 && f64::is_finite(multiplier) => multiplier,
+                    _ => {
+                        return Err(Error::invalid(
+                            "gas_multiplier",
+                            "must be a finite number above 0",
+                        ))
+                    }
+                },
+            },
+        };
+
+        Ok(GasConfig {
+            strategy,
+            max_gas_price: args::u256_opt(arg, "max_gas_price")?,
+            gas_limit: args::u256_opt(arg, "gas_limit")?,
+            gas_margin: args::parse(arg, "gas_margin")?,
+        })
+    }
+
+    /// Returns the explicit gas limit or the `eth_estimateGas` estimation for
+    /// the calldata with the safety margin on top.
+    pub fn gas_limit(
+        &self,
+        logger: &slog::Logger,
+        eth_client: &web3::Web3<impl Transport>,
+        from: Option<Address>,
+        to: Option<Address>,
+        data: &[u8],
+    ) -> Result<U256> {
+        if let Some(gas_limit) = self.gas_limit {
+            return Ok(gas_limit);
+        }
+
+        let mut req = serde_json::json!({ "data": format!("0x{}", hex::encode(data)) });
+        if let Some(from) = from {
+            req["from"] = serde_json::json!(from);
+        }
+        if let Some(to) = to {
+            req["to"] = serde_json::json!(to);
+        }
+
+        let value = eth_client
+            .transport()
+            .execute("eth_estimateGas", vec![req])
+            .wait()?;
+        let estimate: U256 =
+            serde_json::from_value(value).map_err(|e| web3::Error::Decoder(e.to_string()))?;
+        let gas_limit = estimate * U256::from(100 + self.gas_margin) / U256::from(100);
+
+        debug!(
+            logger,
+            "gas estimate {} with {}% margin is {}", estimate, self.gas_margin, gas_limit
+        );
+
+        Ok(gas_limit)
+    }
+
+    /// Returns the gas price for the next transaction according to the strategy.
+    /// Fails when the price is above `max_gas_price`.
+    pub fn gas_price(
+        &self,
+        logger: &slog::Logger,
+        eth_client: &web3::Web3<impl Transport>,
+    ) -> Result<U256> {
+        let gas_price = match self.strategy {
+            GasStrategy::Node { multiplier } => {
+                let suggested = eth_client.eth().gas_price().wait()?;
+                scale(suggested, multiplier)
+            }
+            GasStrategy::Fixed(gas_price) => gas_price,
+            GasStrategy::Percentile { blocks, percentile } => {
+                fee_history_price(eth_client, blocks, percentile)?
+            }
+        };
+
+        debug!(
+            logger,
+            "gas price {} chosen by {:?} strategy", gas_price, self.strategy
+        );
+
+        if let Some(max_gas_price) = self.max_gas_price {
+            if gas_price > max_gas_price {
+                crit!(
+                    logger,
+                    "ALERT: gas price {} is above the cap {}, tx is skipped",
+                    gas_price,
+                    max_gas_price
+                );
+                return Err(Error::GasPriceCap {
+                    gas_price,
+                    max_gas_price,
+                });
+            }
+        }
+
+        Ok(gas_price)
+    }
+}
+
+/// Prints the gas and the total cost of the tx which would be sent.
+pub fn print_dry_run(gas_limit: U256, gas_price: U256) {
+    let cost = gas_limit * gas_price;
+    println!("gas limit: {}", gas_limit);
+    println!("gas price: {} wei", gas_price);
+    println!(
+        "total cost: {} wei ({} ETH)",
+        cost,
+        web3util::format_units(cost, 18)
+    );
+}
+
+fn scale(value: U256, multiplier: f64) -> U256 {
+    let percent = (multiplier * 100.0).round() as u64;
+    value * U256::from(percent) / U256::from(100)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeHistory {
+    base_fee_per_gas: Vec<U256>,
+    reward: Option<Vec<Vec<U256>>>,
+}
+
+fn fee_history_price(
+    eth_client: &web3::Web3<impl Transport>,
+    blocks: u64,
+    percentile: f64,
+) -> Result<U256> {
+    let value = eth_client
+        .transport()
+        .execute("eth_feeHistory", fee_history_params(blocks, percentile))
+        .wait()?;
+    let history: FeeHistory =
+        serde_json::from_value(value).map_err(|e| web3::Error::Decoder(e.to_string()))?;
+    next_gas_price(history)
+}
+
+/// Parameters of `eth_feeHistory` for the percentile of the priority fees of
+/// the last `blocks` blocks.
+fn fee_history_params(blocks: u64, percentile: f64) -> Vec<serde_json::Value> {
+    vec![
+        serde_json::json!(format!("{:#x}", blocks)),
+        serde_json::json!("latest"),
+        serde_json::json!([percentile]),
+    ]
+}
+
+/// Base fee of the next block plus the median of the percentile priority fees
+/// of the blocks, the upper middle one for an even count.
+fn next_gas_price(history: FeeHistory) -> Result<U256> {
+    // the last base fee is the one of the next block
+    let base_fee = match history.base_fee_per_gas.last() {
+        Some(base_fee) => *base_fee,
+        None => {
+            return Err(web3::Error::InvalidResponse(
+                "eth_feeHistory returned no base fee".to_string(),
+            )
+            .into())
+        }
+    };
+
+    let mut rewards: Vec<U256> = history
+        .reward
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|r| r.first().cloned())
+        .collect();
+    rewards.sort();
+    let tip = match rewards.len() {
+        0 => U256::zero(),
+        n => rewards[n / 2],
+    };
+
+    Ok(base_fee + tip)
+}
+
+/// Gas price arguments shared by the subcommands sending transactions.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("gas_strategy")
+            .env("PO_GAS_STRATEGY")
+            .long("gas_strategy")
+            .possible_values(&["node", "fixed", "percentile"])
+            .default_value("node")
+            .help("gas price strategy"),
+        Arg::with_name("gas_multiplier")
+            .env("PO_GAS_MULTIPLIER")
+            .long("gas_multiplier")
+            .default_value("1.0")
+            .help("multiplier for the node gas price suggestion"),
+        Arg::with_name("gas_price")
+            .env("PO_GAS_PRICE")
+            .long("gas_price")
+            .help("gas price in wei for the fixed strategy"),
+        Arg::with_name("gas_percentile")
+            .env("PO_GAS_PERCENTILE")
+            .long("gas_percentile")
+            .default_value("50")
+            .help("percentile of the recent priority fees for the percentile strategy"),
+        Arg::with_name("gas_blocks")
+            .env("PO_GAS_BLOCKS")
+            .long("gas_blocks")
+            .default_value("20")
+            .help("number of recent blocks for the percentile strategy"),
+        Arg::with_name("gas_margin")
+            .env("PO_GAS_MARGIN")
+            .long("gas_margin")
+            .default_value("20")
+            .help("safety margin in percent on top of the gas estimation"),
+        Arg::with_name("max_gas_price")
+            .env("PO_MAX_GAS_PRICE")
+            .long("max_gas_price")
+            .help("gas price in wei above which the tx is skipped"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::App;
+
+    fn history(value: serde_json::Value) -> FeeHistory {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn strategy(flags: &[&str]) -> Result<GasStrategy> {
+        let matches = App::new("test")
+            .args(&args())
+            .get_matches_from(std::iter::once("test").chain(flags.iter().cloned()));
+        GasConfig::new(&matches).map(|config| config.strategy)
+    }
+
+    #[test]
+    fn asks_for_the_percentile_of_the_last_blocks() {
+        assert_eq!(
+            fee_history_params(20, 50.0),
+            vec![
+                serde_json::json!("0x14"),
+                serde_json::json!("latest"),
+                serde_json::json!([50.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_the_median_tip_to_the_next_base_fee() {
+        let price = next_gas_price(history(serde_json::json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x64", "0x6e", "0x78", "0xc8"],
+            "gasUsedRatio": [0.5, 0.9, 0.1],
+            "reward": [["0x5"], ["0x1"], ["0x3"]],
+        })))
+        .unwrap();
+        assert_eq!(price, U256::from(200 + 3));
+    }
+
+    #[test]
+    fn takes_the_upper_middle_tip_of_an_even_count() {
+        let price = next_gas_price(history(serde_json::json!({
+            "baseFeePerGas": ["0x64", "0x64"],
+            "reward": [["0x4"], ["0x1"], ["0x8"], ["0x2"]],
+        })))
+        .unwrap();
+        assert_eq!(price, U256::from(100 + 4));
+    }
+
+    #[test]
+    fn skips_the_blocks_without_rewards() {
+        let price = next_gas_price(history(serde_json::json!({
+            "baseFeePerGas": ["0x64", "0x64"],
+            "reward": [[], ["0x7"], []],
+        })))
+        .unwrap();
+        assert_eq!(price, U256::from(100 + 7));
+
+        let price = next_gas_price(history(serde_json::json!({
+            "baseFeePerGas": ["0x64"],
+        })))
+        .unwrap();
+        assert_eq!(price, U256::from(100));
+    }
+
+    #[test]
+    fn fails_without_a_base_fee() {
+        assert!(next_gas_price(history(serde_json::json!({
+            "baseFeePerGas": [],
+            "reward": [["0x1"]],
+        })))
+        .is_err());
+    }
+
+    #[test]
+    fn scales_by_the_multiplier_in_percent() {
+        assert_eq!(scale(U256::from(1000), 1.0), U256::from(1000));
+        assert_eq!(scale(U256::from(1000), 1.25), U256::from(1250));
+        assert_eq!(scale(U256::from(1000), 0.125), U256::from(130));
+    }
+
+    #[test]
+    fn rejects_a_multiplier_which_is_not_above_zero() {
+        for multiplier in &["0", "-1", "NaN", "inf"] {
+            assert!(strategy(&[&format!("--gas_multiplier={}", multiplier)]).is_err());
+        }
+        match strategy(&["--gas_multiplier", "1.5"]) {
+            Ok(GasStrategy::Node { multiplier }) => assert_eq!(multiplier, 1.5),
+            _ => panic!("1.5 is rejected"),
+        }
+    }
+}
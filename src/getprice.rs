@@ -0,0 +1,65 @@
+use crate::cli;
+use clap::ArgMatches;
+use priceoracle::client::{PriceOracle, PRICE_DECIMALS};
+use priceoracle::error::Result;
+use priceoracle::gasprice::GasConfig;
+use priceoracle::web3util;
+use web3::types::Address;
+use web3::Transport;
+
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(ws), &config)
+}
+
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(http), &config)
+}
+
+fn run(logger: &slog::Logger, web3: web3::Web3<impl Transport>, config: &Config) -> Result<()> {
+    debug!(
+        logger,
+        "getprice called to the {} network with contractaddr {:?}",
+        config.net,
+        config.contract_addr
+    );
+
+    let oracle = PriceOracle::new(web3, config.contract_addr, None, GasConfig::default());
+    match oracle.latest_round()? {
+        Some(round) => {
+            println!(
+                "price:   {} BTC per ETH",
+                web3util::format_units(round.answer, PRICE_DECIMALS)
+            );
+            println!("raw:     {}", round.answer);
+            println!("round:   {}", round.round_id);
+            println!("updated: {} ({}s ago)", round.updated_at, round.age());
+        }
+        None => println!("price was never set"),
+    }
+
+    Ok(())
+}
+
+struct Config {
+    contract_addr: Address,
+    net: String,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            contract_addr: cli::address(arg, "contractaddr")?,
+            net: cli::required(arg, "net")?.to_string(),
+        })
+    }
+}
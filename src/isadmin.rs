@@ -0,0 +1,56 @@
+use crate::cli;
+use clap::ArgMatches;
+use priceoracle::client::PriceOracle;
+use priceoracle::error::Result;
+use priceoracle::gasprice::GasConfig;
+use web3::types::Address;
+use web3::Transport;
+
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(ws), &config)
+}
+
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(http), &config)
+}
+
+fn run(logger: &slog::Logger, web3: web3::Web3<impl Transport>, config: &Config) -> Result<()> {
+    debug!(
+        logger,
+        "isadmin called to the {} network for {:?} and contractaddr {:?}",
+        config.net,
+        config.admin,
+        config.contract_addr
+    );
+
+    let oracle = PriceOracle::new(web3, config.contract_addr, None, GasConfig::default());
+    println!("{}", oracle.is_admin(config.admin)?);
+
+    Ok(())
+}
+
+struct Config {
+    contract_addr: Address,
+    admin: Address,
+    net: String,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            contract_addr: cli::address(arg, "contractaddr")?,
+            admin: cli::address(arg, "admin")?,
+            net: cli::required(arg, "net")?.to_string(),
+        })
+    }
+}
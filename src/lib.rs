@@ -0,0 +1,71 @@
+//! Ethereum price oracle for the ETH-BTC pair.
+//!
+//! The [`PriceOracle`] client reads and updates the `PriceOracle` contract
+//! from `src/contract/priceoracle.sol`, the [`PriceSource`] implementations
+//! fetch the price from external apis and the [`Service`] pushes the fetched
+//! price to the contract.
+//!
+//! ```no_run
+//! use priceoracle::{CryptoCompare, GasConfig, GasStrategy, PriceOracle, Service};
+//! use std::time::Duration;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> priceoracle::Result<()> {
+//! # let logger = slog::Logger::root(slog::Discard, slog::o!());
+//! let (eloop, http) = web3::transports::Http::new("http://localhost:8545")?;
+//! eloop.into_remote();
+//!
+//! let gas = GasConfig {
+//!     strategy: GasStrategy::Node { multiplier: 1.0 },
+//!     max_gas_price: None,
+//!     gas_limit: None,
+//!     gas_margin: 20,
+//! };
+//! let address = "0x0000000000000000000000000000000000000000".parse().unwrap();
+//! let oracle = PriceOracle::new(web3::Web3::new(http), address, None, gas);
+//! println!("current price: {:?}", oracle.price()?);
+//!
+//! let source = CryptoCompare::new("https://min-api.cryptocompare.com", "api key");
+//! Service::new(source, oracle, Duration::from_secs(5))
+//!     .run(&logger)
+//!     .await
+//! # }
+//! ```
+
+#[macro_use]
+extern crate slog;
+
+pub mod admin;
+pub(crate) mod args;
+pub mod bindings;
+pub mod ccip;
+pub mod checkpoint;
+pub mod client;
+pub mod cors;
+pub mod deploy;
+pub mod election;
+pub mod error;
+pub mod eventread;
+pub mod feed;
+pub mod gasprice;
+pub mod notify;
+mod openapi;
+pub mod peer;
+pub mod quota;
+pub mod record;
+pub mod reorg;
+pub mod report;
+pub mod server;
+pub mod service;
+pub mod sink;
+pub mod source;
+pub mod tls;
+pub mod updateprice;
+pub mod web3util;
+
+pub use crate::client::{PriceOracle, Round, Signer};
+pub use crate::error::{Error, Result};
+pub use crate::gasprice::{GasConfig, GasStrategy};
+pub use crate::openapi::spec as openapi_spec;
+pub use crate::service::{Quorum, Service};
+pub use crate::source::{CryptoCompare, PriceSource};
@@ -2,22 +2,24 @@ extern crate clap;
 #[macro_use]
 extern crate slog;
 extern crate slog_term;
-#[macro_use]
-extern crate rust_embed;
-extern crate hex;
+
+mod cli;
+mod getprice;
+mod isadmin;
+mod setadmin;
+mod setreporter;
+mod status;
+mod webhookrecv;
 
 use slog::Drain;
 
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
+use priceoracle::{
+    admin, ccip, cors, deploy, eventread, feed, gasprice, notify, quota, server, service, tls,
+    updateprice,
+};
 use std::process;
 
-mod deploy;
-mod eventread;
-mod server;
-mod service;
-mod updateprice;
-mod web3util;
-
 fn main() {
     let matches = build_app_get_matches();
 
@@ -27,7 +29,7 @@ fn main() {
     }
 }
 
-fn run(matches: ArgMatches<'static>) -> Result<(), Box<dyn std::error::Error>> {
+fn run(matches: ArgMatches<'static>) -> priceoracle::Result<()> {
     let min_log_level = match matches.occurrences_of("verbose") {
         0 => slog::Level::Info,
         1 => slog::Level::Debug,
@@ -50,7 +52,7 @@ fn run(matches: ArgMatches<'static>) -> Result<(), Box<dyn std::error::Error>> {
         ("server", Some(server_matches)) => server::run(logger, server_matches),
         ("service", Some(service_matches)) => service::run(logger, service_matches),
         ("deploy", Some(deploy_matches)) => {
-            let transport = deploy_matches.value_of("transport").unwrap();
+            let transport = cli::required(deploy_matches, "transport")?;
             if transport == "http" {
                 deploy::run_with_http(logger, deploy_matches)
             } else {
@@ -58,14 +60,64 @@ fn run(matches: ArgMatches<'static>) -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         ("updateprice", Some(up_matches)) => {
-            let transport = up_matches.value_of("transport").unwrap();
+            let transport = cli::required(up_matches, "transport")?;
             if transport == "http" {
                 updateprice::run_with_http(logger, up_matches)
             } else {
                 updateprice::run_with_ws(logger, up_matches)
             }
         }
-        ("eventread", Some(ev_matches)) => eventread::run_with_ws(logger, ev_matches),
+        ("setadmin", Some(sa_matches)) => {
+            let transport = cli::required(sa_matches, "transport")?;
+            if transport == "http" {
+                setadmin::run_with_http(logger, sa_matches)
+            } else {
+                setadmin::run_with_ws(logger, sa_matches)
+            }
+        }
+        ("setreporter", Some(sr_matches)) => {
+            let transport = cli::required(sr_matches, "transport")?;
+            if transport == "http" {
+                setreporter::run_with_http(logger, sr_matches)
+            } else {
+                setreporter::run_with_ws(logger, sr_matches)
+            }
+        }
+        ("isadmin", Some(ia_matches)) => {
+            let transport = cli::required(ia_matches, "transport")?;
+            if transport == "http" {
+                isadmin::run_with_http(logger, ia_matches)
+            } else {
+                isadmin::run_with_ws(logger, ia_matches)
+            }
+        }
+        ("getprice", Some(gp_matches)) => {
+            let transport = cli::required(gp_matches, "transport")?;
+            if transport == "http" {
+                getprice::run_with_http(logger, gp_matches)
+            } else {
+                getprice::run_with_ws(logger, gp_matches)
+            }
+        }
+        ("status", Some(st_matches)) => {
+            let transport = cli::required(st_matches, "transport")?;
+            if transport == "http" {
+                status::run_with_http(logger, st_matches)
+            } else {
+                status::run_with_ws(logger, st_matches)
+            }
+        }
+        ("eventread", Some(ev_matches)) => {
+            let transport = cli::required(ev_matches, "transport")?;
+            if transport == "http" {
+                eventread::run_with_http(logger, ev_matches)
+            } else {
+                eventread::run_with_ws(logger, ev_matches)
+            }
+        }
+        ("webhookrecv", Some(wr_matches)) => webhookrecv::run(logger, wr_matches),
+        ("apikey", Some(ak_matches)) => quota::run(ak_matches),
+        ("openapi", Some(_)) => print_openapi(),
         ("", None) => {
             error!(logger, "no subcommand was used");
             Ok(())
@@ -74,6 +126,15 @@ fn run(matches: ArgMatches<'static>) -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+/// Prints the OpenAPI document for the client generators.
+fn print_openapi() -> priceoracle::Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&priceoracle::openapi_spec()).unwrap_or_default()
+    );
+    Ok(())
+}
+
 pub fn build_app_get_matches() -> ArgMatches<'static> {
     App::new("priceoracle")
         .version("0.0.1")
@@ -95,7 +156,41 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                         .short("b")
                         .long("bind")
                         .help("address:port"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .env("PO_ETHEREUM_CONFIRMATIONS")
+                        .long("confirmations")
+                        .default_value("12")
+                        .help("blocks on top of a PriceChanged event before it is pushed to /v1/stream"),
+                )
+                .arg(
+                    Arg::with_name("poll_interval")
+                        .env("PO_SERVER_POLL_INTERVAL")
+                        .long("poll_interval")
+                        .default_value("5")
+                        .help("interval in seconds between the reads of the PriceChanged events"),
+                )
+                .args(&feed::args("stream.db"))
+                .args(&quota::args())
+                .args(&tls::args())
+                .args(&cors::args())
+                .args(&ccip::args()),
         )
         .subcommand(
             SubCommand::with_name("service")
@@ -159,7 +254,108 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                         .env("PO_ETHEREUM_CHAIN_ID")
                         .long("chain_id")
                         .help("chain id for tx signing"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("reporter_bind")
+                        .env("PO_REPORTER_BIND")
+                        .long("reporter_bind")
+                        .help("address:port of the reporter endpoints, enables the quorum mode"),
+                )
+                .arg(
+                    Arg::with_name("peers")
+                        .env("PO_REPORTER_PEERS")
+                        .long("peers")
+                        .help("comma separated urls of the reporter endpoints of the peers"),
+                )
+                .arg(
+                    Arg::with_name("leader")
+                        .long("leader")
+                        .help("aggregates the observations of the peers and submits the reports"),
+                )
+                .arg(
+                    Arg::with_name("max_deviation")
+                        .env("PO_REPORTER_MAX_DEVIATION")
+                        .long("max_deviation")
+                        .default_value("1.0")
+                        .help("max deviation in percent of the signed answer from the own observation"),
+                )
+                .arg(
+                    Arg::with_name("node_bind")
+                        .env("PO_NODE_BIND")
+                        .long("node_bind")
+                        .help("address:port of the node endpoints, enables the quote exchange"),
+                )
+                .arg(
+                    Arg::with_name("node_peers")
+                        .env("PO_NODE_PEERS")
+                        .long("node_peers")
+                        .help("comma separated urls of the node endpoints of the peers"),
+                )
+                .arg(
+                    Arg::with_name("trusted_nodes")
+                        .env("PO_TRUSTED_NODES")
+                        .long("trusted_nodes")
+                        .help("comma separated addresses of the nodes whose quotes are accepted"),
+                )
+                .arg(
+                    Arg::with_name("quote_max_age")
+                        .env("PO_QUOTE_MAX_AGE")
+                        .long("quote_max_age")
+                        .default_value("60")
+                        .help("quotes older than this many seconds are ignored"),
+                )
+                .arg(
+                    Arg::with_name("max_divergence")
+                        .env("PO_NODE_MAX_DIVERGENCE")
+                        .long("max_divergence")
+                        .default_value("2.0")
+                        .help("max deviation in percent of a node price from the shared median"),
+                )
+                .arg(
+                    Arg::with_name("lease")
+                        .env("PO_LEASE")
+                        .long("lease")
+                        .help("file:///path or redis://host:port/key of the leader lease, enables the election"),
+                )
+                .arg(
+                    Arg::with_name("lease_ttl")
+                        .env("PO_LEASE_TTL")
+                        .long("lease_ttl")
+                        .default_value("30")
+                        .help("seconds the leader lease is valid without renewal"),
+                )
+                .arg(
+                    Arg::with_name("instance_id")
+                        .env("PO_INSTANCE_ID")
+                        .long("instance_id")
+                        .help("unique name of the instance in the election, hostname-pid by default"),
+                )
+                .arg(
+                    Arg::with_name("stream_bind")
+                        .env("PO_STREAM_BIND")
+                        .long("stream_bind")
+                        .help("address:port of the /v1/stream and /v1/events feed of the prices, the txs and the PriceChanged events"),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .env("PO_ETHEREUM_CONFIRMATIONS")
+                        .long("confirmations")
+                        .default_value("12")
+                        .help("blocks on top of a PriceChanged event before it is pushed to /v1/stream"),
+                )
+                .arg(
+                    Arg::with_name("alarm_deviation")
+                        .env("PO_ALARM_DEVIATION")
+                        .long("alarm_deviation")
+                        .help("change of the price in percent between two polls that is notified"),
+                )
+                .args(&admin::args())
+                .args(&feed::args("service_stream.db"))
+                .args(&quota::args())
+                .args(&tls::args())
+                .args(&cors::args())
+                .args(&notify::args())
+                .args(&gasprice::args()),
         )
         .subcommand(
             SubCommand::with_name("deploy")
@@ -202,7 +398,34 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                         .env("PO_ETHEREUM_CHAIN_ID")
                         .long("chain_id")
                         .help("chain id for sign tx"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("description")
+                        .env("PO_FEED_DESCRIPTION")
+                        .long("description")
+                        .default_value("ETH / BTC")
+                        .help("feed description returned by AggregatorV3Interface.description()"),
+                )
+                .arg(
+                    Arg::with_name("ccip_urls")
+                        .env("PO_CCIP_URLS")
+                        .long("ccip_urls")
+                        .requires("ccip_signer")
+                        .help("comma separated CCIP-Read gateway urls, deploys OffchainPriceOracle of the description instead"),
+                )
+                .arg(
+                    Arg::with_name("ccip_signer")
+                        .env("PO_CCIP_SIGNER")
+                        .long("ccip_signer")
+                        .requires("ccip_urls")
+                        .help("address of the --ccip_private_key of the gateway"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("prints the gas estimate and the total cost without sending the tx"),
+                )
+                .args(&gasprice::args()),
         )
         .subcommand(
             SubCommand::with_name("updateprice")
@@ -229,6 +452,11 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                         .long("newprice")
                         .help("set new price in uint256"),
                 )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("prints the gas estimate and the total cost without sending the tx"),
+                )
                 .arg(
                     Arg::with_name("contractaddr")
                         .required(true)
@@ -260,6 +488,276 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                         .env("PO_ETHEREUM_CHAIN_ID")
                         .long("chain_id")
                         .help("chain id for tx signing"),
+                )
+                .args(&gasprice::args()),
+        )
+        .subcommand(
+            SubCommand::with_name("setadmin")
+                .about("grants or revokes the admin rights in the contract")
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .required(true)
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .help("ws or http"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                )
+                .arg(
+                    Arg::with_name("admin")
+                        .required(true)
+                        .takes_value(true)
+                        .long("admin")
+                        .help("address to grant or revoke the admin rights"),
+                )
+                .arg(
+                    Arg::with_name("grant")
+                        .long("grant")
+                        .help("allows the admin to update the price"),
+                )
+                .arg(
+                    Arg::with_name("revoke")
+                        .long("revoke")
+                        .help("disallows the admin to update the price"),
+                )
+                .group(
+                    ArgGroup::with_name("action")
+                        .args(&["grant", "revoke"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .env("PO_ETHEREUM_CONFIRMATIONS")
+                        .long("confirmations")
+                        .default_value("1")
+                        .help("number of blocks to wait for after the tx is mined"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("prints the gas estimate and the total cost without sending the tx"),
+                )
+                .arg(
+                    Arg::with_name("gas_limit")
+                        .env("PO_ETHEREUM_GAS_LIMIT")
+                        .long("gas_limit")
+                        .help("gas limit for tx"),
+                )
+                .arg(
+                    Arg::with_name("from_addr")
+                        .env("PO_ETHEREUM_FROM_ADDR")
+                        .long("from_addr")
+                        .help("admin of the contract address"),
+                )
+                .arg(
+                    Arg::with_name("private_key")
+                        .env("PO_ETHEREUM_PRIVATE_KEY")
+                        .long("private_key")
+                        .help("private key for tx signing"),
+                )
+                .arg(
+                    Arg::with_name("chain_id")
+                        .env("PO_ETHEREUM_CHAIN_ID")
+                        .long("chain_id")
+                        .help("chain id for tx signing"),
+                )
+                .args(&gasprice::args()),
+        )
+        .subcommand(
+            SubCommand::with_name("setreporter")
+                .about("registers the reporters and sets the quorum of the reports")
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .required(true)
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .help("ws or http"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                )
+                .arg(
+                    Arg::with_name("reporter")
+                        .takes_value(true)
+                        .long("reporter")
+                        .requires("action")
+                        .help("address to grant or revoke the reporter rights"),
+                )
+                .arg(
+                    Arg::with_name("grant")
+                        .long("grant")
+                        .help("allows the reporter to sign the reports"),
+                )
+                .arg(
+                    Arg::with_name("revoke")
+                        .long("revoke")
+                        .help("disallows the reporter to sign the reports"),
+                )
+                .group(ArgGroup::with_name("action").args(&["grant", "revoke"]))
+                .arg(
+                    Arg::with_name("quorum")
+                        .takes_value(true)
+                        .long("quorum")
+                        .help("number of reporter signatures a report needs, 0 to update by the admins"),
+                )
+                .group(
+                    ArgGroup::with_name("target")
+                        .args(&["reporter", "quorum"])
+                        .multiple(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .env("PO_ETHEREUM_CONFIRMATIONS")
+                        .long("confirmations")
+                        .default_value("1")
+                        .help("number of blocks to wait for after the tx is mined"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("prints the gas estimate and the total cost without sending the tx"),
+                )
+                .arg(
+                    Arg::with_name("gas_limit")
+                        .env("PO_ETHEREUM_GAS_LIMIT")
+                        .long("gas_limit")
+                        .help("gas limit for tx"),
+                )
+                .arg(
+                    Arg::with_name("from_addr")
+                        .env("PO_ETHEREUM_FROM_ADDR")
+                        .long("from_addr")
+                        .help("admin of the contract address"),
+                )
+                .arg(
+                    Arg::with_name("private_key")
+                        .env("PO_ETHEREUM_PRIVATE_KEY")
+                        .long("private_key")
+                        .help("private key for tx signing"),
+                )
+                .arg(
+                    Arg::with_name("chain_id")
+                        .env("PO_ETHEREUM_CHAIN_ID")
+                        .long("chain_id")
+                        .help("chain id for tx signing"),
+                )
+                .args(&gasprice::args()),
+        )
+        .subcommand(
+            SubCommand::with_name("isadmin")
+                .about("checks the admin rights in the contract")
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .required(true)
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .help("ws or http"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                )
+                .arg(
+                    Arg::with_name("admin")
+                        .required(true)
+                        .takes_value(true)
+                        .long("admin")
+                        .help("address to check"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("getprice")
+                .about("prints the current price in the contract")
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .required(true)
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .help("ws or http"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("prints the state of the contract and the reporter")
+                .arg(
+                    Arg::with_name("net")
+                        .required(true)
+                        .env("PO_ETHEREUM_NETWORK")
+                        .long("net")
+                        .help("mainnet or testnet"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .required(true)
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .help("ws or http"),
+                )
+                .arg(
+                    Arg::with_name("contractaddr")
+                        .required(true)
+                        .env("PO_CONTRACT_ADDRESS")
+                        .short("ca")
+                        .long("contractaddr")
+                        .help("address of the contract in the Ethereum network"),
+                )
+                .arg(
+                    Arg::with_name("from_addr")
+                        .env("PO_ETHEREUM_FROM_ADDR")
+                        .long("from_addr")
+                        .help("reporter address, the first node account by default"),
                 ),
         )
         .subcommand(
@@ -282,12 +780,135 @@ pub fn build_app_get_matches() -> ArgMatches<'static> {
                 )
                 .arg(
                     Arg::with_name("blocknum")
-                        .required(true)
                         .env("PO_ETHEREUM_BLOCKNUM")
                         .short("bn")
                         .long("blocknum")
-                        .help(" blocknum from which we start parsing ethereum logs"),
+                        .default_value("0")
+                        .help("blocknum from which we start parsing ethereum logs without a checkpoint"),
+                )
+                .arg(
+                    Arg::with_name("checkpoint")
+                        .env("PO_EVENTREAD_CHECKPOINT")
+                        .long("checkpoint")
+                        .default_value("eventread.checkpoint")
+                        .help("file of the last processed block, the reading resumes after it"),
+                )
+                .arg(
+                    Arg::with_name("chunk_size")
+                        .env("PO_EVENTREAD_CHUNK_SIZE")
+                        .long("chunk_size")
+                        .default_value("5000")
+                        .help("max blocks of one eth_getLogs query of the backfill"),
+                )
+                .arg(
+                    Arg::with_name("transport")
+                        .env("PO_ETHEREUM_TRANSPORT")
+                        .long("transport")
+                        .default_value("ws")
+                        .help("ws to subscribe or http to poll eth_getLogs"),
+                )
+                .arg(
+                    Arg::with_name("poll_interval")
+                        .env("PO_EVENTREAD_POLL_INTERVAL")
+                        .long("poll_interval")
+                        .default_value("5")
+                        .help("interval in seconds between the polls of the http transport"),
+                )
+                .arg(
+                    Arg::with_name("sink")
+                        .env("PO_EVENTREAD_SINKS")
+                        .long("sink")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .use_delimiter(true)
+                        .default_value("stdout")
+                        .help("stdout, csv:<path>, sqlite:<path> or webhook:<url>, repeat for several sinks"),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .env("PO_ETHEREUM_CONFIRMATIONS")
+                        .long("confirmations")
+                        .default_value("12")
+                        .help("blocks on top of the event block before the event is printed"),
+                )
+                .arg(
+                    Arg::with_name("reorg_window")
+                        .env("PO_REORG_WINDOW")
+                        .long("reorg_window")
+                        .default_value("128")
+                        .help("recent blocks kept to retract the events of a reorg"),
+                )
+                .args(&notify::args()),
+        )
+        .subcommand(
+            SubCommand::with_name("webhookrecv")
+                .about("receives and prints the webhook notifications")
+                .arg(
+                    Arg::with_name("bind")
+                        .required(true)
+                        .env("PO_WEBHOOK_BIND")
+                        .short("b")
+                        .long("bind")
+                        .help("address:port"),
+                )
+                .arg(
+                    Arg::with_name("notify_secret")
+                        .required(true)
+                        .env("PO_NOTIFY_SECRET")
+                        .long("notify_secret")
+                        .help("HMAC-SHA256 key the signatures are verified with"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apikey")
+                .about("manages the API keys of the server")
+                .arg(
+                    Arg::with_name("api_keys")
+                        .env("PO_API_KEYS")
+                        .long("api_keys")
+                        .default_value("api_keys.db")
+                        .help("sqlite file of the API keys"),
+                )
+                .arg(
+                    Arg::with_name("add")
+                        .takes_value(true)
+                        .long("add")
+                        .help("creates a key for the named client and prints it"),
+                )
+                .arg(
+                    Arg::with_name("revoke")
+                        .takes_value(true)
+                        .long("revoke")
+                        .help("revokes the key of the named client"),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .long("list")
+                        .help("lists the clients and their limits"),
+                )
+                .group(
+                    ArgGroup::with_name("action")
+                        .args(&["add", "revoke", "list"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("rate")
+                        .takes_value(true)
+                        .long("rate")
+                        .requires("add")
+                        .help("requests per second of the key instead of --key_rate of the server"),
+                )
+                .arg(
+                    Arg::with_name("burst")
+                        .takes_value(true)
+                        .long("burst")
+                        .requires("add")
+                        .help("requests the key can make at once instead of --key_burst of the server"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("openapi")
+                .about("prints the OpenAPI document of the http endpoints"),
+        )
         .get_matches()
 }
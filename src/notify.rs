@@ -0,0 +1,611 @@
+//! Outbound webhook notifications of the service and `eventread`.
+//!
+//! A notification is stored in the queue directory first and delivered by a
+//! background thread, so it survives restarts and receiver outages. Every
+//! request carries the `X-Priceoracle-Signature: sha256=<hex>` HMAC of the body
+//! with the shared secret, failed deliveries are retried with exponential
+//! backoff in the order they were queued. A notification the receiver rejects
+//! or which runs out of attempts or age is moved to the dead-letter file, so it
+//! doesn't hold back the later ones.
+
+use crate::args;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use hmac::{Hmac, Mac, NewMac};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::timeout;
+
+pub const SIGNATURE_HEADER: &str = "x-priceoracle-signature";
+
+/// Max seconds between the delivery attempts of a notification.
+const MAX_BACKOFF: u64 = 300;
+
+/// Max time the receiver has to answer a delivery, a slower answer is a
+/// transient failure.
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// `PriceChanged` record of `eventread`, confirmed or retracted
+    PriceChanged,
+    /// Update tx failed or was reverted
+    UpdateFailed,
+    /// Price source is unreachable
+    SourceDown,
+    /// Price moved more than the alarm threshold or a peer diverged
+    Deviation,
+}
+
+/// Body of the webhook request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    /// Unique id, receivers drop the repeated deliveries by it
+    pub id: String,
+    pub kind: Kind,
+    /// Unix time the notification was queued at
+    pub created_at: u64,
+    pub data: serde_json::Value,
+}
+
+/// Notification given up on, a line of the dead-letter file.
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    notification: &'a Notification,
+    attempts: u32,
+    /// Unix time it was given up at
+    failed_at: u64,
+    error: &'a str,
+}
+
+/// Failed delivery attempt.
+#[derive(Debug)]
+enum Failure {
+    /// Network error, timeout, 5xx, 408 or 429, the delivery is retried
+    Transient(String),
+    /// Any other rejection of the receiver, a retry gets the same answer
+    Permanent(String),
+}
+
+/// Queued notification with its delivery state.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    notification: Notification,
+    attempts: u32,
+    /// Unix time of the next attempt
+    next_attempt: u64,
+}
+
+/// HMAC-SHA256 of the body in hex.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).expect("hmac accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks the `sha256=<hex>` signature header of the body in constant time.
+pub fn verify(secret: &[u8], body: &[u8], header: &str) -> bool {
+    let signature = match header
+        .strip_prefix("sha256=")
+        .and_then(|sig| hex::decode(sig).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).expect("hmac accepts keys of any size");
+    mac.update(body);
+    mac.verify(&signature).is_ok()
+}
+
+/// Persistent queue of the notifications to the webhook url.
+pub struct Notifier {
+    url: hyper::Uri,
+    secret: Vec<u8>,
+    dir: PathBuf,
+    /// JSON lines of the notifications given up on
+    dead_letter: PathBuf,
+    max_attempts: u32,
+    /// Seconds since the queueing after which a failed delivery is given up
+    max_age: u64,
+    seq: AtomicU64,
+}
+
+impl Notifier {
+    pub fn new(
+        url: &str,
+        secret: &str,
+        dir: impl Into<PathBuf>,
+        dead_letter: impl Into<PathBuf>,
+        max_attempts: u32,
+        max_age: Duration,
+    ) -> Result<Self> {
+        let url = url.parse().map_err(|e| Error::invalid("notify_url", e))?;
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| storage(&dir, e))?;
+
+        Ok(Notifier {
+            url,
+            secret: secret.as_bytes().to_vec(),
+            dir,
+            dead_letter: dead_letter.into(),
+            max_attempts,
+            max_age: max_age.as_secs(),
+            seq: AtomicU64::new(0),
+        })
+    }
+
+    /// Notifier of `--notify_url`, `None` when it is not set.
+    pub fn from_args(arg: &ArgMatches) -> Result<Option<Self>> {
+        match arg.value_of("notify_url") {
+            Some(url) => Ok(Some(Notifier::new(
+                url,
+                args::required(arg, "notify_secret")?,
+                args::required(arg, "notify_queue")?,
+                args::required(arg, "notify_dead_letter")?,
+                args::parse(arg, "notify_max_attempts")?,
+                Duration::from_secs(args::parse(arg, "notify_max_age")?),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the notification in the queue, the delivery happens in
+    /// [`Notifier::spawn`].
+    pub fn notify(&self, kind: Kind, data: serde_json::Value) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // the name orders the queue and is unique within the process
+        let id = format!(
+            "{:020}-{:06}-{:010}",
+            now.as_nanos(),
+            std::process::id(),
+            self.seq.fetch_add(1, Ordering::SeqCst)
+        );
+        let entry = Entry {
+            notification: Notification {
+                id: id.clone(),
+                kind,
+                created_at: now.as_secs(),
+                data,
+            },
+            attempts: 0,
+            next_attempt: 0,
+        };
+        self.store(&self.dir.join(format!("{}.json", id)), &entry)
+    }
+
+    fn store(&self, path: &Path, entry: &Entry) -> Result<()> {
+        let body = serde_json::to_vec(entry).map_err(|e| Error::Storage(e.to_string()))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, body)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| storage(path, e))
+    }
+
+    /// Queued notifications in the delivery order.
+    fn queued(&self) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .map_err(|e| storage(&self.dir, e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Delivers the due notifications until the first transient failure, which
+    /// keeps the order for the receiver. The failures which are permanent or
+    /// over `max_attempts` or `max_age` go to the dead-letter file instead.
+    async fn deliver(
+        &self,
+        logger: &slog::Logger,
+        client: &Client<HttpsConnector<HttpConnector>>,
+    ) -> Result<()> {
+        for path in self.queued()? {
+            let content = fs::read(&path).map_err(|e| storage(&path, e))?;
+            let mut entry: Entry = match serde_json::from_slice(&content) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    error!(logger, "dropping corrupted notification {:?}: {}", path, e);
+                    fs::remove_file(&path).map_err(|e| storage(&path, e))?;
+                    continue;
+                }
+            };
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            if entry.next_attempt > now {
+                return Ok(());
+            }
+
+            let e = match self.post(client, &entry.notification).await {
+                Ok(()) => {
+                    debug!(logger, "notification {} delivered", entry.notification.id);
+                    fs::remove_file(&path).map_err(|e| storage(&path, e))?;
+                    continue;
+                }
+                Err(e) => e,
+            };
+            entry.attempts += 1;
+            let expired = entry.attempts >= self.max_attempts
+                || now.saturating_sub(entry.notification.created_at) >= self.max_age;
+            match e {
+                Failure::Transient(e) if !expired => {
+                    let backoff = MAX_BACKOFF.min(1 << entry.attempts.min(16));
+                    entry.next_attempt = now + backoff;
+                    warn!(
+                        logger,
+                        "notification {} attempt {} failed: {}, retry in {}s",
+                        entry.notification.id,
+                        entry.attempts,
+                        e,
+                        backoff
+                    );
+                    return self.store(&path, &entry);
+                }
+                Failure::Transient(e) | Failure::Permanent(e) => {
+                    error!(
+                        logger,
+                        "notification {} is given up after {} attempts: {}",
+                        entry.notification.id,
+                        entry.attempts,
+                        e
+                    );
+                    self.bury(&entry, now, &e)?;
+                    fs::remove_file(&path).map_err(|e| storage(&path, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the notification to the dead-letter file.
+    fn bury(&self, entry: &Entry, now: u64, error: &str) -> Result<()> {
+        let dead = DeadLetter {
+            notification: &entry.notification,
+            attempts: entry.attempts,
+            failed_at: now,
+            error,
+        };
+        let mut line = serde_json::to_vec(&dead).map_err(|e| Error::Storage(e.to_string()))?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|e| storage(&self.dead_letter, e))
+    }
+
+    async fn post(
+        &self,
+        client: &Client<HttpsConnector<HttpConnector>>,
+        notification: &Notification,
+    ) -> std::result::Result<(), Failure> {
+        let body =
+            serde_json::to_vec(notification).map_err(|e| Failure::Permanent(e.to_string()))?;
+        let request = Request::post(self.url.clone())
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", sign(&self.secret, &body)),
+            )
+            .body(Body::from(body))
+            .map_err(|e| Failure::Permanent(e.to_string()))?;
+        let status = match timeout(POST_TIMEOUT, client.request(request)).await {
+            Ok(Ok(resp)) => resp.status(),
+            Ok(Err(e)) => return Err(Failure::Transient(e.to_string())),
+            Err(_) => {
+                return Err(Failure::Transient(format!(
+                    "no response in {:?}",
+                    POST_TIMEOUT
+                )))
+            }
+        };
+        classify(status)
+    }
+
+    /// Delivers the queue in a background thread with its own runtime, so both
+    /// the async service and the blocking `eventread` can use it.
+    pub fn spawn(self: std::sync::Arc<Self>, logger: slog::Logger) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut runtime = match tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    error!(logger, "notification delivery is not started: {}", e);
+                    return;
+                }
+            };
+            let client = Client::builder().build(HttpsConnector::new());
+
+            runtime.block_on(async {
+                loop {
+                    if let Err(e) = self.deliver(&logger, &client).await {
+                        error!(logger, "notification queue failed: {}", e);
+                    }
+                    tokio::time::delay_for(Duration::from_secs(1)).await;
+                }
+            })
+        })
+    }
+}
+
+/// Failure of the response status, `Ok` for a success.
+fn classify(status: StatusCode) -> std::result::Result<(), Failure> {
+    if status.is_success() {
+        return Ok(());
+    }
+    let e = format!("unexpected response status {}", status);
+    match status {
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => Err(Failure::Transient(e)),
+        _ if status.is_client_error() => Err(Failure::Permanent(e)),
+        _ => Err(Failure::Transient(e)),
+    }
+}
+
+/// Notification arguments shared by `service` and `eventread`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("notify_url")
+            .env("PO_NOTIFY_URL")
+            .long("notify_url")
+            .help("webhook url the notifications are posted to"),
+        Arg::with_name("notify_secret")
+            .env("PO_NOTIFY_SECRET")
+            .long("notify_secret")
+            .help("HMAC-SHA256 key of the X-Priceoracle-Signature header"),
+        Arg::with_name("notify_queue")
+            .env("PO_NOTIFY_QUEUE")
+            .long("notify_queue")
+            .default_value("notifications")
+            .help("directory of the notifications waiting for the delivery"),
+        Arg::with_name("notify_dead_letter")
+            .env("PO_NOTIFY_DEAD_LETTER")
+            .long("notify_dead_letter")
+            .default_value("notifications.dead")
+            .help("file the notifications given up on are appended to as JSON lines"),
+        Arg::with_name("notify_max_attempts")
+            .env("PO_NOTIFY_MAX_ATTEMPTS")
+            .long("notify_max_attempts")
+            .default_value("20")
+            .help("delivery attempts of a notification before it is given up"),
+        Arg::with_name("notify_max_age")
+            .env("PO_NOTIFY_MAX_AGE")
+            .long("notify_max_age")
+            .default_value("86400")
+            .help("seconds after the queueing a failed notification is given up"),
+    ]
+}
+
+fn storage(path: &Path, e: std::io::Error) -> Error {
+    Error::Storage(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use serde_json::Value;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    const SECRET: &str = "secret";
+
+    /// Notifications the receiver got, with whether the signature was valid.
+    type Received = Arc<Mutex<Vec<(Kind, bool)>>>;
+
+    /// Local webhook receiver answering `price_changed` and `deviation` with
+    /// 200, `update_failed` with 503 and `source_down` with 400.
+    fn receiver() -> (SocketAddr, Received) {
+        let received = Received::default();
+        let log = received.clone();
+        let service = make_service_fn(move |_| {
+            let log = log.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                    let log = log.clone();
+                    async move {
+                        let header = req.headers()[SIGNATURE_HEADER]
+                            .to_str()
+                            .unwrap()
+                            .to_string();
+                        let body = hyper::body::to_bytes(req).await?;
+                        let notification: Notification = serde_json::from_slice(&body).unwrap();
+                        let signed = verify(SECRET.as_bytes(), &body, &header);
+                        log.lock().unwrap().push((notification.kind, signed));
+                        let status = match notification.kind {
+                            Kind::PriceChanged | Kind::Deviation => StatusCode::OK,
+                            Kind::UpdateFailed => StatusCode::SERVICE_UNAVAILABLE,
+                            Kind::SourceDown => StatusCode::BAD_REQUEST,
+                        };
+                        let mut resp = Response::new(Body::empty());
+                        *resp.status_mut() = status;
+                        Ok::<_, hyper::Error>(resp)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, received)
+    }
+
+    /// Notifier with a fresh queue and dead-letter file named after the test.
+    fn notifier(name: &str, url: &str, max_attempts: u32) -> Notifier {
+        let dir = std::env::temp_dir().join(format!("notify-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        Notifier::new(
+            url,
+            SECRET,
+            dir.join("queue"),
+            dir.join("dead"),
+            max_attempts,
+            Duration::from_secs(3600),
+        )
+        .unwrap()
+    }
+
+    async fn deliver(notifier: &Notifier) {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let client = Client::builder().build(HttpsConnector::new());
+        notifier.deliver(&logger, &client).await.unwrap();
+    }
+
+    fn queued(notifier: &Notifier) -> Vec<Entry> {
+        let queued = notifier.queued().unwrap();
+        queued
+            .iter()
+            .map(|path| serde_json::from_slice(&fs::read(path).unwrap()).unwrap())
+            .collect()
+    }
+
+    fn dead_letters(notifier: &Notifier) -> Vec<serde_json::Value> {
+        fs::read_to_string(&notifier.dead_letter)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn verifies_the_signature_of_the_body() {
+        let header = format!("sha256={}", sign(b"secret", b"body"));
+        assert!(verify(b"secret", b"body", &header));
+        assert!(!verify(b"other", b"body", &header));
+        assert!(!verify(b"secret", b"bodies", &header));
+        assert!(!verify(b"secret", b"body", &header["sha256=".len()..]));
+        assert!(!verify(b"secret", b"body", "sha256=zz"));
+        assert!(!verify(b"secret", b"body", &header[..header.len() - 2]));
+    }
+
+    #[test]
+    fn classifies_the_response_statuses() {
+        assert!(classify(StatusCode::OK).is_ok());
+        assert!(classify(StatusCode::NO_CONTENT).is_ok());
+        for status in &[
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(matches!(classify(*status), Err(Failure::Transient(_))));
+        }
+        for status in &[
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+            StatusCode::GONE,
+        ] {
+            assert!(matches!(classify(*status), Err(Failure::Permanent(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_the_signed_notifications_in_order() {
+        let (addr, received) = receiver();
+        let notifier = notifier("delivered", &format!("http://{}", addr), 5);
+        notifier.notify(Kind::PriceChanged, Value::Null).unwrap();
+        notifier.notify(Kind::Deviation, Value::Null).unwrap();
+
+        deliver(&notifier).await;
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(Kind::PriceChanged, true), (Kind::Deviation, true)]
+        );
+        assert!(queued(&notifier).is_empty());
+        assert!(dead_letters(&notifier).is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_a_transient_failure_before_the_later_notifications() {
+        let (addr, received) = receiver();
+        let notifier = notifier("transient", &format!("http://{}", addr), 5);
+        notifier.notify(Kind::UpdateFailed, Value::Null).unwrap();
+        notifier.notify(Kind::PriceChanged, Value::Null).unwrap();
+
+        deliver(&notifier).await;
+        // the retry is not due yet
+        deliver(&notifier).await;
+
+        assert_eq!(*received.lock().unwrap(), vec![(Kind::UpdateFailed, true)]);
+        let queued = queued(&notifier);
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0].notification.kind, Kind::UpdateFailed);
+        assert_eq!(queued[0].attempts, 1);
+        assert!(queued[0].next_attempt > queued[0].notification.created_at);
+        assert_eq!(queued[1].attempts, 0);
+        assert!(dead_letters(&notifier).is_empty());
+    }
+
+    #[tokio::test]
+    async fn buries_a_permanent_failure_and_delivers_the_rest() {
+        let (addr, received) = receiver();
+        let notifier = notifier("permanent", &format!("http://{}", addr), 5);
+        notifier.notify(Kind::SourceDown, Value::Null).unwrap();
+        notifier.notify(Kind::PriceChanged, Value::Null).unwrap();
+
+        deliver(&notifier).await;
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(Kind::SourceDown, true), (Kind::PriceChanged, true)]
+        );
+        assert!(queued(&notifier).is_empty());
+        let dead = dead_letters(&notifier);
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0]["notification"]["kind"], "source_down");
+        assert_eq!(dead[0]["attempts"], 1);
+    }
+
+    #[tokio::test]
+    async fn buries_a_transient_failure_out_of_attempts() {
+        let (addr, _) = receiver();
+        let notifier = notifier("attempts", &format!("http://{}", addr), 1);
+        notifier.notify(Kind::UpdateFailed, Value::Null).unwrap();
+
+        deliver(&notifier).await;
+
+        assert!(queued(&notifier).is_empty());
+        let dead = dead_letters(&notifier);
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0]["notification"]["kind"], "update_failed");
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_receiver_is_a_transient_failure() {
+        // the port of a dropped listener refuses the connection
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let notifier = notifier("unreachable", &format!("http://{}", addr), 5);
+        notifier.notify(Kind::PriceChanged, Value::Null).unwrap();
+
+        deliver(&notifier).await;
+
+        let queued = queued(&notifier);
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].attempts, 1);
+        assert!(dead_letters(&notifier).is_empty());
+    }
+}
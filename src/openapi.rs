@@ -0,0 +1,749 @@
+//! OpenAPI 3 document of the HTTP endpoints, served by `server` at
+//! `/openapi.json` and printed by the `openapi` command.
+//!
+//! The schemas are derived from the request and response types of the
+//! handlers, so a field added to a handler type is in the document too. The
+//! routers dispatch on the route tables of this module, so does a route.
+
+use crate::feed::{Command, Message, Reply};
+use crate::peer::{Consensus, SignedQuotes};
+use crate::report::{Observation, ReportRequest};
+use crate::server::{
+    paths, CcipError, CcipRequest, CcipResponse, ErrorResponse, HealthResponse, HistoryEntry,
+    PauseResponse, PriceResponse, SetAdminRequest, TransactionResponse, UpdateRequest,
+};
+use hyper::Method;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+
+/// Schema of a web3 `U256`, serialized as a `0x` hex quantity.
+pub(crate) fn uint256(_: &mut SchemaGenerator) -> Schema {
+    hex_string(
+        "uint256 as a 0x prefixed hex quantity",
+        "^0x[0-9a-fA-F]{1,64}$",
+    )
+}
+
+/// Schema of a web3 `Address`.
+pub(crate) fn address(_: &mut SchemaGenerator) -> Schema {
+    hex_string("20 byte address as 0x prefixed hex", "^0x[0-9a-fA-F]{40}$")
+}
+
+/// Schema of a web3 `Bytes`, such as a signature.
+pub(crate) fn bytes(_: &mut SchemaGenerator) -> Schema {
+    hex_string("bytes as 0x prefixed hex", "^0x([0-9a-fA-F]{2})*$")
+}
+
+fn hex_string(description: &str, pattern: &str) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(pattern.to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Operation of a route. The routers of `server` and `report` dispatch on the
+/// endpoint of the request, so they serve exactly the routes of the document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Endpoint {
+    Price,
+    History,
+    Events,
+    Stream,
+    CcipLookup,
+    Ccip,
+    Health,
+    OpenApi,
+    Metrics,
+    Quotes,
+    PushQuotes,
+    Consensus,
+    Observation,
+    Report,
+    AdminUpdate,
+    AdminPause,
+    AdminResume,
+    AdminAdmins,
+}
+
+/// Method and path of a router endpoint.
+pub(crate) type Route = (&'static str, &'static str, Endpoint);
+
+/// Routes of the public API of `server`.
+pub(crate) const API_ROUTES: &[Route] = &[
+    ("get", paths::PRICE, Endpoint::Price),
+    ("get", paths::HISTORY, Endpoint::History),
+    ("get", paths::EVENTS, Endpoint::Events),
+    ("get", paths::STREAM, Endpoint::Stream),
+    ("get", paths::CCIP_LOOKUP, Endpoint::CcipLookup),
+    ("post", paths::CCIP, Endpoint::Ccip),
+    ("get", paths::HEALTH, Endpoint::Health),
+    ("get", paths::OPENAPI, Endpoint::OpenApi),
+];
+
+/// Routes of the usage counters of `server` and `service`.
+pub(crate) const METRICS_ROUTES: &[Route] = &[("get", paths::METRICS, Endpoint::Metrics)];
+
+/// Routes of the feed stream of `service`.
+pub(crate) const STREAM_ROUTES: &[Route] = &[
+    ("get", paths::STREAM, Endpoint::Stream),
+    ("get", paths::EVENTS, Endpoint::Events),
+];
+
+/// Routes of the quote exchange between the nodes.
+pub(crate) const NODE_ROUTES: &[Route] = &[
+    ("get", paths::QUOTES, Endpoint::Quotes),
+    ("post", paths::QUOTES, Endpoint::PushQuotes),
+    ("get", paths::CONSENSUS, Endpoint::Consensus),
+];
+
+/// Routes of the reporter asked by the leader.
+pub(crate) const REPORTER_ROUTES: &[Route] = &[
+    ("get", paths::OBSERVATION, Endpoint::Observation),
+    ("post", paths::REPORT, Endpoint::Report),
+];
+
+/// Routes of the admin api of `service`.
+pub(crate) const ADMIN_ROUTES: &[Route] = &[
+    ("post", paths::ADMIN_UPDATE, Endpoint::AdminUpdate),
+    ("post", paths::ADMIN_PAUSE, Endpoint::AdminPause),
+    ("post", paths::ADMIN_RESUME, Endpoint::AdminResume),
+    ("post", paths::ADMIN_ADMINS, Endpoint::AdminAdmins),
+];
+
+/// Endpoint of the request among the routes of a router. A path with
+/// parameters matches by its part before the first `{`.
+pub(crate) fn endpoint(routes: &[Route], method: &Method, path: &str) -> Option<Endpoint> {
+    routes
+        .iter()
+        .find(|(route_method, route_path, _)| {
+            method.as_str().eq_ignore_ascii_case(route_method)
+                && match route_path.find('{') {
+                    Some(params) => path.starts_with(&route_path[..params]),
+                    None => path == *route_path,
+                }
+        })
+        .map(|&(_, _, endpoint)| endpoint)
+}
+
+/// Listener of a command and the routes it serves.
+struct Listener {
+    /// Command and the flag of the address, such as `server --bind`.
+    bind: &'static str,
+    /// Address of the `servers` entry until the client sets its own.
+    default: &'static str,
+    /// The listener serves https with `--tls_cert`.
+    tls: bool,
+    routes: &'static [Route],
+}
+
+/// Listeners with the route tables their routers dispatch on.
+const LISTENERS: &[Listener] = &[
+    Listener {
+        bind: "server --bind",
+        default: "127.0.0.1:8080",
+        tls: true,
+        routes: API_ROUTES,
+    },
+    Listener {
+        bind: "server --metrics_bind",
+        default: "127.0.0.1:9300",
+        tls: false,
+        routes: METRICS_ROUTES,
+    },
+    Listener {
+        bind: "service --metrics_bind",
+        default: "127.0.0.1:9300",
+        tls: false,
+        routes: METRICS_ROUTES,
+    },
+    Listener {
+        bind: "service --stream_bind",
+        default: "127.0.0.1:9500",
+        tls: true,
+        routes: STREAM_ROUTES,
+    },
+    Listener {
+        bind: "service --node_bind",
+        default: "127.0.0.1:9400",
+        tls: false,
+        routes: NODE_ROUTES,
+    },
+    Listener {
+        bind: "service --reporter_bind",
+        default: "127.0.0.1:9001",
+        tls: false,
+        routes: REPORTER_ROUTES,
+    },
+    Listener {
+        bind: "service --admin_bind",
+        default: "127.0.0.1:9200",
+        tls: false,
+        routes: ADMIN_ROUTES,
+    },
+];
+
+impl Listener {
+    /// `servers` entries of the listener.
+    fn servers(&self) -> Vec<Value> {
+        let schemes: &[&str] = if self.tls {
+            &["http", "https"]
+        } else {
+            &["http"]
+        };
+        schemes
+            .iter()
+            .map(|scheme| {
+                json!({
+                    "url": format!("{}://{{address}}", scheme),
+                    "description": self.bind,
+                    "variables": { "address": { "default": self.default } },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Security of the keyed `server` endpoints, anonymous requests are allowed
+/// unless --require_api_key is set.
+fn api_key() -> Value {
+    json!([{ "apiKeyHeader": [] }, { "apiKeyQuery": [] }, {}])
+}
+
+fn bearer() -> Value {
+    json!([{ "bearer": [] }])
+}
+
+/// Builds the operations with the schemas referenced from the components.
+struct Spec {
+    gen: RefCell<SchemaGenerator>,
+}
+
+impl Spec {
+    fn operation(&self, endpoint: Endpoint) -> Value {
+        match endpoint {
+            Endpoint::Price => self.get_price(),
+            Endpoint::History => self.get_history(),
+            Endpoint::Events => self.stream_events(),
+            Endpoint::Stream => self.stream(),
+            Endpoint::CcipLookup => self.ccip_lookup(),
+            Endpoint::Ccip => self.ccip_lookup_post(),
+            Endpoint::Health => self.get_health(),
+            Endpoint::OpenApi => self.get_openapi(),
+            Endpoint::Metrics => self.get_metrics(),
+            Endpoint::Quotes => self.get_quotes(),
+            Endpoint::PushQuotes => self.push_quotes(),
+            Endpoint::Consensus => self.get_consensus(),
+            Endpoint::Observation => self.get_observation(),
+            Endpoint::Report => self.sign_report(),
+            Endpoint::AdminUpdate => self.admin_update(),
+            Endpoint::AdminPause => self.admin_pause(),
+            Endpoint::AdminResume => self.admin_resume(),
+            Endpoint::AdminAdmins => self.admin_set_admin(),
+        }
+    }
+
+    fn schema<T: JsonSchema>(&self) -> Value {
+        json!(self.gen.borrow_mut().subschema_for::<T>())
+    }
+
+    /// Schema of a parameter with one of the hex schemas above.
+    fn schema_with(&self, schema: fn(&mut SchemaGenerator) -> Schema) -> Value {
+        json!(schema(&mut self.gen.borrow_mut()))
+    }
+
+    fn json<T: JsonSchema>(&self, description: &str) -> Value {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": self.schema::<T>() } },
+        })
+    }
+
+    fn error(&self, description: &str) -> Value {
+        self.json::<ErrorResponse>(description)
+    }
+
+    /// Answers of the key check and the rate limits of `server`.
+    fn limited(&self, mut responses: Value) -> Value {
+        responses["401"] = self.error("the API key is unknown, revoked or required");
+        responses["429"] = json!({
+            "description": "the key or the client IP is over its rate limit",
+            "headers": {
+                "Retry-After": {
+                    "description": "seconds until the next request is allowed",
+                    "schema": { "type": "integer" },
+                },
+            },
+            "content": {
+                "application/json": { "schema": self.schema::<ErrorResponse>() },
+            },
+        });
+        responses
+    }
+
+    fn get_price(&self) -> Value {
+        json!({
+            "tags": ["price"],
+            "operationId": "getPrice",
+            "summary": "Latest round of the contract",
+            "security": api_key(),
+            "responses": self.limited(json!({
+                "200": self.json::<PriceResponse>("latest round"),
+                "404": self.error("the price was never set"),
+                "502": self.error("the ethereum node request failed"),
+            })),
+        })
+    }
+
+    fn get_history(&self) -> Value {
+        json!({
+            "tags": ["history"],
+            "operationId": "getHistory",
+            "summary": "Stored feed messages after the id, oldest first",
+            "security": api_key(),
+            "parameters": [
+                {
+                    "name": "after",
+                    "in": "query",
+                    "description": "id of the last message the client has, 0 for the oldest kept",
+                    "schema": { "type": "integer", "minimum": 0, "default": 0 },
+                },
+                {
+                    "name": "limit",
+                    "in": "query",
+                    "schema": { "type": "integer", "minimum": 1, "maximum": 1000, "default": 100 },
+                },
+            ],
+            "responses": self.limited(json!({
+                "200": {
+                    "description": "messages of the history",
+                    "content": {
+                        "application/json": {
+                            "schema": { "type": "array", "items": self.schema::<HistoryEntry>() },
+                        },
+                    },
+                },
+                "400": self.error("after or limit is not a number"),
+            })),
+        })
+    }
+
+    fn stream_events(&self) -> Value {
+        json!({
+            "tags": ["history"],
+            "operationId": "streamEvents",
+            "summary": "Server-Sent Events of the feed messages",
+            "description": "Every event has the id of the message in the history, the `type` of the \
+                message as its event name and the message as its data. A client resuming with \
+                `Last-Event-ID` receives the stored messages after it first. `server --bind` \
+                carries the `price_changed` messages only, `service --stream_bind` also the \
+                `price` and `transaction` messages, each with its own history and ids.",
+            "security": api_key(),
+            "parameters": [{
+                "name": "Last-Event-ID",
+                "in": "header",
+                "schema": { "type": "integer", "minimum": 0 },
+            }],
+            "responses": self.limited(json!({
+                "200": {
+                    "description": "endless event stream",
+                    "content": { "text/event-stream": { "schema": self.schema::<Message>() } },
+                },
+                "400": self.error("Last-Event-ID is not an id"),
+            })),
+        })
+    }
+
+    fn stream(&self) -> Value {
+        json!({
+            "tags": ["history"],
+            "operationId": "stream",
+            "summary": "WebSocket of the feed messages",
+            "description": "The client sends the commands as text frames and receives their \
+                replies and the messages of the subscribed pairs, see `x-websocket`. \
+                `server --bind` carries the `price_changed` messages only, `service --stream_bind` \
+                also the `price` and `transaction` messages.",
+            "x-websocket": {
+                "client": self.schema::<Command>(),
+                "server": { "oneOf": [self.schema::<Reply>(), self.schema::<Message>()] },
+            },
+            "security": api_key(),
+            "responses": self.limited(json!({
+                "101": { "description": "switched to the WebSocket protocol" },
+                "400": self.error("not a WebSocket handshake"),
+            })),
+        })
+    }
+
+    fn ccip_lookup(&self) -> Value {
+        json!({
+            "tags": ["ccip"],
+            "operationId": "ccipLookup",
+            "summary": "Round of the OffchainLookup signed by the gateway",
+            "description": "The gateway url of OffchainPriceOracle, the client substitutes the \
+                sender and the callData of the revert.",
+            "security": api_key(),
+            "parameters": [
+                {
+                    "name": "sender",
+                    "in": "path",
+                    "required": true,
+                    "schema": self.schema_with(address),
+                },
+                {
+                    "name": "data",
+                    "in": "path",
+                    "required": true,
+                    "description": "callData of `price(string,uint80)`",
+                    "schema": self.schema_with(bytes),
+                },
+            ],
+            "responses": self.limited(self.ccip_responses()),
+        })
+    }
+
+    fn ccip_lookup_post(&self) -> Value {
+        json!({
+            "tags": ["ccip"],
+            "operationId": "ccipLookupPost",
+            "summary": "Round of the OffchainLookup signed by the gateway, for the urls without {data}",
+            "security": api_key(),
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": self.schema::<CcipRequest>() } },
+            },
+            "responses": self.limited(self.ccip_responses()),
+        })
+    }
+
+    fn get_health(&self) -> Value {
+        json!({
+            "tags": ["health"],
+            "operationId": "getHealth",
+            "summary": "Reachability of the ethereum node and the age of the price",
+            "responses": {
+                "200": self.json::<HealthResponse>("the server can read the contract"),
+                "503": self.json::<HealthResponse>("the ethereum node request failed"),
+            },
+        })
+    }
+
+    fn get_openapi(&self) -> Value {
+        json!({
+            "tags": ["health"],
+            "operationId": "getOpenApi",
+            "summary": "This document",
+            "responses": {
+                "200": {
+                    "description": "OpenAPI 3 document",
+                    "content": { "application/json": { "schema": { "type": "object" } } },
+                },
+            },
+        })
+    }
+
+    fn get_metrics(&self) -> Value {
+        json!({
+            "tags": ["health"],
+            "operationId": "getMetrics",
+            "summary": "Usage counters of the API keys in the Prometheus text format",
+            "responses": {
+                "200": {
+                    "description": "counters",
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+            },
+        })
+    }
+
+    fn get_quotes(&self) -> Value {
+        json!({
+            "tags": ["attestation"],
+            "operationId": "getQuotes",
+            "summary": "Last quotes signed by the node",
+            "responses": {
+                "200": self.json::<SignedQuotes>("signed quotes"),
+                "404": self.error("no quotes yet"),
+            },
+        })
+    }
+
+    fn push_quotes(&self) -> Value {
+        json!({
+            "tags": ["attestation"],
+            "operationId": "pushQuotes",
+            "summary": "Quotes signed by a peer node",
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": self.schema::<SignedQuotes>() } },
+            },
+            "responses": {
+                "200": { "description": "the quotes are accepted" },
+                "400": self.error("the body is not signed quotes"),
+                "401": self.error("the signer is not trusted or the signature is invalid"),
+            },
+        })
+    }
+
+    fn get_consensus(&self) -> Value {
+        json!({
+            "tags": ["attestation"],
+            "operationId": "getConsensus",
+            "summary": "Median of the fresh quotes of the node and its peers",
+            "responses": {
+                "200": self.json::<Consensus>("median and the node prices"),
+                "404": self.error("no fresh quotes"),
+            },
+        })
+    }
+
+    fn get_observation(&self) -> Value {
+        json!({
+            "tags": ["attestation"],
+            "operationId": "getObservation",
+            "summary": "Answer for the next round signed by the reporter",
+            "responses": {
+                "200": self.json::<Observation>("signed observation"),
+                "404": self.error("no observation yet"),
+            },
+        })
+    }
+
+    fn sign_report(&self) -> Value {
+        json!({
+            "tags": ["attestation"],
+            "operationId": "signReport",
+            "summary": "Signs the answer of the leader if it is close to the own observation",
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": self.schema::<ReportRequest>() } },
+            },
+            "responses": {
+                "200": self.json::<Observation>("answer signed by the reporter"),
+                "400": self.error("the body is not a report request"),
+                "409": self.error("the answer deviates from the own observation"),
+            },
+        })
+    }
+
+    fn admin_update(&self) -> Value {
+        json!({
+            "tags": ["admin"],
+            "operationId": "adminUpdate",
+            "summary": "Pushes the price now",
+            "security": bearer(),
+            "requestBody": {
+                "content": { "application/json": { "schema": self.schema::<UpdateRequest>() } },
+            },
+            "responses": self.admin_responses(),
+        })
+    }
+
+    fn admin_pause(&self) -> Value {
+        json!({
+            "tags": ["admin"],
+            "operationId": "adminPause",
+            "summary": "Stops the price pushes",
+            "security": bearer(),
+            "responses": self.pause_responses(),
+        })
+    }
+
+    fn admin_resume(&self) -> Value {
+        json!({
+            "tags": ["admin"],
+            "operationId": "adminResume",
+            "summary": "Restarts the price pushes",
+            "security": bearer(),
+            "responses": self.pause_responses(),
+        })
+    }
+
+    fn admin_set_admin(&self) -> Value {
+        json!({
+            "tags": ["admin"],
+            "operationId": "adminSetAdmin",
+            "summary": "Calls setAdmin of the contract",
+            "security": bearer(),
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": self.schema::<SetAdminRequest>() } },
+            },
+            "responses": self.admin_responses(),
+        })
+    }
+    fn ccip_responses(&self) -> Value {
+        json!({
+            "200": self.json::<CcipResponse>("response of priceWithProof"),
+            "400": self.json::<CcipError>("the callData is malformed or the sender is not in --ccip_senders"),
+            "404": self.json::<CcipError>("the gateway is not enabled, or the pair or the round is unknown"),
+            "500": self.json::<CcipError>("signing failed"),
+            "502": self.json::<CcipError>("the ethereum node request failed"),
+        })
+    }
+
+    fn admin_responses(&self) -> Value {
+        json!({
+            "200": self.json::<TransactionResponse>("tx of the action, no hash when none was needed"),
+            "400": self.error("the parameters are invalid"),
+            "401": self.error("bearer token is required"),
+            "409": self.error("the action is refused in the current mode of the service"),
+            "413": self.error("the body exceeds 64 KiB"),
+            "503": self.error("the service loop is stopped"),
+        })
+    }
+
+    fn pause_responses(&self) -> Value {
+        json!({
+            "200": self.json::<PauseResponse>("new and previous state"),
+            "401": self.error("bearer token is required"),
+            "413": self.error("the body exceeds 64 KiB"),
+        })
+    }
+}
+
+/// Paths of the listeners, every path with the `servers` of the listeners
+/// serving it. A route served by several listeners keeps the operation of the
+/// first one.
+fn paths(spec: &Spec) -> Map<String, Value> {
+    let mut paths = Map::new();
+    for listener in LISTENERS {
+        for (method, path, endpoint) in listener.routes {
+            let item = paths
+                .entry(*path)
+                .or_insert_with(|| json!({ "servers": [] }));
+            if item.get(method).is_none() {
+                item[method] = spec.operation(*endpoint);
+            }
+            let servers = item["servers"].as_array_mut().expect("servers is an array");
+            for server in listener.servers() {
+                if !servers.contains(&server) {
+                    servers.push(server);
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// OpenAPI document of all the endpoints.
+pub fn spec() -> Value {
+    let spec = Spec {
+        gen: RefCell::new(SchemaSettings::openapi3().into_generator()),
+    };
+    let paths = paths(&spec);
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "priceoracle",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "HTTP API of the price oracle. The endpoints are served by different listeners, \
+                the `servers` of a path name the flags of its listeners.",
+        },
+        "tags": [
+            { "name": "price", "description": "latest round of the contract" },
+            { "name": "history", "description": "feed messages, served by server --bind and service --stream_bind" },
+            { "name": "health", "description": "state of the server, its usage counters and this document" },
+            { "name": "ccip", "description": "EIP-3668 gateway, enabled by --ccip_private_key" },
+            { "name": "attestation", "description": "quote exchange and quorum reports between the service nodes" },
+            { "name": "admin", "description": "operator actions on the service" },
+        ],
+        "paths": paths,
+        "components": {
+            "schemas": spec.gen.into_inner().take_definitions(),
+            "securitySchemes": {
+                "apiKeyHeader": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+                "apiKeyQuery": { "type": "apiKey", "in": "query", "name": "api_key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn method(method: &str) -> Method {
+        method.to_uppercase().parse().unwrap()
+    }
+
+    #[test]
+    fn every_route_of_the_listeners_is_in_the_document() {
+        let spec = spec();
+        for listener in LISTENERS {
+            for (route_method, path, route_endpoint) in listener.routes {
+                let item = &spec["paths"][path];
+                assert!(
+                    item[route_method]["operationId"].is_string(),
+                    "{} {} of {} is not in the document",
+                    route_method,
+                    path,
+                    listener.bind
+                );
+                assert!(item["servers"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|server| server["description"] == listener.bind));
+                assert_eq!(
+                    endpoint(listener.routes, &method(route_method), path),
+                    Some(*route_endpoint)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_document_has_only_the_routes_of_the_listeners() {
+        let spec = spec();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for route_method in item.as_object().unwrap().keys() {
+                if route_method == "servers" {
+                    continue;
+                }
+                assert!(LISTENERS.iter().any(|listener| listener
+                    .routes
+                    .iter()
+                    .any(|(m, p, _)| m == route_method && p == path)));
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_method_and_the_path() {
+        let lookup = "/v1/ccip/0x0000000000000000000000000000000000000001/0x12.json";
+        assert_eq!(
+            endpoint(API_ROUTES, &Method::GET, lookup),
+            Some(Endpoint::CcipLookup)
+        );
+        assert_eq!(
+            endpoint(API_ROUTES, &Method::POST, paths::CCIP),
+            Some(Endpoint::Ccip)
+        );
+        assert_eq!(endpoint(API_ROUTES, &Method::GET, paths::CCIP), None);
+        assert_eq!(endpoint(API_ROUTES, &Method::POST, paths::PRICE), None);
+        assert_eq!(endpoint(API_ROUTES, &Method::GET, "/v1/price/"), None);
+        assert_eq!(endpoint(API_ROUTES, &Method::GET, paths::METRICS), None);
+        assert_eq!(
+            endpoint(NODE_ROUTES, &Method::POST, paths::QUOTES),
+            Some(Endpoint::PushQuotes)
+        );
+        assert_eq!(
+            endpoint(NODE_ROUTES, &Method::GET, paths::QUOTES),
+            Some(Endpoint::Quotes)
+        );
+    }
+}
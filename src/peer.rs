@@ -0,0 +1,442 @@
+//! Exchange of the observed quotes between independent oracle nodes.
+//!
+//! Every node signs the quotes it fetched from its price sources and serves
+//! them at `GET /v1/quotes`. The node pushes its quotes to the peers at
+//! `POST /v1/quotes` and pulls theirs, only the quotes signed by the trusted
+//! nodes are accepted. The nodes then agree on the median of the node prices
+//! and report the peers diverging from it.
+
+use crate::client::Signer;
+use crate::error::{Error, Result};
+use crate::openapi;
+use crate::report;
+use crate::server;
+use ethtxsign::abi::{self, Token};
+use futures::future::join_all;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use web3::types::{Address, Bytes, H256, U256};
+
+/// Seconds the timestamp of the quotes may be ahead of the own clock.
+const MAX_CLOCK_SKEW: u64 = 30;
+
+/// Price fetched from one source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Quote {
+    pub source: String,
+    /// BTC for 1 ETH multiplied by 10^18
+    #[schemars(schema_with = "openapi::uint256")]
+    pub price: U256,
+}
+
+/// Quotes of the node signed by its key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SignedQuotes {
+    #[schemars(schema_with = "openapi::address")]
+    pub node: Address,
+    pub quotes: Vec<Quote>,
+    /// Unix time the quotes were fetched at
+    pub timestamp: u64,
+    /// Signature of the [`quotes_digest`]
+    #[schemars(schema_with = "openapi::bytes")]
+    pub signature: Bytes,
+}
+
+impl SignedQuotes {
+    pub fn sign(quotes: Vec<Quote>, timestamp: u64, signer: &Signer) -> Result<Self> {
+        let digest = quotes_digest(signer.address, &quotes, timestamp);
+        Ok(SignedQuotes {
+            node: signer.address,
+            quotes,
+            timestamp,
+            signature: Bytes(ethtxsign::sign_hash(&digest, &signer.private_key)?),
+        })
+    }
+
+    /// Checks that the quotes are signed by the node.
+    pub fn verify(&self) -> Result<()> {
+        let digest = quotes_digest(self.node, &self.quotes, self.timestamp);
+        let signer = ethtxsign::recover_address(&digest, &self.signature.0)?;
+        if signer != self.node {
+            return Err(Error::Peer(format!(
+                "quotes of {:?} are signed by {:?}",
+                self.node, signer
+            )));
+        }
+        Ok(())
+    }
+
+    /// Median of the quotes of the node.
+    pub fn price(&self) -> Option<U256> {
+        report::median(self.quotes.iter().map(|q| q.price).collect())
+    }
+}
+
+/// `keccak256(abi.encode(node, (string source, uint256 price)[], timestamp))`
+pub fn quotes_digest(node: Address, quotes: &[Quote], timestamp: u64) -> H256 {
+    let quotes = quotes
+        .iter()
+        .map(|q| Token::Tuple(vec![Token::String(q.source.clone()), Token::Uint(q.price)]))
+        .collect();
+    let data = abi::encode(&[
+        Token::Address(node),
+        Token::Array(quotes),
+        Token::Uint(timestamp.into()),
+    ]);
+    H256::from_slice(&ethtxsign::keccak256_hash(&data))
+}
+
+/// Price of a node compared to the shared median.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct NodePrice {
+    #[schemars(schema_with = "openapi::address")]
+    pub node: Address,
+    #[schemars(schema_with = "openapi::uint256")]
+    pub price: U256,
+    /// Difference from the shared median in percent
+    pub deviation: f64,
+    /// `deviation` is above `max_divergence`
+    pub divergent: bool,
+}
+
+/// Shared view of the fresh quotes of the node and its peers.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Consensus {
+    #[schemars(schema_with = "openapi::uint256")]
+    pub median: U256,
+    pub nodes: Vec<NodePrice>,
+}
+
+/// Quotes of the node and the ones received from the peers.
+pub struct Node {
+    signer: Signer,
+    /// Nodes whose quotes are accepted
+    trusted: Vec<Address>,
+    /// Base urls of the peers the quotes are exchanged with
+    peers: Vec<String>,
+    /// Quotes older than this many seconds are ignored
+    max_age: u64,
+    /// Max difference in percent between a node price and the shared median
+    max_divergence: f64,
+    own: Mutex<Option<SignedQuotes>>,
+    received: Mutex<HashMap<Address, SignedQuotes>>,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Node {
+    pub fn new(
+        signer: Signer,
+        trusted: Vec<Address>,
+        peers: Vec<String>,
+        max_age: u64,
+        max_divergence: f64,
+    ) -> Result<Self> {
+        if ethtxsign::private_key_address(&signer.private_key)? != signer.address {
+            return Err(Error::invalid(
+                "private_key",
+                "the key does not belong to --from_addr",
+            ));
+        }
+
+        Ok(Node {
+            signer,
+            trusted,
+            peers,
+            max_age,
+            max_divergence,
+            own: Mutex::new(None),
+            received: Mutex::new(HashMap::new()),
+            client: Client::builder().build(HttpsConnector::new()),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address
+    }
+
+    /// Signs and keeps the own quotes.
+    pub fn publish(&self, quotes: Vec<Quote>) -> Result<SignedQuotes> {
+        let signed = SignedQuotes::sign(quotes, now(), &self.signer)?;
+        *self
+            .own
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(signed.clone());
+        Ok(signed)
+    }
+
+    /// Returns the last own quotes.
+    pub fn quotes(&self) -> Option<SignedQuotes> {
+        self.own
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Keeps the quotes of a trusted peer, older quotes than the kept ones are
+    /// ignored. Quotes from more than [`MAX_CLOCK_SKEW`] in the future are
+    /// refused, they would stay fresh and win over the newer ones.
+    pub fn accept(&self, quotes: SignedQuotes) -> Result<()> {
+        if !self.trusted.contains(&quotes.node) {
+            return Err(Error::Peer(format!("{:?} is not trusted", quotes.node)));
+        }
+        quotes.verify()?;
+        let now = now();
+        if quotes.timestamp > now + MAX_CLOCK_SKEW {
+            return Err(Error::Peer(format!(
+                "quotes of {:?} are {}s in the future",
+                quotes.node,
+                quotes.timestamp - now
+            )));
+        }
+        if now.saturating_sub(quotes.timestamp) > self.max_age {
+            return Err(Error::Peer(format!(
+                "quotes of {:?} are older than {}s",
+                quotes.node, self.max_age
+            )));
+        }
+
+        let mut received = self
+            .received
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let newer = received
+            .get(&quotes.node)
+            .map_or(true, |kept| kept.timestamp < quotes.timestamp);
+        if newer {
+            received.insert(quotes.node, quotes);
+        }
+        Ok(())
+    }
+
+    /// Pushes the own quotes to the peers and pulls theirs, failed peers are
+    /// skipped. The peers are contacted at once, each request within the
+    /// timeout of the report peers.
+    pub async fn exchange(&self, logger: &slog::Logger) {
+        let own = self
+            .quotes()
+            .and_then(|quotes| serde_json::to_vec(&quotes).ok());
+
+        let exchanges = self.peers.iter().map(|url| {
+            let own = own.clone();
+            async move {
+                let pushed = match own {
+                    Some(body) => {
+                        let request = Request::post(format!("{}{}", url, server::paths::QUOTES))
+                            .header(hyper::header::CONTENT_TYPE, "application/json")
+                            .body(Body::from(body));
+                        report::fetch(&self.client, request).await.map(|_| ())
+                    }
+                    None => Ok(()),
+                };
+
+                let request =
+                    Request::get(format!("{}{}", url, server::paths::QUOTES)).body(Body::empty());
+                let accepted = match report::fetch(&self.client, request)
+                    .await
+                    .map_err(Error::Peer)
+                {
+                    Ok(body) => serde_json::from_slice(&body)
+                        .map_err(|e| Error::Peer(format!("unexpected response body: {}", e)))
+                        .and_then(|quotes| self.accept(quotes)),
+                    Err(e) => Err(e),
+                };
+                (url, pushed, accepted)
+            }
+        });
+
+        for (url, pushed, accepted) in join_all(exchanges).await {
+            if let Err(e) = pushed {
+                warn!(logger, "peer {} refused the quotes: {}", url, e);
+            }
+            if let Err(e) = accepted {
+                warn!(logger, "peer {} quotes failed: {}", url, e);
+            }
+        }
+    }
+
+    /// Median of the fresh node prices and the deviation of every node from it.
+    pub fn consensus(&self) -> Option<Consensus> {
+        let now = now();
+        let mut nodes: Vec<(Address, U256)> = self
+            .received
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .chain(self.quotes().as_ref())
+            .filter(|quotes| now.saturating_sub(quotes.timestamp) <= self.max_age)
+            .filter_map(|quotes| quotes.price().map(|price| (quotes.node, price)))
+            .collect();
+        nodes.sort();
+
+        let median = report::median(nodes.iter().map(|&(_, price)| price).collect())?;
+        let nodes = nodes
+            .into_iter()
+            .map(|(node, price)| {
+                let deviation = deviation(price, median);
+                NodePrice {
+                    node,
+                    price,
+                    deviation,
+                    divergent: deviation > self.max_divergence,
+                }
+            })
+            .collect();
+
+        Some(Consensus { median, nodes })
+    }
+}
+
+/// Difference between the price and the median in percent.
+fn deviation(price: U256, median: U256) -> f64 {
+    let median = median.low_u128() as f64;
+    if median == 0.0 {
+        return 0.0;
+    }
+    (price.low_u128() as f64 - median).abs() / median * 100.0
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(key: u64) -> Signer {
+        let private_key = H256::from_low_u64_be(key);
+        Signer {
+            address: ethtxsign::private_key_address(&private_key).unwrap(),
+            private_key,
+            chain_id: 1,
+        }
+    }
+
+    fn quotes(prices: &[u64]) -> Vec<Quote> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, price)| Quote {
+                source: format!("source{}", i),
+                price: (*price).into(),
+            })
+            .collect()
+    }
+
+    /// Node of key 1 trusting the keys 2 and 3, with a max age of 60s and a
+    /// max divergence of 5%.
+    fn node() -> Node {
+        Node::new(
+            signer(1),
+            vec![signer(2).address, signer(3).address],
+            Vec::new(),
+            60,
+            5.0,
+        )
+        .unwrap()
+    }
+
+    fn signed(key: u64, prices: &[u64], timestamp: u64) -> SignedQuotes {
+        SignedQuotes::sign(quotes(prices), timestamp, &signer(key)).unwrap()
+    }
+
+    #[test]
+    fn accepts_the_signed_quotes_of_a_trusted_node() {
+        let node = node();
+        node.accept(signed(2, &[100], now())).unwrap();
+        assert_eq!(node.consensus().unwrap().median, 100.into());
+    }
+
+    #[test]
+    fn refuses_the_quotes_of_an_untrusted_node() {
+        assert!(node().accept(signed(4, &[100], now())).is_err());
+    }
+
+    #[test]
+    fn refuses_quotes_not_signed_by_the_node() {
+        let node = node();
+
+        let mut tampered = signed(2, &[100], now());
+        tampered.quotes[0].price = 200.into();
+        assert!(node.accept(tampered).is_err());
+
+        let mut impersonated = signed(3, &[100], now());
+        impersonated.node = signer(2).address;
+        assert!(node.accept(impersonated).is_err());
+
+        assert!(node.consensus().is_none());
+    }
+
+    #[test]
+    fn refuses_quotes_from_beyond_the_clock_skew_and_stale_ones() {
+        let node = node();
+        assert!(node
+            .accept(signed(2, &[100], now() + MAX_CLOCK_SKEW + 5))
+            .is_err());
+        assert!(node.accept(signed(2, &[100], now() - 61)).is_err());
+        node.accept(signed(2, &[100], now() + MAX_CLOCK_SKEW - 5))
+            .unwrap();
+    }
+
+    #[test]
+    fn keeps_the_newest_quotes_of_a_node() {
+        let node = node();
+        let now = now();
+        node.accept(signed(2, &[100], now)).unwrap();
+        node.accept(signed(2, &[200], now - 5)).unwrap();
+        assert_eq!(node.consensus().unwrap().median, 100.into());
+        node.accept(signed(2, &[300], now + 1)).unwrap();
+        assert_eq!(node.consensus().unwrap().median, 300.into());
+    }
+
+    #[test]
+    fn consensus_is_the_lower_middle_node_price() {
+        let node = node();
+        // the price of a node is the median of its own quotes
+        node.publish(quotes(&[90, 100, 1000])).unwrap();
+        node.accept(signed(2, &[102], now())).unwrap();
+        node.accept(signed(3, &[110, 120], now())).unwrap();
+
+        let consensus = node.consensus().unwrap();
+        assert_eq!(consensus.median, 102.into());
+        let divergent: Vec<(Address, bool)> = consensus
+            .nodes
+            .iter()
+            .map(|n| (n.node, n.divergent))
+            .collect();
+        let mut expected = vec![
+            (signer(1).address, false),
+            (signer(2).address, false),
+            (signer(3).address, true),
+        ];
+        expected.sort();
+        assert_eq!(divergent, expected);
+        let own = consensus
+            .nodes
+            .iter()
+            .find(|n| n.node == signer(1).address)
+            .unwrap();
+        assert!((own.deviation - 100.0 * 2.0 / 102.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn consensus_ignores_the_stale_quotes() {
+        let node = node();
+        node.publish(quotes(&[100])).unwrap();
+        let stale = signed(2, &[500], now() - 61);
+        node.received.lock().unwrap().insert(stale.node, stale);
+
+        let consensus = node.consensus().unwrap();
+        assert_eq!(consensus.median, 100.into());
+        assert_eq!(consensus.nodes.len(), 1);
+    }
+}
@@ -0,0 +1,646 @@
+//! API keys, rate limits and usage counters of the public price API.
+//!
+//! Keys are stored in a sqlite file as SHA-256 hashes, the key itself is shown
+//! once by `apikey --add`. Every request takes a token from the bucket of its
+//! client IP and, with a key, from the bucket of the key.
+
+use crate::args;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Buckets kept before the full ones, then the least recently used one, are
+/// dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Wait of a bucket that is never refilled, or too slowly to tell.
+const NEVER: Duration = Duration::from_secs(u32::MAX as u64);
+
+/// How long a key looked up in the sqlite file is trusted, a revoked key is
+/// refused at most this late.
+const KEY_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Client name of the requests without a key.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Stored API key without the key itself.
+#[derive(Debug, Clone)]
+pub struct KeyInfo {
+    pub name: String,
+    /// Requests per second, the default of `--key_rate` when `None`
+    pub rate: Option<f64>,
+    /// Requests allowed at once, the default of `--key_burst` when `None`
+    pub burst: Option<f64>,
+    pub created_at: u64,
+    pub revoked: bool,
+}
+
+/// API keys in the `api_keys` table of a sqlite file.
+pub struct ApiKeys {
+    path: String,
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl ApiKeys {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path).map_err(|e| storage(path, e))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS api_keys (
+                hash TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                rate REAL,
+                burst REAL,
+                created_at INTEGER NOT NULL,
+                revoked INTEGER NOT NULL DEFAULT 0
+            )",
+            rusqlite::NO_PARAMS,
+        )
+        .map_err(|e| storage(path, e))?;
+
+        Ok(ApiKeys {
+            path: path.to_string(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Creates the key of the client and returns it, only its hash is stored.
+    pub fn add(&self, name: &str, rate: Option<f64>, burst: Option<f64>) -> Result<String> {
+        let rate = rate.map(|rate| check_limit("rate", rate)).transpose()?;
+        let burst = burst.map(|burst| check_limit("burst", burst)).transpose()?;
+        let mut random = [0u8; 24];
+        File::open("/dev/urandom")
+            .and_then(|mut urandom| urandom.read_exact(&mut random))
+            .map_err(|e| Error::Storage(format!("/dev/urandom: {}", e)))?;
+        let key = hex::encode(random);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.lock()
+            .execute(
+                "INSERT INTO api_keys (hash, name, rate, burst, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![hash(&key), name, rate, burst, now as i64],
+            )
+            .map_err(|e| storage(&self.path, e))?;
+        Ok(key)
+    }
+
+    /// Revokes the key of the client, returns whether it existed.
+    pub fn revoke(&self, name: &str) -> Result<bool> {
+        let changed = self
+            .lock()
+            .execute(
+                "UPDATE api_keys SET revoked = 1 WHERE name = ?1",
+                rusqlite::params![name],
+            )
+            .map_err(|e| storage(&self.path, e))?;
+        Ok(changed > 0)
+    }
+
+    pub fn list(&self) -> Result<Vec<KeyInfo>> {
+        self.query(
+            "SELECT name, rate, burst, created_at, revoked FROM api_keys ORDER BY name",
+            rusqlite::NO_PARAMS,
+        )
+    }
+
+    /// Active key matching the one of the request.
+    pub fn find(&self, key: &str) -> Result<Option<KeyInfo>> {
+        let keys = self.query(
+            "SELECT name, rate, burst, created_at, revoked FROM api_keys
+                WHERE hash = ?1 AND revoked = 0",
+            rusqlite::params![hash(key)],
+        )?;
+        Ok(keys.into_iter().next())
+    }
+
+    fn query<P>(&self, sql: &str, params: P) -> Result<Vec<KeyInfo>>
+    where
+        P: IntoIterator,
+        P::Item: rusqlite::ToSql,
+    {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(sql).map_err(|e| storage(&self.path, e))?;
+        let keys = stmt
+            .query_map(params, |row| {
+                Ok(KeyInfo {
+                    name: row.get(0)?,
+                    rate: row.get(1)?,
+                    burst: row.get(2)?,
+                    created_at: row.get::<_, i64>(3)? as u64,
+                    revoked: row.get(4)?,
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| storage(&self.path, e))?;
+        Ok(keys)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Rates and bursts are finite and not negative, the buckets can't be filled
+/// otherwise.
+fn check_limit(name: &'static str, value: f64) -> Result<f64> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(Error::invalid(
+            name,
+            format!("{} is not a number >= 0", value),
+        ))
+    }
+}
+
+fn limit(arg: &ArgMatches, name: &'static str) -> Result<f64> {
+    check_limit(name, args::parse(arg, name)?)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets refilled with `rate` tokens per second up to `burst`.
+#[derive(Default)]
+struct Limiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Limiter {
+    /// Takes a token of the client, the error is the time until the next one.
+    fn take(&self, client: &str, rate: f64, burst: f64) -> std::result::Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(client) {
+            // a full bucket is the same as a missing one
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < burst
+            });
+            // the clients draining their buckets are not all dropped this way
+            if buckets.len() >= MAX_BUCKETS {
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(client, _)| client.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = burst.min(bucket.tokens + elapsed * rate);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        // a tiny or zero rate overflows the duration
+        let wait = (1.0 - bucket.tokens) / rate;
+        if rate > 0.0 && wait < NEVER.as_secs_f64() {
+            Err(Duration::from_secs_f64(wait))
+        } else {
+            Err(NEVER)
+        }
+    }
+}
+
+/// Outcome of the admission of a request.
+#[derive(Debug)]
+pub enum Admission {
+    /// Name of the key or [`ANONYMOUS`]
+    Allowed(String),
+    /// The key is unknown or revoked
+    UnknownKey,
+    /// `--require_api_key` is set and the request has no key
+    KeyRequired,
+    /// The bucket of the key or the IP is empty
+    Limited {
+        client: String,
+        /// `key` or `ip`
+        limit: &'static str,
+        retry_after: Duration,
+    },
+}
+
+/// API keys, limits and usage of the public endpoints.
+pub struct Quota {
+    keys: Option<ApiKeys>,
+    require_key: bool,
+    forwarded_for: bool,
+    key_rate: f64,
+    key_burst: f64,
+    ip_rate: f64,
+    ip_burst: f64,
+    by_key: Limiter,
+    by_ip: Limiter,
+    /// Keys by hash with the time they were read, unknown keys as `None`
+    found: Mutex<HashMap<String, (Option<KeyInfo>, Instant)>>,
+    /// Responses by client and status
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    /// Rejected requests by client and limit
+    limited: Mutex<BTreeMap<(String, &'static str), u64>>,
+}
+
+impl Quota {
+    /// Keys of `--api_keys` and the limits of the arguments.
+    pub fn from_args(arg: &ArgMatches) -> Result<Self> {
+        let keys = match arg.value_of("api_keys") {
+            Some(path) => Some(ApiKeys::open(path)?),
+            None => None,
+        };
+        let require_key = arg.is_present("require_api_key");
+        if require_key && keys.is_none() {
+            return Err(Error::missing("api_keys"));
+        }
+
+        Ok(Quota {
+            keys,
+            require_key,
+            forwarded_for: arg.is_present("forwarded_for"),
+            key_rate: limit(arg, "key_rate")?,
+            key_burst: limit(arg, "key_burst")?,
+            ip_rate: limit(arg, "ip_rate")?,
+            ip_burst: limit(arg, "ip_burst")?,
+            by_key: Limiter::default(),
+            by_ip: Limiter::default(),
+            found: Mutex::new(HashMap::new()),
+            requests: Mutex::new(BTreeMap::new()),
+            limited: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Whether the client IP is taken from `X-Forwarded-For`.
+    pub fn forwarded_for(&self) -> bool {
+        self.forwarded_for
+    }
+
+    /// Takes the token of the IP, then checks the key of the request and takes
+    /// the token of the key. The IP pays for the unknown keys too, so they can't
+    /// be guessed faster than its limit.
+    pub fn admit(&self, key: Option<&str>, ip: IpAddr) -> Result<Admission> {
+        if let Err(retry_after) = self
+            .by_ip
+            .take(&ip.to_string(), self.ip_rate, self.ip_burst)
+        {
+            // the key is not checked yet
+            let limited = Admission::Limited {
+                client: ANONYMOUS.to_string(),
+                limit: "ip",
+                retry_after,
+            };
+            return Ok(self.count_limited(ANONYMOUS, "ip", limited));
+        }
+
+        let info = match (key, &self.keys) {
+            (Some(key), Some(keys)) => match self.find(keys, key)? {
+                Some(info) => Some(info),
+                None => return Ok(self.count_limited(ANONYMOUS, "key", Admission::UnknownKey)),
+            },
+            (None, _) if self.require_key => {
+                return Ok(self.count_limited(ANONYMOUS, "key", Admission::KeyRequired))
+            }
+            _ => None,
+        };
+        let client = info
+            .as_ref()
+            .map_or(ANONYMOUS.to_string(), |info| info.name.clone());
+
+        if let Some(info) = info {
+            let rate = info.rate.unwrap_or(self.key_rate);
+            let burst = info.burst.unwrap_or(self.key_burst);
+            if let Err(retry_after) = self.by_key.take(&info.name, rate, burst) {
+                let limited = Admission::Limited {
+                    client: client.clone(),
+                    limit: "key",
+                    retry_after,
+                };
+                return Ok(self.count_limited(&client, "key", limited));
+            }
+        }
+
+        Ok(Admission::Allowed(client))
+    }
+
+    /// Key of the request from the cache, read from the sqlite file at most
+    /// once per [`KEY_CACHE_TTL`] so the requests don't wait for the file.
+    fn find(&self, keys: &ApiKeys, key: &str) -> Result<Option<KeyInfo>> {
+        let hash = hash(key);
+        let now = Instant::now();
+        let cached = self
+            .lock_found()
+            .get(&hash)
+            .filter(|(_, read)| now.duration_since(*read) < KEY_CACHE_TTL)
+            .map(|(info, _)| info.clone());
+        if let Some(info) = cached {
+            return Ok(info);
+        }
+
+        let info = keys.find(key)?;
+        let mut found = self.lock_found();
+        if found.len() >= MAX_BUCKETS {
+            found.retain(|_, (_, read)| now.duration_since(*read) < KEY_CACHE_TTL);
+            if found.len() >= MAX_BUCKETS {
+                found.clear();
+            }
+        }
+        found.insert(hash, (info.clone(), now));
+        Ok(info)
+    }
+
+    fn lock_found(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Option<KeyInfo>, Instant)>> {
+        self.found
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn count_limited(&self, client: &str, limit: &'static str, admission: Admission) -> Admission {
+        *self
+            .limited
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry((client.to_string(), limit))
+            .or_default() += 1;
+        admission
+    }
+
+    /// Counts the response to the admitted client.
+    pub fn record(&self, client: &str, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry((client.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// Usage counters in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP priceoracle_api_requests_total Responses of the public api by client and status."
+        );
+        let _ = writeln!(out, "# TYPE priceoracle_api_requests_total counter");
+        for ((client, status), count) in self
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "priceoracle_api_requests_total{{client=\"{}\",status=\"{}\"}} {}",
+                label(client),
+                status,
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP priceoracle_api_rejected_total Requests rejected by the key check or the rate limits."
+        );
+        let _ = writeln!(out, "# TYPE priceoracle_api_rejected_total counter");
+        for ((client, limit), count) in self
+            .limited
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "priceoracle_api_rejected_total{{client=\"{}\",limit=\"{}\"}} {}",
+                label(client),
+                limit,
+                count
+            );
+        }
+        out
+    }
+}
+
+/// Escapes the label value of the Prometheus text format.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Key and rate limit arguments of `server` and of the stream of `service`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("api_keys")
+            .env("PO_API_KEYS")
+            .long("api_keys")
+            .help("sqlite file of the API keys managed with the apikey command"),
+        Arg::with_name("require_api_key")
+            .long("require_api_key")
+            .help("rejects the requests without a valid key"),
+        Arg::with_name("key_rate")
+            .env("PO_KEY_RATE")
+            .long("key_rate")
+            .default_value("10")
+            .help("default requests per second of a key"),
+        Arg::with_name("key_burst")
+            .env("PO_KEY_BURST")
+            .long("key_burst")
+            .default_value("20")
+            .help("default requests a key can make at once"),
+        Arg::with_name("ip_rate")
+            .env("PO_IP_RATE")
+            .long("ip_rate")
+            .default_value("5")
+            .help("requests per second of a client IP"),
+        Arg::with_name("ip_burst")
+            .env("PO_IP_BURST")
+            .long("ip_burst")
+            .default_value("10")
+            .help("requests a client IP can make at once"),
+        Arg::with_name("metrics_bind")
+            .env("PO_METRICS_BIND")
+            .long("metrics_bind")
+            .help("address:port of /metrics with the usage counters, off the public api as they name the clients"),
+        Arg::with_name("forwarded_for").long("forwarded_for").help(
+            "takes the client IP from the last X-Forwarded-For entry, only behind a trusted proxy",
+        ),
+    ]
+}
+
+/// Manages the API keys of `--api_keys`.
+pub fn run(arg: &ArgMatches) -> Result<()> {
+    let keys = ApiKeys::open(args::required(arg, "api_keys")?)?;
+
+    if let Some(name) = arg.value_of("add") {
+        let key = keys.add(
+            name,
+            args::parse_opt(arg, "rate")?,
+            args::parse_opt(arg, "burst")?,
+        )?;
+        println!("{}", key);
+    } else if let Some(name) = arg.value_of("revoke") {
+        if !keys.revoke(name)? {
+            return Err(Error::invalid("revoke", format!("no key named {}", name)));
+        }
+        println!("{} is revoked", name);
+    } else {
+        for key in keys.list()? {
+            println!(
+                "{}\trate {}\tburst {}\tcreated {}{}",
+                key.name,
+                key.rate.map_or("default".to_string(), |r| r.to_string()),
+                key.burst.map_or("default".to_string(), |b| b.to_string()),
+                key.created_at,
+                if key.revoked { "\trevoked" } else { "" }
+            );
+        }
+    }
+    Ok(())
+}
+
+fn storage(path: &str, e: rusqlite::Error) -> Error {
+    Error::Storage(format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn quota(keys: Option<ApiKeys>) -> Quota {
+        Quota {
+            keys,
+            require_key: false,
+            forwarded_for: false,
+            key_rate: 10.0,
+            key_burst: 2.0,
+            ip_rate: 10.0,
+            ip_burst: 4.0,
+            by_key: Limiter::default(),
+            by_ip: Limiter::default(),
+            found: Mutex::new(HashMap::new()),
+            requests: Mutex::new(BTreeMap::new()),
+            limited: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    #[test]
+    fn bucket_allows_the_burst_then_waits_for_a_token() {
+        let limiter = Limiter::default();
+        for _ in 0..3 {
+            assert_eq!(limiter.take("client", 2.0, 3.0), Ok(()));
+        }
+        let wait = limiter.take("client", 2.0, 3.0).unwrap_err();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        // the buckets of the other clients are full
+        assert_eq!(limiter.take("other", 2.0, 3.0), Ok(()));
+    }
+
+    #[test]
+    fn bucket_is_refilled_at_the_rate() {
+        let limiter = Limiter::default();
+        assert_eq!(limiter.take("client", 1000.0, 1.0), Ok(()));
+        assert!(limiter.take("client", 1000.0, 1.0).is_err());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(limiter.take("client", 1000.0, 1.0), Ok(()));
+    }
+
+    #[test]
+    fn empty_bucket_of_a_zero_or_tiny_rate_never_refills() {
+        let limiter = Limiter::default();
+        for rate in &[0.0, 1e-300, f64::MIN_POSITIVE, f64::NAN] {
+            let client = rate.to_string();
+            assert_eq!(limiter.take(&client, *rate, 0.0), Err(NEVER));
+        }
+    }
+
+    #[test]
+    fn limits_must_be_finite_and_not_negative() {
+        assert_eq!(check_limit("rate", 0.0).ok(), Some(0.0));
+        assert_eq!(check_limit("rate", 2.5).ok(), Some(2.5));
+        for value in &[-1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(check_limit("rate", *value).is_err());
+        }
+    }
+
+    #[test]
+    fn keys_are_admitted_by_their_own_bucket() {
+        let keys = ApiKeys::open(":memory:").unwrap();
+        assert!(keys.add("bad", Some(f64::NAN), None).is_err());
+        let key = keys.add("alice", None, None).unwrap();
+        let quota = quota(Some(keys));
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        for _ in 0..2 {
+            match quota.admit(Some(&key), ip).unwrap() {
+                Admission::Allowed(client) => assert_eq!(client, "alice"),
+                other => panic!("{:?}", other),
+            }
+        }
+        match quota.admit(Some(&key), ip).unwrap() {
+            Admission::Limited { client, limit, .. } => {
+                assert_eq!((client.as_str(), limit), ("alice", "key"))
+            }
+            other => panic!("{:?}", other),
+        }
+        match quota.admit(Some("unknown"), ip).unwrap() {
+            Admission::UnknownKey => {}
+            other => panic!("{:?}", other),
+        }
+        // the IP paid for every request
+        match quota.admit(None, ip).unwrap() {
+            Admission::Limited { limit, .. } => assert_eq!(limit, "ip"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn looked_up_keys_are_cached() {
+        let keys = ApiKeys::open(":memory:").unwrap();
+        let key = keys.add("alice", None, None).unwrap();
+        let quota = quota(Some(keys));
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        assert!(matches!(
+            quota.admit(Some(&key), ip).unwrap(),
+            Admission::Allowed(_)
+        ));
+        quota.keys.as_ref().unwrap().revoke("alice").unwrap();
+        // refused only once the cached lookup expires
+        assert!(matches!(
+            quota.admit(Some(&key), ip).unwrap(),
+            Admission::Allowed(_)
+        ));
+        quota.lock_found().clear();
+        assert!(matches!(
+            quota.admit(Some(&key), ip).unwrap(),
+            Admission::UnknownKey
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! Contract events decoded into typed records for the [`crate::sink`]s.
+
+use crate::bindings::price_oracle::events;
+use crate::error::Result;
+use serde::Serialize;
+use web3::types::{Address, Log, H256};
+
+/// Decoded fields of the contract event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ContractEvent {
+    PriceChanged {
+        round_id: String,
+        /// BTC for 1 ETH multiplied by 10^18
+        answer: String,
+        updated_at: u64,
+        answered_in_round: String,
+    },
+    AdminChanged {
+        admin: Address,
+        value: bool,
+    },
+    ReporterChanged {
+        reporter: Address,
+        value: bool,
+    },
+    QuorumChanged {
+        quorum: String,
+    },
+}
+
+impl ContractEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContractEvent::PriceChanged { .. } => "PriceChanged",
+            ContractEvent::AdminChanged { .. } => "AdminChanged",
+            ContractEvent::ReporterChanged { .. } => "ReporterChanged",
+            ContractEvent::QuorumChanged { .. } => "QuorumChanged",
+        }
+    }
+
+    /// Decodes the log by its topic, `None` for the events of other contracts.
+    pub fn decode(log: &Log) -> Result<Option<Self>> {
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => return Ok(None),
+        };
+
+        let event = if topic == events::PriceChanged::topic() {
+            let e = events::PriceChanged::decode(log)?;
+            ContractEvent::PriceChanged {
+                round_id: e.round_id.to_string(),
+                answer: e.answer.to_string(),
+                updated_at: e.updated_at.low_u64(),
+                answered_in_round: e.answered_in_round.to_string(),
+            }
+        } else if topic == events::AdminChanged::topic() {
+            let e = events::AdminChanged::decode(log)?;
+            ContractEvent::AdminChanged {
+                admin: e.admin,
+                value: e.value,
+            }
+        } else if topic == events::ReporterChanged::topic() {
+            let e = events::ReporterChanged::decode(log)?;
+            ContractEvent::ReporterChanged {
+                reporter: e.reporter,
+                value: e.value,
+            }
+        } else if topic == events::QuorumChanged::topic() {
+            let e = events::QuorumChanged::decode(log)?;
+            ContractEvent::QuorumChanged {
+                quorum: e.quorum.to_string(),
+            }
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Topics of all the [`ContractEvent`]s.
+pub fn topics() -> Vec<H256> {
+    vec![
+        events::PriceChanged::topic(),
+        events::AdminChanged::topic(),
+        events::ReporterChanged::topic(),
+        events::QuorumChanged::topic(),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Confirmed,
+    /// Undoes the confirmed record with the same `block_hash` and `log_index`
+    Retracted,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Confirmed => "confirmed",
+            Status::Retracted => "retracted",
+        }
+    }
+}
+
+/// Contract event with its position in the chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventRecord {
+    pub status: Status,
+    pub event: &'static str,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub transaction_hash: Option<H256>,
+    pub log_index: u64,
+    /// Unix timestamp of the block, `None` when the block was not fetched
+    pub timestamp: Option<u64>,
+    pub fields: ContractEvent,
+}
+
+impl EventRecord {
+    /// `None` for the logs of other contracts or without a block.
+    pub fn new(status: Status, log: &Log, timestamp: Option<u64>) -> Result<Option<Self>> {
+        let (block_number, block_hash) = match (log.block_number, log.block_hash) {
+            (Some(number), Some(hash)) => (number.as_u64(), hash),
+            _ => return Ok(None),
+        };
+        Ok(ContractEvent::decode(log)?.map(|fields| EventRecord {
+            status,
+            event: fields.name(),
+            block_number,
+            block_hash,
+            transaction_hash: log.transaction_hash,
+            log_index: log.log_index.unwrap_or_default().low_u64(),
+            timestamp,
+            fields,
+        }))
+    }
+}
@@ -0,0 +1,407 @@
+//! Reorg-safe stream of the contract logs.
+//!
+//! [`ReorgBuffer`] holds the logs until they have enough confirmations and
+//! keeps the hashes of the recent blocks. A log removed by the node or mined
+//! in a block replaced by a reorg is dropped while pending and retracted once
+//! it was confirmed.
+//!
+//! A block whose parent hash doesn't match the known block below it replaces
+//! that block too. The reader walks the parents down from there with
+//! [`ReorgBuffer::unchecked_block`] and [`ReorgBuffer::push_block`] until they
+//! meet the known chain, so a reorg deeper than the head is seen in both the
+//! subscription and the polling modes.
+
+use std::collections::BTreeMap;
+use web3::types::{Log, H256, U256};
+
+/// Change of the confirmed stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The log has the required confirmations
+    Confirmed(Log),
+    /// The confirmed log is no longer in the canonical chain
+    Retracted(Log),
+}
+
+pub struct ReorgBuffer {
+    /// Blocks on top of the block of the log, 1 confirms the logs of the head
+    confirmations: u64,
+    /// Number of the recent blocks whose hashes and confirmed logs are kept
+    window: u64,
+    head: u64,
+    hashes: BTreeMap<u64, H256>,
+    /// Hashes of the chain of the head, linked by the parent hashes
+    chain: BTreeMap<u64, H256>,
+    /// Block whose parent is not matched with the known chain yet
+    unchecked: Option<(u64, H256)>,
+    pending: Vec<Log>,
+    confirmed: Vec<Log>,
+}
+
+impl ReorgBuffer {
+    pub fn new(confirmations: u64, window: u64) -> Self {
+        ReorgBuffer {
+            confirmations,
+            window,
+            head: 0,
+            hashes: BTreeMap::new(),
+            chain: BTreeMap::new(),
+            unchecked: None,
+            pending: Vec::new(),
+            confirmed: Vec::new(),
+        }
+    }
+
+    /// Adds the log of the subscription, logs without a block are skipped.
+    pub fn push_log(&mut self, log: Log) -> Vec<Change> {
+        let (number, hash) = match position(&log) {
+            Some((number, hash, _)) => (number, hash),
+            None => return Vec::new(),
+        };
+
+        if log.removed == Some(true) {
+            let key = position(&log);
+            self.pending.retain(|l| position(l) != key);
+            return match self.confirmed.iter().position(|l| position(l) == key) {
+                Some(i) => vec![Change::Retracted(self.confirmed.remove(i))],
+                None => Vec::new(),
+            };
+        }
+
+        let mut changes = self.observe_block(number, hash);
+        let key = position(&log);
+        let known = self
+            .pending
+            .iter()
+            .chain(self.confirmed.iter())
+            .any(|l| position(l) == key);
+        if !known {
+            self.pending.push(log);
+        }
+        changes.extend(self.confirm());
+        changes
+    }
+
+    /// Adds the new head, a head at a known height with another hash or below
+    /// the current head is a reorg, and so is a parent hash that doesn't match
+    /// the known block below it.
+    pub fn push_head(&mut self, number: u64, hash: H256, parent_hash: H256) -> Vec<Change> {
+        let mut changes = self.observe_block(number, hash);
+        if number < self.head {
+            changes.extend(self.invalidate(number + 1, None));
+        }
+        self.head = number;
+        self.chain.insert(number, hash);
+
+        let oldest = self.head.saturating_sub(self.window);
+        self.hashes = self.hashes.split_off(&oldest);
+        self.chain = self.chain.split_off(&oldest);
+        self.confirmed
+            .retain(|l| position(l).map_or(false, |(n, _, _)| n >= oldest));
+
+        changes.extend(self.check_parent(number, parent_hash));
+        changes.extend(self.confirm());
+        changes
+    }
+
+    /// Adds the parent hash of [`ReorgBuffer::unchecked_block`], the block of
+    /// `hash` itself is already in the chain of the head.
+    pub fn push_block(&mut self, number: u64, hash: H256, parent_hash: H256) -> Vec<Change> {
+        if self.unchecked != Some((number, hash)) {
+            return Vec::new();
+        }
+        let mut changes = self.check_parent(number, parent_hash);
+        changes.extend(self.confirm());
+        changes
+    }
+
+    /// Block of the chain of the head whose parent is not matched with the
+    /// known blocks yet, the reader fetches it by the hash and pushes it with
+    /// [`ReorgBuffer::push_block`].
+    pub fn unchecked_block(&self) -> Option<(u64, H256)> {
+        self.unchecked
+    }
+
+    /// Highest block whose logs are all confirmed, `None` before the first head.
+    pub fn confirmed_block(&self) -> Option<u64> {
+        if self.head == 0 {
+            return None;
+        }
+        (self.head + 1).checked_sub(self.confirmations.max(1))
+    }
+
+    /// Highest block whose logs are all emitted, the confirmed block below the
+    /// pending logs. `None` while the walk down the parents may still replace
+    /// the blocks, as the logs are not confirmed meanwhile.
+    pub fn emitted_block(&self) -> Option<u64> {
+        if self.unchecked.is_some() {
+            return None;
+        }
+        let confirmed = self.confirmed_block()?;
+        let lowest_pending = self
+            .pending
+            .iter()
+            .filter_map(|l| position(l).map(|(n, _, _)| n))
+            .min();
+        match lowest_pending {
+            Some(lowest) => Some(confirmed.min(lowest.checked_sub(1)?)),
+            None => Some(confirmed),
+        }
+    }
+
+    fn observe_block(&mut self, number: u64, hash: H256) -> Vec<Change> {
+        match self.hashes.insert(number, hash) {
+            Some(known) if known != hash => self.invalidate(number, Some(hash)),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Takes `parent_hash` as the block below `number` and continues the walk
+    /// down until it meets the chain of the previous heads. A block replaced
+    /// this way has its logs dropped or retracted.
+    fn check_parent(&mut self, number: u64, parent_hash: H256) -> Vec<Change> {
+        self.unchecked = None;
+        let below = match number.checked_sub(1) {
+            Some(below) if below >= self.head.saturating_sub(self.window) => below,
+            _ => return Vec::new(),
+        };
+        if self.chain.get(&below) == Some(&parent_hash) {
+            return Vec::new();
+        }
+
+        // nothing to meet below the first head
+        if self.chain.range(..below).next().is_some() {
+            self.unchecked = Some((below, parent_hash));
+        }
+        self.chain.insert(below, parent_hash);
+        self.hashes.insert(below, parent_hash);
+        self.retract(|n, h| n == below && h != parent_hash)
+    }
+
+    /// Drops the hashes and the logs from the height on that are not in the
+    /// block `hash`, the confirmed ones are retracted.
+    fn invalidate(&mut self, from: u64, hash: Option<H256>) -> Vec<Change> {
+        self.hashes.split_off(&(from + 1));
+        self.chain.split_off(&(from + 1));
+        if hash.is_none() {
+            self.hashes.remove(&from);
+        }
+        if self.chain.get(&from).copied() != hash {
+            self.chain.remove(&from);
+        }
+        self.retract(|n, h| n > from || (n == from && Some(h) != hash))
+    }
+
+    /// Drops the pending logs of the stale blocks and retracts the confirmed
+    /// ones.
+    fn retract(&mut self, stale: impl Fn(u64, H256) -> bool) -> Vec<Change> {
+        let stale = |log: &Log| position(log).map_or(true, |(n, h, _)| stale(n, h));
+        self.pending.retain(|l| !stale(l));
+
+        let (retracted, kept) = self.confirmed.drain(..).partition(|l| stale(l));
+        self.confirmed = kept;
+        retracted.into_iter().map(Change::Retracted).collect()
+    }
+
+    /// Confirms the logs with enough blocks on top, not while the walk down
+    /// the parents may still replace their blocks.
+    fn confirm(&mut self) -> Vec<Change> {
+        if self.unchecked.is_some() {
+            return Vec::new();
+        }
+        let (head, confirmations, hashes) = (self.head, self.confirmations, &self.hashes);
+        // logs of the blocks replaced at the same height are dropped
+        self.pending.retain(|l| match position(l) {
+            Some((n, h, _)) => hashes.get(&n).map_or(true, |known| *known == h),
+            None => false,
+        });
+
+        let (mut ready, pending): (Vec<Log>, Vec<Log>) =
+            self.pending.drain(..).partition(|l| match position(l) {
+                Some((n, _, _)) => head + 1 >= n + confirmations,
+                None => false,
+            });
+        self.pending = pending;
+
+        ready.sort_by_key(|l| position(l).map(|(n, _, i)| (n, i)));
+        self.confirmed.extend(ready.iter().cloned());
+        ready.into_iter().map(Change::Confirmed).collect()
+    }
+}
+
+/// Block number, block hash and log index of the mined log.
+fn position(log: &Log) -> Option<(u64, H256, U256)> {
+    Some((
+        log.block_number?.as_u64(),
+        log.block_hash?,
+        log.log_index.unwrap_or_default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hash of the block at the height on the fork, forks differ in the first byte.
+    fn hash(number: u64, fork: u8) -> H256 {
+        let mut hash = H256::from_low_u64_be(number);
+        hash.as_bytes_mut()[0] = fork;
+        hash
+    }
+
+    fn log(number: u64, fork: u8, index: u64) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": "0x0000000000000000000000000000000000000000",
+            "topics": [],
+            "data": "0x",
+            "blockHash": hash(number, fork),
+            "blockNumber": format!("{:#x}", number),
+            "logIndex": format!("{:#x}", index),
+            "removed": false,
+        }))
+        .unwrap()
+    }
+
+    fn removed(log: &Log) -> Log {
+        Log {
+            removed: Some(true),
+            ..log.clone()
+        }
+    }
+
+    /// Pushes the heads `from..=to` of the fork, each child of the previous one.
+    fn heads(buffer: &mut ReorgBuffer, from: u64, to: u64, fork: u8) -> Vec<Change> {
+        (from..=to)
+            .flat_map(|n| buffer.push_head(n, hash(n, fork), hash(n - 1, fork)))
+            .collect()
+    }
+
+    #[test]
+    fn removed_pending_log_is_dropped() {
+        let mut buffer = ReorgBuffer::new(3, 64);
+        heads(&mut buffer, 1, 10, b'a');
+        let log = log(10, b'a', 0);
+        assert!(buffer.push_log(log.clone()).is_empty());
+        assert!(buffer.push_log(removed(&log)).is_empty());
+        assert!(heads(&mut buffer, 11, 13, b'a').is_empty());
+    }
+
+    #[test]
+    fn removed_confirmed_log_is_retracted() {
+        let mut buffer = ReorgBuffer::new(1, 64);
+        heads(&mut buffer, 1, 10, b'a');
+        let log = log(10, b'a', 0);
+        assert_eq!(
+            buffer.push_log(log.clone()),
+            vec![Change::Confirmed(log.clone())]
+        );
+        assert_eq!(buffer.push_log(removed(&log)), vec![Change::Retracted(log)]);
+    }
+
+    #[test]
+    fn head_replaced_at_the_same_height_retracts_its_logs() {
+        let mut buffer = ReorgBuffer::new(1, 64);
+        heads(&mut buffer, 1, 11, b'a');
+        let log = log(11, b'a', 0);
+        assert_eq!(
+            buffer.push_log(log.clone()),
+            vec![Change::Confirmed(log.clone())]
+        );
+        assert_eq!(
+            buffer.push_head(11, hash(11, b'b'), hash(10, b'a')),
+            vec![Change::Retracted(log)]
+        );
+        assert_eq!(buffer.unchecked_block(), None);
+    }
+
+    #[test]
+    fn shorter_head_retracts_the_logs_above_it() {
+        let mut buffer = ReorgBuffer::new(1, 64);
+        heads(&mut buffer, 1, 12, b'a');
+        let log = log(12, b'a', 0);
+        assert_eq!(
+            buffer.push_log(log.clone()),
+            vec![Change::Confirmed(log.clone())]
+        );
+        assert_eq!(
+            buffer.push_head(11, hash(11, b'b'), hash(10, b'a')),
+            vec![Change::Retracted(log)]
+        );
+        assert_eq!(buffer.confirmed_block(), Some(11));
+    }
+
+    #[test]
+    fn deep_reorg_is_resolved_by_the_walk_down_the_parents() {
+        let mut buffer = ReorgBuffer::new(1, 64);
+        heads(&mut buffer, 1, 13, b'a');
+        let replaced = log(11, b'a', 0);
+        assert_eq!(
+            buffer.push_log(replaced.clone()),
+            vec![Change::Confirmed(replaced.clone())]
+        );
+
+        // the fork starts above block 10
+        assert!(buffer
+            .push_head(14, hash(14, b'b'), hash(13, b'b'))
+            .is_empty());
+        assert_eq!(buffer.unchecked_block(), Some((13, hash(13, b'b'))));
+        assert_eq!(buffer.emitted_block(), None);
+
+        // logs of the new chain wait for the walk
+        let new = log(14, b'b', 0);
+        assert!(buffer.push_log(new.clone()).is_empty());
+
+        assert!(buffer
+            .push_block(13, hash(13, b'b'), hash(12, b'b'))
+            .is_empty());
+        assert_eq!(
+            buffer.push_block(12, hash(12, b'b'), hash(11, b'b')),
+            vec![Change::Retracted(replaced)]
+        );
+        assert_eq!(buffer.unchecked_block(), Some((11, hash(11, b'b'))));
+        assert_eq!(
+            buffer.push_block(11, hash(11, b'b'), hash(10, b'a')),
+            vec![Change::Confirmed(new)]
+        );
+        assert_eq!(buffer.unchecked_block(), None);
+        assert_eq!(buffer.emitted_block(), Some(14));
+    }
+
+    #[test]
+    fn gap_in_the_heads_is_walked_down() {
+        let mut buffer = ReorgBuffer::new(1, 64);
+        heads(&mut buffer, 1, 10, b'a');
+        assert!(buffer
+            .push_head(13, hash(13, b'a'), hash(12, b'a'))
+            .is_empty());
+        assert_eq!(buffer.unchecked_block(), Some((12, hash(12, b'a'))));
+
+        let log = log(13, b'a', 0);
+        assert!(buffer.push_log(log.clone()).is_empty());
+        assert!(buffer
+            .push_block(12, hash(12, b'a'), hash(11, b'a'))
+            .is_empty());
+        assert_eq!(
+            buffer.push_block(11, hash(11, b'a'), hash(10, b'a')),
+            vec![Change::Confirmed(log)]
+        );
+        assert_eq!(buffer.unchecked_block(), None);
+    }
+
+    #[test]
+    fn log_is_confirmed_with_the_confirmations_on_top() {
+        let mut buffer = ReorgBuffer::new(3, 64);
+        heads(&mut buffer, 1, 10, b'a');
+        let log = log(10, b'a', 0);
+        assert!(buffer.push_log(log.clone()).is_empty());
+        // head + 1 >= number + confirmations
+        assert!(heads(&mut buffer, 11, 11, b'a').is_empty());
+        assert_eq!(buffer.emitted_block(), Some(9));
+        assert_eq!(
+            heads(&mut buffer, 12, 12, b'a'),
+            vec![Change::Confirmed(log)]
+        );
+        assert_eq!(buffer.confirmed_block(), Some(10));
+        assert_eq!(buffer.emitted_block(), Some(10));
+    }
+}
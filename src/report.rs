@@ -0,0 +1,504 @@
+//! Off-chain reporting between several `service` instances.
+//!
+//! Every reporter signs its observation of the price for the next round and
+//! serves it at `GET /v1/observation`. The leader collects the observations of
+//! the peers, takes the median and asks the peers to sign it at
+//! `POST /v1/report`. A peer signs the median only when it is within
+//! `max_deviation` of its own observation. The leader submits the report to
+//! the contract once it has the quorum of signatures.
+
+use crate::client::Signer;
+use crate::error::{Error, Result};
+use crate::openapi::{self, Endpoint};
+use crate::server;
+use bytes::buf::BufExt as _;
+use ethtxsign::abi::{self, Token};
+use futures::future::join_all;
+use hyper::client::HttpConnector;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server, StatusCode};
+use hyper_tls::HttpsConnector;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::timeout;
+use web3::types::{Address, Bytes, H256, U256};
+
+/// Max time a peer has to answer a request, of the leader or of the quote
+/// exchange.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answer for the round signed by the reporter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Observation {
+    #[schemars(schema_with = "openapi::uint256")]
+    pub round_id: U256,
+    #[schemars(schema_with = "openapi::uint256")]
+    pub answer: U256,
+    #[schemars(schema_with = "openapi::address")]
+    pub reporter: Address,
+    /// Signature of the `reportDigest` of the round and the answer
+    #[schemars(schema_with = "openapi::bytes")]
+    pub signature: Bytes,
+}
+
+impl Observation {
+    pub fn sign(contract: Address, round_id: U256, answer: U256, signer: &Signer) -> Result<Self> {
+        let digest = report_digest(contract, round_id, answer);
+        Ok(Observation {
+            round_id,
+            answer,
+            reporter: signer.address,
+            signature: Bytes(ethtxsign::sign_hash(&digest, &signer.private_key)?),
+        })
+    }
+
+    /// Checks that the signature is made by the reporter.
+    pub fn verify(&self, contract: Address) -> Result<()> {
+        let digest = report_digest(contract, self.round_id, self.answer);
+        let signer = ethtxsign::recover_address(&digest, &self.signature.0)?;
+        if signer != self.reporter {
+            return Err(Error::Quorum(format!(
+                "observation of {:?} is signed by {:?}",
+                self.reporter, signer
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Answer the leader asks the peers to sign.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReportRequest {
+    #[schemars(schema_with = "openapi::uint256")]
+    pub round_id: U256,
+    #[schemars(schema_with = "openapi::uint256")]
+    pub answer: U256,
+}
+
+/// `reportDigest(roundId, answer)` of the contract.
+pub fn report_digest(contract: Address, round_id: U256, answer: U256) -> H256 {
+    let data = abi::encode(&[
+        Token::Address(contract),
+        Token::Uint(round_id),
+        Token::Uint(answer),
+    ]);
+    H256::from_slice(&ethtxsign::keccak256_hash(&data))
+}
+
+/// Median of the answers, the lower middle answer for an even count.
+pub fn median(mut answers: Vec<U256>) -> Option<U256> {
+    answers.sort();
+    if answers.is_empty() {
+        None
+    } else {
+        Some(answers[(answers.len() - 1) / 2])
+    }
+}
+
+/// Sorts the signatures by the reporter and drops the repeated reporters,
+/// `submitReport` requires strictly ascending signers.
+pub fn sort_signatures(signatures: &mut Vec<Observation>) {
+    signatures.sort_by_key(|o| o.reporter);
+    signatures.dedup_by_key(|o| o.reporter);
+}
+
+/// Concatenates the signatures in the order `submitReport` expects, the
+/// observations must be sorted by [`sort_signatures`].
+pub fn concat_signatures(signatures: &[Observation]) -> Vec<u8> {
+    signatures
+        .iter()
+        .flat_map(|o| o.signature.0.iter().cloned())
+        .collect()
+}
+
+/// Reporter state shared by the service loop and the reporter endpoints.
+pub struct Reporter {
+    contract: Address,
+    signer: Signer,
+    /// Max difference in percent between the own observation and the answer
+    /// the reporter signs
+    max_deviation: f64,
+    observation: Mutex<Option<Observation>>,
+}
+
+impl Reporter {
+    pub fn new(contract: Address, signer: Signer, max_deviation: f64) -> Result<Self> {
+        if ethtxsign::private_key_address(&signer.private_key)? != signer.address {
+            return Err(Error::invalid(
+                "private_key",
+                "the key does not belong to --from_addr",
+            ));
+        }
+
+        Ok(Reporter {
+            contract,
+            signer,
+            max_deviation,
+            observation: Mutex::new(None),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address
+    }
+
+    /// Signs and keeps the own observation of the round.
+    pub fn observe(&self, round_id: U256, answer: U256) -> Result<Observation> {
+        let observation = Observation::sign(self.contract, round_id, answer, &self.signer)?;
+        *self
+            .observation
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(observation.clone());
+        Ok(observation)
+    }
+
+    /// Returns the last own observation.
+    pub fn observation(&self) -> Option<Observation> {
+        self.observation
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Signs the answer of the leader when it is within `max_deviation` of the
+    /// own observation of the same round, compared in hundredths of a percent.
+    pub fn sign_report(&self, request: &ReportRequest) -> Result<Observation> {
+        let own = self
+            .observation()
+            .ok_or_else(|| Error::Quorum("no observation yet".to_string()))?;
+        if own.round_id != request.round_id {
+            return Err(Error::Quorum(format!(
+                "observed round {} but the report is for round {}",
+                own.round_id, request.round_id
+            )));
+        }
+
+        if own.answer.is_zero() {
+            return Err(Error::Quorum(format!(
+                "observed a zero answer for round {}",
+                own.round_id
+            )));
+        }
+        let difference = if request.answer > own.answer {
+            request.answer - own.answer
+        } else {
+            own.answer - request.answer
+        };
+        // difference / own > max_deviation in basis points, without the rounding
+        let difference = difference.saturating_mul(U256::from(10_000));
+        let max_deviation = U256::from((self.max_deviation * 100.0) as u64);
+        if difference > own.answer.saturating_mul(max_deviation) {
+            let deviation = difference / own.answer;
+            return Err(Error::Quorum(format!(
+                "answer {} deviates {}.{:02}% from the observed {}",
+                request.answer,
+                deviation / 100,
+                (deviation % 100).low_u64(),
+                own.answer
+            )));
+        }
+
+        Observation::sign(
+            self.contract,
+            request.round_id,
+            request.answer,
+            &self.signer,
+        )
+    }
+
+    /// Collects the observations of the round, takes the median and gathers the
+    /// signatures of the median. Returns the median and the valid signatures
+    /// sorted by the reporter.
+    pub async fn aggregate(
+        &self,
+        logger: &slog::Logger,
+        peers: &Peers,
+        own: Observation,
+    ) -> Result<(U256, Vec<Observation>)> {
+        let round_id = own.round_id;
+        let mut observations = peers.observations(logger, self.contract, round_id).await;
+        observations.push(own);
+
+        let answer = median(observations.iter().map(|o| o.answer).collect())
+            .ok_or_else(|| Error::Quorum("no observations".to_string()))?;
+        debug!(
+            logger,
+            "round {} median {} of {} observations",
+            round_id,
+            answer,
+            observations.len()
+        );
+
+        let request = ReportRequest { round_id, answer };
+        let mut signatures = peers.signatures(logger, self.contract, &request).await;
+        match self.sign_report(&request) {
+            Ok(signature) => signatures.push(signature),
+            Err(e) => warn!(logger, "own signature is refused: {}", e),
+        }
+
+        sort_signatures(&mut signatures);
+
+        Ok((answer, signatures))
+    }
+}
+
+/// Other reporters the leader exchanges the reports with.
+pub struct Peers {
+    urls: Vec<String>,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Peers {
+    /// `urls` are the base urls of the reporter endpoints of the peers.
+    pub fn new(urls: Vec<String>) -> Self {
+        Peers {
+            urls,
+            client: Client::builder().build(HttpsConnector::new()),
+        }
+    }
+
+    /// Returns the verified observations of the round, failed peers are skipped.
+    /// The peers are asked at once, each within [`PEER_TIMEOUT`].
+    pub async fn observations(
+        &self,
+        logger: &slog::Logger,
+        contract: Address,
+        round_id: U256,
+    ) -> Vec<Observation> {
+        let fetches = self.urls.iter().map(|url| async move {
+            let request =
+                Request::get(format!("{}{}", url, server::paths::OBSERVATION)).body(Body::empty());
+            (url, self.fetch(request).await)
+        });
+
+        let mut observations = Vec::new();
+        for (url, fetched) in join_all(fetches).await {
+            match fetched {
+                Ok(o) if o.round_id != round_id => debug!(
+                    logger,
+                    "peer {} observed round {} instead of {}", url, o.round_id, round_id
+                ),
+                Ok(o) => match o.verify(contract) {
+                    Ok(()) => observations.push(o),
+                    Err(e) => warn!(logger, "peer {}: {}", url, e),
+                },
+                Err(e) => warn!(logger, "peer {} observation failed: {}", url, e),
+            }
+        }
+        observations
+    }
+
+    /// Returns the verified signatures of the answer, refusals are skipped. The
+    /// peers are asked at once, each within [`PEER_TIMEOUT`].
+    pub async fn signatures(
+        &self,
+        logger: &slog::Logger,
+        contract: Address,
+        request: &ReportRequest,
+    ) -> Vec<Observation> {
+        let body = serde_json::to_vec(request).unwrap_or_default();
+        let fetches = self.urls.iter().map(|url| {
+            let http_request = Request::post(format!("{}{}", url, server::paths::REPORT))
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.clone()));
+            async move { (url, self.fetch(http_request).await) }
+        });
+
+        let mut signatures = Vec::new();
+        for (url, fetched) in join_all(fetches).await {
+            match fetched {
+                Ok(o) if o.round_id != request.round_id || o.answer != request.answer => {
+                    warn!(logger, "peer {} signed another report {:?}", url, o)
+                }
+                Ok(o) => match o.verify(contract) {
+                    Ok(()) => signatures.push(o),
+                    Err(e) => warn!(logger, "peer {}: {}", url, e),
+                },
+                Err(e) => warn!(logger, "peer {} refused to sign: {}", url, e),
+            }
+        }
+        signatures
+    }
+
+    async fn fetch(
+        &self,
+        request: std::result::Result<Request<Body>, hyper::http::Error>,
+    ) -> Result<Observation> {
+        let body = fetch(&self.client, request).await.map_err(Error::Quorum)?;
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::Quorum(format!("unexpected response body: {}", e)))
+    }
+}
+
+/// Sends the request to a peer and returns the body of the successful
+/// response, the error is the reason of the failure. The peer has
+/// [`PEER_TIMEOUT`] for the whole response.
+pub(crate) async fn fetch(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    request: std::result::Result<Request<Body>, hyper::http::Error>,
+) -> std::result::Result<Vec<u8>, String> {
+    let request = request.map_err(|e| e.to_string())?;
+    let (status, body) = timeout(PEER_TIMEOUT, async {
+        let resp = client.request(request).await?;
+        let status = resp.status();
+        hyper::body::to_bytes(resp).await.map(|body| (status, body))
+    })
+    .await
+    .map_err(|_| format!("no answer in {:?}", PEER_TIMEOUT))?
+    .map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        let reason = String::from_utf8_lossy(&body);
+        return Err(format!("status {}: {}", status, reason));
+    }
+
+    Ok(body.to_vec())
+}
+
+async fn routes(
+    reporter: Arc<Reporter>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    match openapi::endpoint(openapi::REPORTER_ROUTES, req.method(), req.uri().path()) {
+        Some(Endpoint::Observation) => Ok(match reporter.observation() {
+            Some(observation) => server::json(StatusCode::OK, &observation),
+            None => server::error_json(StatusCode::NOT_FOUND, "no observation yet"),
+        }),
+        Some(Endpoint::Report) => {
+            let body = hyper::body::aggregate(req).await?;
+            let request: ReportRequest = match serde_json::from_reader(body.reader()) {
+                Ok(request) => request,
+                Err(e) => return Ok(server::error_json(StatusCode::BAD_REQUEST, e)),
+            };
+            Ok(match reporter.sign_report(&request) {
+                Ok(signature) => server::json(StatusCode::OK, &signature),
+                Err(e) => server::error_json(StatusCode::CONFLICT, e),
+            })
+        }
+        _ => {
+            let mut not_found = Response::default();
+            *not_found.status_mut() = StatusCode::NOT_FOUND;
+            Ok(not_found)
+        }
+    }
+}
+
+/// Serves the reporter endpoints to the leader.
+pub async fn serve(logger: slog::Logger, addr: SocketAddr, reporter: Arc<Reporter>) -> Result<()> {
+    let service = make_service_fn(move |_| {
+        let reporter = reporter.clone();
+        async move { Ok::<_, hyper::Error>(service_fn(move |req| routes(reporter.clone(), req))) }
+    });
+
+    info!(logger, "reporter listening on http://{}", addr);
+    Server::bind(&addr).serve(service).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(key: u64) -> Signer {
+        let private_key = H256::from_low_u64_be(key);
+        Signer {
+            address: ethtxsign::private_key_address(&private_key).unwrap(),
+            private_key,
+            chain_id: 1,
+        }
+    }
+
+    fn contract() -> Address {
+        Address::from_low_u64_be(0xc0)
+    }
+
+    /// Reporter of key 1 that observed 10000 for round 1.
+    fn observed(max_deviation: f64) -> Reporter {
+        let reporter = Reporter::new(contract(), signer(1), max_deviation).unwrap();
+        reporter.observe(1.into(), 10_000.into()).unwrap();
+        reporter
+    }
+
+    fn sign_report(reporter: &Reporter, answer: u64) -> Result<Observation> {
+        reporter.sign_report(&ReportRequest {
+            round_id: 1.into(),
+            answer: answer.into(),
+        })
+    }
+
+    fn answers(answers: &[u64]) -> Vec<U256> {
+        answers.iter().map(|&a| a.into()).collect()
+    }
+
+    #[test]
+    fn median_is_the_lower_middle_answer() {
+        assert_eq!(median(Vec::new()), None);
+        assert_eq!(median(answers(&[7])), Some(7.into()));
+        assert_eq!(median(answers(&[30, 10, 20])), Some(20.into()));
+        assert_eq!(median(answers(&[40, 10, 30, 20])), Some(20.into()));
+        assert_eq!(median(answers(&[5, 5, 1, 9])), Some(5.into()));
+    }
+
+    #[test]
+    fn signs_the_answers_within_the_max_deviation() {
+        let reporter = observed(1.0);
+        assert!(sign_report(&reporter, 10_000).is_ok());
+        assert!(sign_report(&reporter, 10_100).is_ok());
+        assert!(sign_report(&reporter, 9_900).is_ok());
+        assert!(sign_report(&reporter, 10_101).is_err());
+        assert!(sign_report(&reporter, 9_899).is_err());
+
+        let signature = sign_report(&reporter, 10_100).unwrap();
+        assert_eq!(signature.reporter, signer(1).address);
+        assert_eq!(signature.answer, 10_100.into());
+        assert!(signature.verify(contract()).is_ok());
+        assert!(signature.verify(Address::from_low_u64_be(0xc1)).is_err());
+    }
+
+    #[test]
+    fn compares_the_deviation_in_basis_points() {
+        let reporter = observed(0.5);
+        assert!(sign_report(&reporter, 10_050).is_ok());
+        assert!(sign_report(&reporter, 10_051).is_err());
+
+        let reporter = observed(0.0);
+        assert!(sign_report(&reporter, 10_000).is_ok());
+        assert!(sign_report(&reporter, 10_001).is_err());
+    }
+
+    #[test]
+    fn refuses_other_rounds_and_zero_observations() {
+        let reporter = observed(1.0);
+        let request = ReportRequest {
+            round_id: 2.into(),
+            answer: 10_000.into(),
+        };
+        assert!(reporter.sign_report(&request).is_err());
+
+        let reporter = Reporter::new(contract(), signer(1), 1.0).unwrap();
+        assert!(sign_report(&reporter, 10_000).is_err());
+        reporter.observe(1.into(), U256::zero()).unwrap();
+        assert!(sign_report(&reporter, 0).is_err());
+    }
+
+    #[test]
+    fn sorts_the_signatures_by_strictly_ascending_reporters() {
+        let sign = |key| Observation::sign(contract(), 1.into(), 100.into(), &signer(key)).unwrap();
+        let mut signatures = vec![sign(3), sign(1), sign(4), sign(2), sign(1), sign(3)];
+        sort_signatures(&mut signatures);
+
+        assert_eq!(signatures.len(), 4);
+        assert!(signatures
+            .windows(2)
+            .all(|pair| pair[0].reporter < pair[1].reporter));
+
+        let concatenated = concat_signatures(&signatures);
+        assert_eq!(concatenated.len(), 4 * 65);
+        for (chunk, signature) in concatenated.chunks(65).zip(&signatures) {
+            assert_eq!(chunk, &signature.signature.0[..]);
+        }
+    }
+}
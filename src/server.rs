@@ -1,14 +1,508 @@
+use crate::admin::{Action, Admin};
+use crate::args;
+use crate::ccip::{Gateway, PriceRequest};
+use crate::client::{PriceOracle, Round, PRICE_DECIMALS};
+use crate::cors::Cors;
+use crate::error::Result;
+use crate::feed::{Command, Event, Feed, Reply};
+use crate::gasprice::GasConfig;
+use crate::openapi::{self, Endpoint};
+use crate::peer::{Node, SignedQuotes};
+use crate::quota::{Admission, Quota};
+use crate::tls::Tls;
+use crate::web3util;
 use clap::ArgMatches;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, RecvError};
+use tokio::time::timeout;
+use tokio_rustls::server::TlsStream;
+use tokio_tungstenite::tungstenite::handshake::server::create_response;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
 
+use bytes::buf::BufExt as _;
+use bytes::Bytes;
+use hyper::body::HttpBody as _;
+use hyper::header::{
+    HeaderMap, CACHE_CONTROL, CONTENT_TYPE, ORIGIN, RETRY_AFTER, WWW_AUTHENTICATE,
+};
+use hyper::server::accept;
+use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
-use std::net::ToSocketAddrs;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use web3::transports::Http;
+use web3::types::{Address, Bytes as Web3Bytes, U256, U64};
 
-async fn routes(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-    match (req.method(), req.uri().path()) {
-        (&Method::GET, "/") => Ok(Response::new(Body::from(
-            "Try POSTing data to / such as: `curl localhost:8080/ -XPOST -d 'hello'`",
-        ))),
+/// Idle time after which the event stream sends a comment.
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+/// Messages of a `/v1/history` page without `limit`.
+const HISTORY_PAGE: u64 = 100;
+/// Largest `limit` of a `/v1/history` page.
+const HISTORY_PAGE_MAX: u64 = 1000;
+
+/// Paths of the routers of `server` and `service`, shared with the routes of
+/// the OpenAPI document.
+pub(crate) mod paths {
+    pub const HEALTH: &str = "/health";
+    pub const OPENAPI: &str = "/openapi.json";
+    pub const PRICE: &str = "/v1/price";
+    pub const HISTORY: &str = "/v1/history";
+    pub const STREAM: &str = "/v1/stream";
+    pub const EVENTS: &str = "/v1/events";
+    pub const CCIP: &str = "/v1/ccip";
+    /// `GET` form of [`CCIP`], the router matches the prefix before `{sender}`.
+    pub const CCIP_LOOKUP: &str = "/v1/ccip/{sender}/{data}.json";
+    pub const METRICS: &str = "/metrics";
+    pub const QUOTES: &str = "/v1/quotes";
+    pub const CONSENSUS: &str = "/v1/consensus";
+    pub const OBSERVATION: &str = "/v1/observation";
+    pub const REPORT: &str = "/v1/report";
+    pub const ADMIN_UPDATE: &str = "/admin/update";
+    pub const ADMIN_PAUSE: &str = "/admin/pause";
+    pub const ADMIN_RESUME: &str = "/admin/resume";
+    pub const ADMIN_ADMINS: &str = "/admin/admins";
+
+    /// Part of [`CCIP_LOOKUP`] before the parameters.
+    pub fn ccip_lookup_prefix() -> &'static str {
+        &CCIP_LOOKUP[..CCIP.len() + 1]
+    }
+}
+
+/// Body of `GET /v1/price`.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct PriceResponse {
+    round_id: String,
+    /// BTC for 1 ETH
+    price: String,
+    /// `price` multiplied by 10^18 as stored in the contract
+    answer: String,
+    updated_at: u64,
+    answered_in_round: String,
+    /// Seconds since `updated_at`, consumers reject the price above their threshold
+    age: u64,
+}
+
+impl From<Round> for PriceResponse {
+    fn from(round: Round) -> Self {
+        PriceResponse {
+            round_id: round.round_id.to_string(),
+            price: web3util::format_units(round.answer, PRICE_DECIMALS),
+            answer: round.answer.to_string(),
+            updated_at: round.updated_at.low_u64(),
+            answered_in_round: round.answered_in_round.to_string(),
+            age: round.age(),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ErrorResponse {
+    error: String,
+}
+
+/// Body of `GET /health`.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct HealthResponse {
+    /// `ok`, or `unavailable` when the contract can't be read
+    status: &'static str,
+    /// Seconds since the last price update, none before the first one
+    price_age: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Message of `GET /v1/history`.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct HistoryEntry {
+    /// Id of the message, the `after` of the next page
+    id: u64,
+    #[schemars(with = "crate::feed::Message")]
+    message: serde_json::Value,
+}
+
+/// Body of `POST /admin/pause` and `POST /admin/resume`.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct PauseResponse {
+    paused: bool,
+    was_paused: bool,
+}
+
+/// Body of the admin actions sending a tx.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct TransactionResponse {
+    /// None when the action needed no tx
+    transaction_hash: Option<String>,
+    block_number: Option<u64>,
+    /// `mined` or `reverted`
+    status: Option<&'static str>,
+}
+
+/// Body of `POST /v1/ccip`, the EIP-3668 lookup of the urls without `{data}`.
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct CcipRequest {
+    /// Contract which reverted with `OffchainLookup`
+    #[schemars(schema_with = "openapi::address")]
+    sender: Address,
+    /// `callData` of the lookup, `price(string,uint80)`
+    #[schemars(schema_with = "openapi::bytes")]
+    data: Web3Bytes,
+}
+
+/// Answer of the CCIP-Read gateway, the `_response` of `priceWithProof`.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct CcipResponse {
+    /// ABI encoded `(uint80 roundId, uint256 answer, uint256 updatedAt, uint64 expires, bytes signature)`
+    #[schemars(schema_with = "openapi::bytes")]
+    data: Web3Bytes,
+}
+
+/// Error of the CCIP-Read gateway in the format of EIP-3668.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct CcipError {
+    message: String,
+}
+
+/// State of the public API of `server`.
+struct Api {
+    logger: slog::Logger,
+    oracle: Arc<PriceOracle<Http>>,
+    feed: Arc<Feed>,
+    quota: Arc<Quota>,
+    cors: Option<Cors>,
+    gateway: Option<Gateway>,
+}
+
+async fn routes(
+    api: Arc<Api>,
+    remote: IpAddr,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    let origin = req.headers().get(ORIGIN).cloned();
+    let mut response = api_routes(&api, remote, req).await;
+    if let Some(cors) = &api.cors {
+        cors.apply(origin.as_ref(), &mut response);
+    }
+    Ok(response)
+}
+
+async fn api_routes(api: &Api, remote: IpAddr, req: Request<Body>) -> Response<Body> {
+    let Api {
+        logger,
+        oracle,
+        feed,
+        quota,
+        cors,
+        ..
+    } = api;
+    let endpoint = openapi::endpoint(openapi::API_ROUTES, req.method(), req.uri().path());
+    match (req.method(), req.uri().path(), cors) {
+        (&Method::OPTIONS, _, Some(cors)) => return cors.preflight(req.headers()),
+        (&Method::GET, "/", _) => {
+            return Response::new(Body::from(
+                "Try GETting the price such as: `curl localhost:8080/v1/price`",
+            ))
+        }
+        _ => {}
+    }
+    match endpoint {
+        Some(Endpoint::Health) => return health(oracle),
+        Some(Endpoint::OpenApi) => return json(StatusCode::OK, &openapi::spec()),
+        _ => {}
+    }
+
+    let client = match admit(logger, quota, remote, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+
+    let response = match endpoint {
+        Some(Endpoint::Price) => price(oracle),
+        Some(Endpoint::History) => history(feed, &req),
+        Some(Endpoint::Stream) => stream(logger.clone(), feed.clone(), req),
+        Some(Endpoint::Events) => events(logger.clone(), feed.clone(), &req),
+        Some(Endpoint::CcipLookup) => match ccip_lookup(req.uri().path()) {
+            Ok((sender, call_data)) => ccip(api, sender, &call_data),
+            Err(e) => ccip_error(StatusCode::BAD_REQUEST, e),
+        },
+        Some(Endpoint::Ccip) => match hyper::body::to_bytes(req).await {
+            Ok(body) => match serde_json::from_slice::<CcipRequest>(&body) {
+                Ok(request) => ccip(api, request.sender, &request.data.0),
+                Err(e) => ccip_error(StatusCode::BAD_REQUEST, e),
+            },
+            Err(e) => ccip_error(StatusCode::BAD_REQUEST, e),
+        },
+        _ => error_json(StatusCode::NOT_FOUND, "not found"),
+    };
+    quota.record(&client, response.status().as_u16());
+    response
+}
+
+/// Takes a token of the client under the quota, returns the client name the
+/// response is recorded for or the response refusing the request.
+fn admit(
+    logger: &slog::Logger,
+    quota: &Quota,
+    remote: IpAddr,
+    req: &Request<Body>,
+) -> std::result::Result<String, Response<Body>> {
+    let ip = if quota.forwarded_for() {
+        forwarded_for(req.headers()).unwrap_or(remote)
+    } else {
+        remote
+    };
+    match quota.admit(api_key(req), ip) {
+        Ok(Admission::Allowed(client)) => Ok(client),
+        Ok(Admission::UnknownKey) => Err(error_json(StatusCode::UNAUTHORIZED, "unknown API key")),
+        Ok(Admission::KeyRequired) => Err(error_json(StatusCode::UNAUTHORIZED, "API key required")),
+        Ok(Admission::Limited {
+            client,
+            limit,
+            retry_after,
+        }) => {
+            debug!(
+                logger,
+                "{} from {} over the {} rate limit", client, ip, limit
+            );
+            let mut response = error_json(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("{} rate limit exceeded", limit),
+            );
+            // whole seconds, rounded up so the retry finds a token
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, seconds.to_string().parse().unwrap());
+            Err(response)
+        }
+        Err(e) => Err(error_json(StatusCode::SERVICE_UNAVAILABLE, e)),
+    }
+}
+
+/// Key of the `X-Api-Key` header or of the `api_key` query parameter, which
+/// the browser `EventSource` clients use as they can't set headers.
+fn api_key(req: &Request<Body>) -> Option<&str> {
+    req.headers()
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| query_param(req, "api_key"))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+/// Value of the query parameter, the values of the API need no decoding.
+fn query_param<'a>(req: &'a Request<Body>, name: &str) -> Option<&'a str> {
+    req.uri().query()?.split('&').find_map(|param| {
+        let mut parts = param.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) if key == name => Some(value),
+            _ => None,
+        }
+    })
+}
+
+/// Client IP added by the proxy in front of the server.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .last()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn price(oracle: &PriceOracle<Http>) -> Response<Body> {
+    match oracle.latest_round() {
+        Ok(Some(round)) => json(StatusCode::OK, &PriceResponse::from(round)),
+        Ok(None) => error_json(StatusCode::NOT_FOUND, "price was never set"),
+        Err(e) => error_json(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+/// Sender and `callData` of `/v1/ccip/{sender}/{data}.json`.
+fn ccip_lookup(path: &str) -> std::result::Result<(Address, Vec<u8>), String> {
+    let mut parts = path
+        .trim_start_matches(paths::ccip_lookup_prefix())
+        .splitn(2, '/');
+    let (sender, data) = match (parts.next(), parts.next()) {
+        (Some(sender), Some(data)) => (sender, data.trim_end_matches(".json")),
+        _ => return Err(format!("the path is not {}", paths::CCIP_LOOKUP)),
+    };
+    let sender = sender
+        .trim_start_matches("0x")
+        .parse()
+        .map_err(|_| format!("{} is not an address", sender))?;
+    let call_data = hex::decode(data.trim_start_matches("0x"))
+        .map_err(|e| format!("data is not hex: {}", e))?;
+    Ok((sender, call_data))
+}
+
+/// Signs the requested round of the pair for the contract at `sender`.
+fn ccip(api: &Api, sender: Address, call_data: &[u8]) -> Response<Body> {
+    let gateway = match &api.gateway {
+        Some(gateway) => gateway,
+        None => return ccip_error(StatusCode::NOT_FOUND, "the gateway is not enabled"),
+    };
+    if !gateway.allows(sender) {
+        return ccip_error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "{:?} is not an OffchainPriceOracle of --ccip_senders",
+                sender
+            ),
+        );
+    }
+    let request = match PriceRequest::decode(call_data) {
+        Ok(request) => request,
+        Err(e) => return ccip_error(StatusCode::BAD_REQUEST, e),
+    };
+    if request.pair != api.feed.pair() {
+        return ccip_error(
+            StatusCode::NOT_FOUND,
+            format!("unknown pair {}", request.pair),
+        );
+    }
+
+    let latest = match api.oracle.latest_round() {
+        Ok(Some(latest)) => latest,
+        Ok(None) => return ccip_error(StatusCode::NOT_FOUND, "price was never set"),
+        Err(e) => return ccip_error(StatusCode::BAD_GATEWAY, e),
+    };
+    let round = if request.round_id.is_zero() || request.round_id == latest.round_id {
+        latest
+    } else if request.round_id > latest.round_id {
+        return ccip_error(
+            StatusCode::NOT_FOUND,
+            format!("round {} is not stored yet", request.round_id),
+        );
+    } else {
+        match api.oracle.round(request.round_id) {
+            Ok(round) => round,
+            Err(e) => return ccip_error(StatusCode::BAD_GATEWAY, e),
+        }
+    };
+
+    match gateway.sign(sender, call_data, &round) {
+        Ok(data) => json(
+            StatusCode::OK,
+            &CcipResponse {
+                data: Web3Bytes(data),
+            },
+        ),
+        Err(e) => ccip_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+fn ccip_error(status: StatusCode, message: impl std::fmt::Display) -> Response<Body> {
+    json(
+        status,
+        &CcipError {
+            message: message.to_string(),
+        },
+    )
+}
+
+fn health(oracle: &PriceOracle<Http>) -> Response<Body> {
+    match oracle.latest_round() {
+        Ok(round) => json(
+            StatusCode::OK,
+            &HealthResponse {
+                status: "ok",
+                price_age: round.map(|round| round.age()),
+                error: None,
+            },
+        ),
+        Err(e) => json(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &HealthResponse {
+                status: "unavailable",
+                price_age: None,
+                error: Some(e.to_string()),
+            },
+        ),
+    }
+}
+
+/// Page of the feed history after the `after` id.
+fn history(feed: &Feed, req: &Request<Body>) -> Response<Body> {
+    let after = match query_param(req, "after").map(str::parse).transpose() {
+        Ok(after) => after.unwrap_or(0),
+        Err(e) => return error_json(StatusCode::BAD_REQUEST, format!("after: {}", e)),
+    };
+    let limit: u64 = match query_param(req, "limit").map(str::parse).transpose() {
+        Ok(limit) => limit.unwrap_or(HISTORY_PAGE).clamp(1, HISTORY_PAGE_MAX),
+        Err(e) => return error_json(StatusCode::BAD_REQUEST, format!("limit: {}", e)),
+    };
+
+    match feed.page(after, limit) {
+        Ok(events) => {
+            let entries = events
+                .into_iter()
+                .map(|event| HistoryEntry {
+                    id: event.id,
+                    message: serde_json::from_str(&event.data).unwrap_or_default(),
+                })
+                .collect::<Vec<_>>();
+            json(StatusCode::OK, &entries)
+        }
+        Err(e) => error_json(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+pub(crate) fn json<B: Serialize>(status: StatusCode, body: &B) -> Response<Body> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+pub(crate) fn error_json(status: StatusCode, error: impl std::fmt::Display) -> Response<Body> {
+    json(
+        status,
+        &ErrorResponse {
+            error: error.to_string(),
+        },
+    )
+}
+
+async fn node_routes(
+    node: Arc<Node>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    match openapi::endpoint(openapi::NODE_ROUTES, req.method(), req.uri().path()) {
+        Some(Endpoint::Quotes) => Ok(match node.quotes() {
+            Some(quotes) => json(StatusCode::OK, &quotes),
+            None => error_json(StatusCode::NOT_FOUND, "no quotes yet"),
+        }),
+        Some(Endpoint::PushQuotes) => {
+            let body = hyper::body::aggregate(req).await?;
+            let quotes: SignedQuotes = match serde_json::from_reader(body.reader()) {
+                Ok(quotes) => quotes,
+                Err(e) => return Ok(error_json(StatusCode::BAD_REQUEST, e)),
+            };
+            Ok(match node.accept(quotes) {
+                Ok(()) => Response::new(Body::empty()),
+                Err(e) => error_json(StatusCode::UNAUTHORIZED, e),
+            })
+        }
+        Some(Endpoint::Consensus) => Ok(match node.consensus() {
+            Some(consensus) => json(StatusCode::OK, &consensus),
+            None => error_json(StatusCode::NOT_FOUND, "no fresh quotes"),
+        }),
         _ => {
             let mut not_found = Response::default();
             *not_found.status_mut() = StatusCode::NOT_FOUND;
@@ -17,18 +511,551 @@ async fn routes(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
     }
 }
 
-#[tokio::main]
-pub async fn run(
+/// Upgrades the request to a WebSocket that pushes the feed messages of the
+/// subscribed pairs.
+fn stream(logger: slog::Logger, feed: Arc<Feed>, req: Request<Body>) -> Response<Body> {
+    let mut handshake = tungstenite::handshake::server::Request::new(());
+    *handshake.method_mut() = req.method().clone();
+    *handshake.version_mut() = req.version();
+    *handshake.headers_mut() = req.headers().clone();
+    let response = match create_response(&handshake) {
+        Ok(response) => response,
+        Err(e) => return error_json(StatusCode::BAD_REQUEST, e),
+    };
+
+    tokio::spawn(async move {
+        match req.into_body().on_upgrade().await {
+            Ok(upgraded) => {
+                let ws = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                if let Err(e) = session(ws, &feed).await {
+                    debug!(logger, "stream closed: {}", e);
+                }
+            }
+            Err(e) => warn!(logger, "stream upgrade failed: {}", e),
+        }
+    });
+
+    let (parts, ()) = response.into_parts();
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Answers the commands of the client and forwards the messages of its pairs
+/// until either side closes.
+async fn session<S>(ws: WebSocketStream<S>, feed: &Feed) -> tungstenite::Result<()>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut sink, mut commands) = ws.split();
+    let mut updates = feed.subscribe();
+    let mut pairs = HashSet::new();
+
+    loop {
+        let reply = tokio::select! {
+            command = commands.next() => match command {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(Command::Subscribe { pair }) if pair == feed.pair() => {
+                        pairs.insert(pair.clone());
+                        Reply::Subscribed { pair }
+                    }
+                    Ok(Command::Subscribe { pair }) => Reply::Error {
+                        error: format!("unknown pair {}, the feed has {}", pair, feed.pair()),
+                    },
+                    Ok(Command::Unsubscribe { pair }) => {
+                        pairs.remove(&pair);
+                        Reply::Unsubscribed { pair }
+                    }
+                    Err(e) => Reply::Error { error: e.to_string() },
+                },
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e),
+            },
+            update = updates.recv() => match update {
+                Ok(event) if pairs.contains(&event.pair) => {
+                    sink.send(Message::Text(event.data)).await?;
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => Reply::Lagged { skipped },
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        };
+        sink.send(text(&reply)).await?;
+    }
+}
+
+/// Streams the feed as Server-Sent Events. A client reconnecting with
+/// `Last-Event-ID` gets the stored events after it first.
+fn events(logger: slog::Logger, feed: Arc<Feed>, req: &Request<Body>) -> Response<Body> {
+    let last_id = match req.headers().get("last-event-id") {
+        Some(value) => match value.to_str().ok().and_then(|id| id.trim().parse().ok()) {
+            Some(id) => Some(id),
+            None => return error_json(StatusCode::BAD_REQUEST, "Last-Event-ID is not an id"),
+        },
+        None => None,
+    };
+
+    // subscribed before the history is read, so no event falls in between
+    let updates = feed.subscribe();
+    let history = match last_id.map(|id| feed.since(id)) {
+        Some(Ok(history)) => history,
+        Some(Err(e)) => return error_json(StatusCode::INTERNAL_SERVER_ERROR, e),
+        None => Vec::new(),
+    };
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let last_id = last_id.unwrap_or_default();
+        if let Err(e) = send_events(&mut sender, history, updates, last_id).await {
+            debug!(logger, "event stream closed: {}", e);
+        }
+    });
+
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, "text/event-stream".parse().unwrap());
+    headers.insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+    // nginx buffers the proxied responses otherwise
+    headers.insert("x-accel-buffering", "no".parse().unwrap());
+    response
+}
+
+async fn send_events(
+    sender: &mut hyper::body::Sender,
+    history: Vec<Event>,
+    mut updates: broadcast::Receiver<Event>,
+    mut last_id: u64,
+) -> hyper::Result<()> {
+    sender.send_data(Bytes::from("retry: 3000\n\n")).await?;
+    for event in history {
+        last_id = event.id;
+        sender.send_data(sse(&event)).await?;
+    }
+
+    loop {
+        let chunk = match timeout(SSE_KEEPALIVE, updates.recv()).await {
+            // a comment keeps the idle connection open through the proxies
+            Err(_) => Bytes::from(": keepalive\n\n"),
+            Ok(Ok(event)) if event.id > last_id => {
+                last_id = event.id;
+                sse(&event)
+            }
+            Ok(Ok(_)) => continue,
+            // the client reconnects with its Last-Event-ID and reads the
+            // missed events from the history
+            Ok(Err(RecvError::Lagged(_))) | Ok(Err(RecvError::Closed)) => return Ok(()),
+        };
+        sender.send_data(chunk).await?;
+    }
+}
+
+fn sse(event: &Event) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id, event.kind, event.data
+    ))
+}
+
+fn text<B: Serialize>(body: &B) -> Message {
+    Message::Text(serde_json::to_string(body).unwrap_or_default())
+}
+
+/// State of the `/v1/stream` listener of `service`, which has the quota and the
+/// CORS of the public API of `server`.
+struct StreamApi {
+    logger: slog::Logger,
+    feed: Arc<Feed>,
+    quota: Arc<Quota>,
+    cors: Option<Cors>,
+}
+
+async fn stream_routes(
+    api: Arc<StreamApi>,
+    remote: IpAddr,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    let StreamApi {
+        logger,
+        feed,
+        quota,
+        cors,
+    } = &*api;
+    let origin = req.headers().get(ORIGIN).cloned();
+    let mut response = match (req.method(), cors) {
+        (&Method::OPTIONS, Some(cors)) => cors.preflight(req.headers()),
+        _ => match admit(logger, quota, remote, &req) {
+            Ok(client) => {
+                let endpoint =
+                    openapi::endpoint(openapi::STREAM_ROUTES, req.method(), req.uri().path());
+                let response = match endpoint {
+                    Some(Endpoint::Stream) => stream(logger.clone(), feed.clone(), req),
+                    Some(Endpoint::Events) => events(logger.clone(), feed.clone(), &req),
+                    _ => error_json(StatusCode::NOT_FOUND, "not found"),
+                };
+                quota.record(&client, response.status().as_u16());
+                response
+            }
+            Err(response) => response,
+        },
+    };
+    if let Some(cors) = cors {
+        cors.apply(origin.as_ref(), &mut response);
+    }
+    Ok(response)
+}
+
+/// Serves `/v1/stream` and `/v1/events` of the feed of `service` under the
+/// quota, the CORS and the TLS of the public API of `server`.
+pub async fn serve_stream(
+    logger: slog::Logger,
+    addr: SocketAddr,
+    feed: Arc<Feed>,
+    quota: Arc<Quota>,
+    cors: Option<Cors>,
+    tls: Option<Tls>,
+) -> Result<()> {
+    let api = Arc::new(StreamApi {
+        logger: logger.clone(),
+        feed,
+        quota,
+        cors,
+    });
+    listen(&logger, "stream", addr, tls, move |remote, req| {
+        stream_routes(api.clone(), remote, req)
+    })
+    .await
+}
+
+/// Serves the handler on the address, over https when `tls` is set. The
+/// handler gets the IP of the client along with the request.
+async fn listen<H, F>(
+    logger: &slog::Logger,
+    name: &str,
+    addr: SocketAddr,
+    tls: Option<Tls>,
+    handler: H,
+) -> Result<()>
+where
+    H: Fn(IpAddr, Request<Body>) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = std::result::Result<Response<Body>, hyper::Error>> + Send + 'static,
+{
+    match tls {
+        Some(tls) => {
+            let tls = Arc::new(tls);
+            tokio::spawn({
+                let (logger, tls) = (logger.clone(), tls.clone());
+                async move { tls.watch(logger).await }
+            });
+            let incoming = tls.incoming(logger.clone(), addr).await?;
+            let service = make_service_fn(move |conn: &TlsStream<TcpStream>| {
+                let handler = handler.clone();
+                // fails only when the client is already gone
+                let remote = conn
+                    .get_ref()
+                    .0
+                    .peer_addr()
+                    .map_or(IpAddr::from(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+                async move { Ok::<_, hyper::Error>(service_fn(move |req| handler(remote, req))) }
+            });
+            info!(logger, "{} listening on https://{}", name, addr);
+            Server::builder(accept::from_stream(incoming))
+                .serve(service)
+                .await?;
+        }
+        None => {
+            let service = make_service_fn(move |conn: &AddrStream| {
+                let handler = handler.clone();
+                let remote = conn.remote_addr().ip();
+                async move { Ok::<_, hyper::Error>(service_fn(move |req| handler(remote, req))) }
+            });
+            info!(logger, "{} listening on http://{}", name, addr);
+            Server::bind(&addr).serve(service).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Body of `POST /admin/update`.
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct UpdateRequest {
+    /// Decimal uint256 pushed instead of the price of the source
+    answer: Option<String>,
+}
+
+/// Body of `POST /admin/admins`.
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct SetAdminRequest {
+    #[schemars(schema_with = "openapi::address")]
+    admin: Address,
+    grant: bool,
+}
+
+/// Largest body of an admin request, the actions take a few small fields.
+const MAX_ADMIN_BODY: usize = 64 * 1024;
+
+/// Reads the body, `None` as soon as it grows over `limit` bytes.
+async fn read_body(mut body: Body, limit: usize) -> hyper::Result<Option<Vec<u8>>> {
+    let mut read = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if read.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        read.extend_from_slice(&chunk);
+    }
+    Ok(Some(read))
+}
+
+async fn admin_routes(
+    logger: slog::Logger,
+    admin: Arc<Admin>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    let action = match openapi::endpoint(openapi::ADMIN_ROUTES, req.method(), req.uri().path()) {
+        Some(Endpoint::AdminUpdate) => "update",
+        Some(Endpoint::AdminPause) => "pause",
+        Some(Endpoint::AdminResume) => "resume",
+        Some(Endpoint::AdminAdmins) => "set_admin",
+        _ => return Ok(error_json(StatusCode::NOT_FOUND, "not found")),
+    };
+    // the body of an unauthenticated request is neither read nor logged
+    let principal = match admin.authenticate(req.headers()) {
+        Some(principal) => principal.to_string(),
+        None => {
+            admin.audit(
+                &logger,
+                None,
+                action,
+                &serde_json::Value::Null,
+                "unauthorized",
+            );
+            let mut response = error_json(StatusCode::UNAUTHORIZED, "bearer token is required");
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            return Ok(response);
+        }
+    };
+
+    let body = match read_body(req.into_body(), MAX_ADMIN_BODY).await? {
+        Some(body) => body,
+        None => {
+            let e = format!("the body exceeds {} bytes", MAX_ADMIN_BODY);
+            admin.audit(
+                &logger,
+                Some(&principal),
+                action,
+                &serde_json::Value::Null,
+                &e,
+            );
+            return Ok(error_json(StatusCode::PAYLOAD_TOO_LARGE, e));
+        }
+    };
+    let params = if body.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::from_slice(&body).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&body).into_owned())
+        })
+    };
+
+    let result = admin_action(&admin, action, params.clone()).await;
+    let outcome = match result {
+        Ok(_) => "ok".to_string(),
+        Err((_, ref e)) => e.clone(),
+    };
+    admin.audit(&logger, Some(&principal), action, &params, &outcome);
+
+    Ok(match result {
+        Ok(body) => json(StatusCode::OK, &body),
+        Err((status, e)) => error_json(status, e),
+    })
+}
+
+/// Runs the action, the txs are sent by the poll loop of the service.
+async fn admin_action(
+    admin: &Admin,
+    action: &str,
+    params: serde_json::Value,
+) -> std::result::Result<serde_json::Value, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let action = match action {
+        "pause" | "resume" => {
+            let paused = action == "pause";
+            let was_paused = admin.control().set_paused(paused);
+            return Ok(serde_json::json!(PauseResponse { paused, was_paused }));
+        }
+        "update" => {
+            let request: UpdateRequest =
+                serde_json::from_value(params).map_err(|e| bad_request(e.to_string()))?;
+            let answer = match request.answer {
+                Some(answer) => Some(
+                    U256::from_dec_str(&answer)
+                        .map_err(|_| bad_request(format!("{} is not a uint256", answer)))?,
+                ),
+                None => None,
+            };
+            Action::Update { answer }
+        }
+        _ => {
+            let request: SetAdminRequest =
+                serde_json::from_value(params).map_err(|e| bad_request(e.to_string()))?;
+            Action::SetAdmin {
+                admin: request.admin,
+                value: request.grant,
+            }
+        }
+    };
+
+    match admin.control().submit(action).await {
+        Ok(Ok(Some(receipt))) => Ok(serde_json::json!(TransactionResponse {
+            transaction_hash: Some(format!("{:?}", receipt.transaction_hash)),
+            block_number: receipt.block_number.map(|n| n.as_u64()),
+            status: Some(if receipt.status == Some(U64::zero()) {
+                "reverted"
+            } else {
+                "mined"
+            }),
+        })),
+        Ok(Ok(None)) => Ok(serde_json::json!(TransactionResponse {
+            transaction_hash: None,
+            block_number: None,
+            status: None,
+        })),
+        Ok(Err(e)) => Err((StatusCode::CONFLICT, e.to_string())),
+        Err(_) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the service loop is stopped".to_string(),
+        )),
+    }
+}
+
+/// Serves the `/admin` endpoints of the service.
+pub async fn serve_admin(logger: slog::Logger, addr: SocketAddr, admin: Arc<Admin>) -> Result<()> {
+    let service_logger = logger.clone();
+    let service = make_service_fn(move |_| {
+        let (logger, admin) = (service_logger.clone(), admin.clone());
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                admin_routes(logger.clone(), admin.clone(), req)
+            }))
+        }
+    });
+
+    if !addr.ip().is_loopback() {
+        warn!(
+            logger,
+            "admin api on {} is plain http, put it behind a tls proxy", addr
+        );
+    }
+    info!(logger, "admin api listening on http://{}/admin", addr);
+    Server::bind(&addr).serve(service).await?;
+
+    Ok(())
+}
+
+/// Serves the `/metrics` usage counters of the public API.
+pub async fn serve_metrics(
     logger: slog::Logger,
-    arg: &ArgMatches<'_>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let bind = arg.value_of("bind").unwrap();
-    let service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(routes)) });
+    addr: SocketAddr,
+    quota: Arc<Quota>,
+) -> Result<()> {
+    let service = make_service_fn(move |_| {
+        let quota = quota.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                let endpoint =
+                    openapi::endpoint(openapi::METRICS_ROUTES, req.method(), req.uri().path());
+                let response = match endpoint {
+                    Some(Endpoint::Metrics) => {
+                        let mut response = Response::new(Body::from(quota.metrics()));
+                        response
+                            .headers_mut()
+                            .insert(CONTENT_TYPE, "text/plain; version=0.0.4".parse().unwrap());
+                        response
+                    }
+                    _ => error_json(StatusCode::NOT_FOUND, "not found"),
+                };
+                async move { Ok::<_, hyper::Error>(response) }
+            }))
+        }
+    });
+
+    info!(logger, "metrics listening on http://{}/metrics", addr);
+    Server::bind(&addr).serve(service).await?;
+
+    Ok(())
+}
+
+/// Serves the node-to-node quote exchange to the peers.
+pub async fn serve_node(logger: slog::Logger, addr: SocketAddr, node: Arc<Node>) -> Result<()> {
+    let node_address = node.address();
+    let service = make_service_fn(move |_| {
+        let node = node.clone();
+        async move { Ok::<_, hyper::Error>(service_fn(move |req| node_routes(node.clone(), req))) }
+    });
 
-    let mut addrs_iter = bind.to_socket_addrs().unwrap();
-    let server = Server::bind(addrs_iter.next().as_ref().unwrap()).serve(service);
-    info!(logger, "listening on http://{}", bind);
-    server.await?;
+    info!(
+        logger,
+        "node {:?} listening on http://{}", node_address, addr
+    );
+    Server::bind(&addr).serve(service).await?;
 
     Ok(())
 }
+
+#[tokio::main]
+pub async fn run(logger: slog::Logger, arg: &ArgMatches<'_>) -> Result<()> {
+    let net = args::required(arg, "net")?;
+    let contract_addr = args::address(arg, "contractaddr")?;
+
+    let (eloop, http) = Http::new(net)?;
+    eloop.into_remote();
+    let oracle = Arc::new(PriceOracle::new(
+        web3::Web3::new(http),
+        contract_addr,
+        None,
+        GasConfig::default(),
+    ));
+
+    let feed = Arc::new(Feed::from_args(&oracle, arg)?);
+    let confirmations = args::parse(arg, "confirmations")?;
+    let poll_interval = Duration::from_secs(args::parse(arg, "poll_interval")?);
+    tokio::spawn(
+        feed.clone()
+            .follow(logger.clone(), oracle.clone(), confirmations, poll_interval),
+    );
+
+    let quota = Arc::new(Quota::from_args(arg)?);
+    if let Some(addr) = args::socket_addr_opt(arg, "metrics_bind")? {
+        tokio::spawn(serve_metrics(logger.clone(), addr, quota.clone()));
+    }
+
+    let api = Arc::new(Api {
+        logger: logger.clone(),
+        oracle,
+        feed,
+        quota,
+        cors: Cors::from_args(arg)?,
+        gateway: Gateway::from_args(arg)?,
+    });
+    if let Some(gateway) = &api.gateway {
+        info!(
+            logger,
+            "ccip-read gateway signing as {:?}",
+            gateway.address()
+        );
+    }
+    let addr = args::socket_addr(arg, "bind")?;
+
+    info!(logger, "serving contract {:?}", contract_addr);
+    listen(
+        &logger,
+        "api",
+        addr,
+        Tls::from_args(arg)?,
+        move |remote, req| routes(api.clone(), remote, req),
+    )
+    .await
+}
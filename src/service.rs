@@ -1,93 +1,779 @@
-#![allow(non_snake_case)]
-
-use serde::Deserialize;
-
 use clap::ArgMatches;
-use hyper::Client;
-use hyper_tls::HttpsConnector;
-use std::{thread, time};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time;
+use tokio::time::delay_for;
 
+use crate::admin::{Action, Admin, Control};
+use crate::args;
+use crate::bindings::price_oracle::functions;
+use crate::client::{PriceOracle, Round, RECEIPT_POLL_INTERVAL};
+use crate::cors::Cors;
+use crate::election::{self, Election};
+use crate::error::{Error, Result};
+use crate::feed::Feed;
+use crate::notify::{Kind, Notifier};
+use crate::peer::{Node, Quote};
+use crate::quota::Quota;
+use crate::report::{self, Peers, Reporter};
+use crate::server;
+use crate::source::{CryptoCompare, PriceSource};
+use crate::tls::Tls;
 use crate::updateprice;
-use bytes::buf::BufExt as _;
-use web3::types::{U128, U256};
+use crate::web3util;
+use web3::types::{Address, TransactionReceipt, U128, U256, U64};
+use web3::Transport;
 
-#[tokio::main]
-pub async fn run(logger: slog::Logger, arg: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new(arg);
-    let url = make_url(config.api_endpoint.unwrap(), config.api_key.unwrap()).unwrap();
-    info!(
-        logger,
-        "service called to the {} with poll interval {}",
-        url,
-        config.poll_interval.unwrap()
-    );
+const MAX_RETRIES: u32 = 5;
 
-    let mut update_conf = updateprice::UpdateConfig::new(arg);
-    let mut prev_price = 0.0;
+/// Polls the price source and pushes the price to the contract when it grows.
+///
+/// With a [`Quorum`] the price is reported together with the peers instead,
+/// see [`crate::report`].
+pub struct Service<S: PriceSource, T: Transport> {
+    source: S,
+    oracle: Arc<PriceOracle<T>>,
+    poll_interval: time::Duration,
+    quorum: Option<Quorum>,
+    node: Option<Arc<Node>>,
+    election: Option<Election>,
+    notifier: Option<Arc<Notifier>>,
+    feed: Option<Arc<Feed>>,
+    control: Option<Arc<Control>>,
+    /// Percent change of the price between two polls that raises an alarm
+    alarm_deviation: Option<f64>,
+}
 
-    loop {
-        let price = fetch_eth_price(url.as_str().parse().unwrap()).await?;
-        info!(logger, "one BTC for ETH now is {:#?}", price);
-
-        if price.ETH > prev_price {
-            let wei = price.ETH * f64::powi(10.0, 18).ceil();
-            let wei = wei as u128;
-            update_conf.new_price = <U256 as From<U128>>::from(wei.into());
-            if let Err(e) = updateprice::update_price(&logger, &update_conf) {
-                info!(logger, "update price error: {:#?}", e);
+/// Off-chain reporting settings of the service.
+pub struct Quorum {
+    pub reporter: Arc<Reporter>,
+    pub peers: Peers,
+    /// The leader aggregates the observations and submits the reports
+    pub leader: bool,
+}
+
+impl<S: PriceSource, T: Transport + Send + Sync + 'static> Service<S, T> {
+    pub fn new(source: S, oracle: PriceOracle<T>, poll_interval: time::Duration) -> Self {
+        Service {
+            source,
+            oracle: Arc::new(oracle),
+            poll_interval,
+            quorum: None,
+            node: None,
+            election: None,
+            notifier: None,
+            feed: None,
+            control: None,
+            alarm_deviation: None,
+        }
+    }
+
+    /// Reports the price with the peers, the contract must have the quorum set.
+    pub fn with_quorum(mut self, quorum: Quorum) -> Self {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    /// Sends the transactions only while the instance holds the leader lease.
+    pub fn with_election(mut self, election: Election) -> Self {
+        self.election = Some(election);
+        self
+    }
+
+    /// Renews the leader lease, an instance without election always leads.
+    fn leads(&self, logger: &slog::Logger) -> bool {
+        self.election
+            .as_ref()
+            .map_or(true, |election| election.renew(logger))
+    }
+
+    /// Sends the tx with the calldata as the leader and waits for its receipt.
+    /// The lease is renewed before every attempt to broadcast and every receipt
+    /// poll, as the retries and the confirmations can outlast its ttl; `None`
+    /// when it was lost and the tx is not sent.
+    ///
+    /// Only the broadcast is retried, a failed receipt poll polls the same tx
+    /// hash again instead of sending the tx twice.
+    async fn send(
+        &self,
+        logger: &slog::Logger,
+        data: Vec<u8>,
+    ) -> Result<Option<TransactionReceipt>> {
+        let sent = retry(logger, || async {
+            if !self.leads(logger) {
+                warn!(logger, "the leader lease is lost, the tx is not sent");
+                return Ok(None);
+            }
+            let (oracle, logger, data) = (self.oracle.clone(), logger.clone(), data.clone());
+            blocking(move || oracle.broadcast(&logger, data))
+                .await
+                .map(Some)
+        })
+        .await?;
+        let (hash, gas_price) = match sent {
+            Some(sent) => sent,
+            None => return Ok(None),
+        };
+        info!(logger, "tx {:?} sent, waiting for the receipt", hash);
+
+        loop {
+            delay_for(RECEIPT_POLL_INTERVAL).await;
+            // keeps the standbys from taking over and sending while it is mined
+            self.leads(logger);
+            let oracle = self.oracle.clone();
+            match blocking(move || oracle.confirmed_receipt(hash)).await {
+                Ok(Some(receipt)) => {
+                    web3util::log_tx_cost(logger, &receipt, gas_price);
+                    return Ok(Some(receipt));
+                }
+                Ok(None) => {}
+                Err(e) if e.is_transient() => {
+                    warn!(logger, "polling the receipt of {:?} failed: {}", hash, e)
+                }
+                Err(e) => return Err(e),
             }
-            prev_price = price.ETH;
         }
+    }
 
-        thread::sleep(time::Duration::from_secs(config.poll_interval.unwrap()));
+    /// Exchanges the fetched quotes with the peer nodes, see [`crate::peer`].
+    pub fn with_node(mut self, node: Arc<Node>) -> Self {
+        self.node = Some(node);
+        self
     }
-}
 
-#[derive(Default)]
-struct Config {
-    api_endpoint: Option<String>,
-    api_key: Option<String>,
-    poll_interval: Option<u64>,
-}
+    /// Sends the webhook notifications of the failures and the alarms, see
+    /// [`crate::notify`].
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>, alarm_deviation: Option<f64>) -> Self {
+        self.notifier = Some(notifier);
+        self.alarm_deviation = alarm_deviation;
+        self
+    }
 
-impl Config {
-    fn new(arg: &ArgMatches) -> Self {
-        let api_endpoint = arg.value_of("api_endpoint").unwrap().to_string();
-        let api_key = arg.value_of("api_key").unwrap().to_string();
-        let cpi = arg.value_of("poll_interval").unwrap();
+    /// Pushes the fetched prices and the mined txs to the stream clients.
+    pub fn with_feed(mut self, feed: Arc<Feed>) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    /// Runs the actions of the admin API and pauses when it is asked to.
+    pub fn with_control(mut self, control: Arc<Control>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    fn paused(&self) -> bool {
+        self.control
+            .as_ref()
+            .map_or(false, |control| control.is_paused())
+    }
+
+    /// Sleeps for the poll interval, an admin action wakes the loop up earlier.
+    async fn wait(&self) {
+        match self.control {
+            Some(ref control) => tokio::select! {
+                _ = delay_for(self.poll_interval) => {}
+                _ = control.woken() => {}
+            },
+            None => delay_for(self.poll_interval).await,
+        }
+    }
+
+    /// Runs the queued admin actions, only the leader sends their txs.
+    async fn admin_actions(&self, logger: &slog::Logger, leader: bool) {
+        let control = match self.control {
+            Some(ref control) => control,
+            None => return,
+        };
+        while let Some(request) = control.take() {
+            let result = if leader {
+                self.admin_action(logger, request.action).await
+            } else {
+                Err(Error::Admin(
+                    "this instance is not the leader, send it to the leader".to_string(),
+                ))
+            };
+            if let Err(ref e) = result {
+                error!(logger, "admin action failed: {}", e);
+            }
+            let _ = request.reply.send(result);
+        }
+    }
+
+    async fn admin_action(
+        &self,
+        logger: &slog::Logger,
+        action: Action,
+    ) -> Result<Option<TransactionReceipt>> {
+        let receipt = match action {
+            Action::SetAdmin { admin, value } => {
+                let receipt = self
+                    .send(logger, functions::set_admin::encode_input(admin, value))
+                    .await?;
+                return receipt.map(Some).ok_or_else(lost_lease);
+            }
+            Action::Update { answer } => match (answer, &self.quorum) {
+                (Some(_), Some(_)) => {
+                    return Err(Error::Admin(
+                        "a manual answer is not signed by the reporters in the quorum mode"
+                            .to_string(),
+                    ))
+                }
+                (Some(answer), None) => Some(
+                    self.send(logger, functions::update_price::encode_input(answer))
+                        .await?
+                        .ok_or_else(lost_lease)?,
+                ),
+                (None, quorum) => {
+                    let answer = to_answer(retry(logger, || self.source.fetch()).await?);
+                    match quorum {
+                        Some(quorum) => self.report(logger, quorum, answer, true).await?,
+                        None => Some(
+                            self.send(logger, functions::update_price::encode_input(answer))
+                                .await?
+                                .ok_or_else(lost_lease)?,
+                        ),
+                    }
+                }
+            },
+        };
+
+        if let Some(ref receipt) = receipt {
+            self.succeeded(logger, receipt);
+        }
+        Ok(receipt)
+    }
+
+    /// Queues the notification, a failed queue is logged and doesn't stop the
+    /// loop.
+    fn notify(&self, logger: &slog::Logger, kind: Kind, data: serde_json::Value) {
+        if let Some(ref notifier) = self.notifier {
+            if let Err(e) = notifier.notify(kind, data) {
+                error!(logger, "{:?} notification is lost: {}", kind, e);
+            }
+        }
+    }
+
+    fn update_failed(&self, logger: &slog::Logger, e: &Error) {
+        self.notify(
+            logger,
+            Kind::UpdateFailed,
+            serde_json::json!({ "error": e.to_string() }),
+        );
+    }
+
+    /// Logs the round of the mined tx, a reverted tx is notified as a failed
+    /// update instead.
+    fn succeeded(&self, logger: &slog::Logger, receipt: &TransactionReceipt) -> bool {
+        let reverted = receipt.status == Some(U64::zero());
+        if let Some(ref feed) = self.feed {
+            if let Err(e) = feed.publish_transaction(receipt, reverted) {
+                error!(logger, "streaming the tx failed: {}", e);
+            }
+        }
+        if reverted {
+            error!(logger, "tx {:?} reverted", receipt.transaction_hash);
+            self.notify(
+                logger,
+                Kind::UpdateFailed,
+                serde_json::json!({
+                    "error": "reverted",
+                    "transaction_hash": receipt.transaction_hash,
+                    "block_number": receipt.block_number,
+                }),
+            );
+            return false;
+        }
+        log_round(logger, receipt);
+        true
+    }
+
+    /// Runs the poll loop. Transient failures are retried, the others stop the loop.
+    pub async fn run(&self, logger: &slog::Logger) -> Result<()> {
+        let mut prev_price = 0.0;
+        let mut last_price: Option<f64> = None;
+        let mut source_down = false;
+        let mut divergent = HashSet::new();
+
+        loop {
+            // renewed before the fetch, so a failing source doesn't lose the lease
+            let leader = self.leads(logger);
+
+            self.admin_actions(logger, leader).await;
+            if self.paused() {
+                debug!(logger, "paused by the admin api, the price is not pushed");
+                self.wait().await;
+                continue;
+            }
+
+            let price = match retry(logger, || self.source.fetch()).await {
+                Ok(price) => price,
+                Err(e) if e.is_transient() => {
+                    error!(logger, "price source {} is down: {}", self.source.name(), e);
+                    if !source_down {
+                        source_down = true;
+                        self.notify(
+                            logger,
+                            Kind::SourceDown,
+                            serde_json::json!({
+                                "source": self.source.name(),
+                                "error": e.to_string(),
+                            }),
+                        );
+                    }
+                    self.wait().await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            source_down = false;
+            info!(logger, "one BTC for ETH now is {:#?}", price);
+
+            if let (Some(last), Some(alarm)) = (last_price, self.alarm_deviation) {
+                let deviation = (price - last).abs() / last * 100.0;
+                if deviation > alarm {
+                    warn!(logger, "price moved {:.2}% since the last poll", deviation);
+                    self.notify(
+                        logger,
+                        Kind::Deviation,
+                        serde_json::json!({
+                            "source": self.source.name(),
+                            "previous": last,
+                            "price": price,
+                            "deviation": deviation,
+                        }),
+                    );
+                }
+            }
+            last_price = Some(price).filter(|&price| price > 0.0);
+
+            let new_price = to_answer(price);
+            if let Some(ref feed) = self.feed {
+                if let Err(e) = feed.publish_price(self.source.name(), new_price) {
+                    error!(logger, "streaming the price failed: {}", e);
+                }
+            }
+
+            if let Some(ref node) = self.node {
+                self.exchange(logger, node, new_price, &mut divergent)
+                    .await?;
+            }
+
+            match self.quorum {
+                Some(ref quorum) => match self.report(logger, quorum, new_price, leader).await {
+                    Ok(Some(receipt)) => {
+                        self.succeeded(logger, &receipt);
+                    }
+                    Ok(None) => {}
+                    Err(e @ Error::GasPriceCap { .. }) => {
+                        warn!(logger, "{}", e);
+                        self.update_failed(logger, &e);
+                    }
+                    Err(e) if e.is_transient() => {
+                        error!(logger, "report error: {}", e);
+                        self.update_failed(logger, &e);
+                    }
+                    Err(e) => {
+                        self.update_failed(logger, &e);
+                        return Err(e);
+                    }
+                },
+                None if price > prev_price && leader => {
+                    match self
+                        .send(logger, functions::update_price::encode_input(new_price))
+                        .await
+                    {
+                        Ok(Some(receipt)) => {
+                            if self.succeeded(logger, &receipt) {
+                                prev_price = price;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e @ Error::GasPriceCap { .. }) => {
+                            warn!(logger, "{}", e);
+                            self.update_failed(logger, &e);
+                        }
+                        Err(e) if e.is_transient() => {
+                            error!(logger, "update price error: {}", e);
+                            self.update_failed(logger, &e);
+                        }
+                        Err(e) => {
+                            self.update_failed(logger, &e);
+                            return Err(e);
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            self.wait().await;
+        }
+    }
+
+    /// Publishes the quote of the source, exchanges the quotes with the peers
+    /// and logs the peers diverging from the shared median. The nodes that
+    /// start diverging are notified once, `divergent` keeps the current ones.
+    async fn exchange(
+        &self,
+        logger: &slog::Logger,
+        node: &Node,
+        price: U256,
+        divergent: &mut HashSet<Address>,
+    ) -> Result<()> {
+        node.publish(vec![Quote {
+            source: self.source.name().to_string(),
+            price,
+        }])?;
+        node.exchange(logger).await;
+
+        if let Some(consensus) = node.consensus() {
+            info!(
+                logger,
+                "shared median {} of {} nodes",
+                consensus.median,
+                consensus.nodes.len()
+            );
+            let mut current = HashSet::new();
+            for peer in consensus.nodes.iter().filter(|n| n.divergent) {
+                warn!(
+                    logger,
+                    "node {:?} price {} diverges {:.2}% from the shared median",
+                    peer.node,
+                    peer.price,
+                    peer.deviation
+                );
+                if !divergent.contains(&peer.node) {
+                    self.notify(
+                        logger,
+                        Kind::Deviation,
+                        serde_json::json!({
+                            "node": peer.node,
+                            "price": peer.price,
+                            "median": consensus.median,
+                            "deviation": peer.deviation,
+                        }),
+                    );
+                }
+                current.insert(peer.node);
+            }
+            *divergent = current;
+        }
+        Ok(())
+    }
+
+    /// Observes the answer for the next round, the leader also aggregates the
+    /// observations of the peers and submits the report.
+    async fn report(
+        &self,
+        logger: &slog::Logger,
+        quorum: &Quorum,
+        answer: U256,
+        leader: bool,
+    ) -> Result<Option<TransactionReceipt>> {
+        let latest = self.oracle.latest_round()?;
+        let round_id = latest.as_ref().map_or(U256::zero(), |r| r.round_id) + 1;
+        let own = quorum.reporter.observe(round_id, answer)?;
+        debug!(logger, "observed {} for round {}", answer, round_id);
+
+        if !quorum.leader || !leader {
+            return Ok(None);
+        }
 
-        let poll_interval = cpi.parse::<u64>().unwrap();
+        let (answer, mut signatures) = quorum
+            .reporter
+            .aggregate(logger, &quorum.peers, own)
+            .await?;
+        if latest.map_or(false, |r| r.answer == answer) {
+            debug!(logger, "median {} is unchanged, report is skipped", answer);
+            return Ok(None);
+        }
+
+        // signatures of unregistered reporters would revert the whole report
+        let mut reporters = Vec::with_capacity(signatures.len());
+        for signature in signatures.drain(..) {
+            if self.oracle.is_reporter(signature.reporter)? {
+                reporters.push(signature);
+            } else {
+                warn!(logger, "{:?} is not a reporter", signature.reporter);
+            }
+        }
 
-        Config {
-            api_endpoint: Some(api_endpoint),
-            api_key: Some(api_key),
-            poll_interval: Some(poll_interval),
+        let required = self.oracle.quorum()?;
+        if U256::from(reporters.len()) < required || required.is_zero() {
+            return Err(Error::Quorum(format!(
+                "{} of {} required signatures for round {}",
+                reporters.len(),
+                required,
+                round_id
+            )));
         }
+
+        let signatures = report::concat_signatures(&reporters);
+        self.send(
+            logger,
+            functions::submit_report::encode_input(round_id, answer, signatures),
+        )
+        .await
     }
 }
 
-fn make_url(api_endpoint: String, api_key: String) -> Option<String> {
-    let mut url = api_endpoint + "/data/price?fsym=BTC&tsyms=ETH";
-    url = url + "&api_key=" + api_key.as_ref();
-    Some(url)
+fn lost_lease() -> Error {
+    Error::Admin("this instance lost the leadership, send it to the new leader".to_string())
+}
+
+/// Price of the source as stored in the contract.
+fn to_answer(price: f64) -> U256 {
+    let wei = price * f64::powi(10.0, 18).ceil();
+    let wei = wei as u128;
+    <U256 as From<U128>>::from(wei.into())
+}
+
+fn log_round(logger: &slog::Logger, receipt: &TransactionReceipt) {
+    info!(logger, "tx: {:?}", receipt.transaction_hash);
+    if let Some(round) = Round::from_receipt(receipt) {
+        info!(
+            logger,
+            "round {} updated_at {}", round.round_id, round.updated_at
+        );
+    }
 }
 
-async fn fetch_eth_price(url: hyper::Uri) -> Result<OneBtcToEth, Box<dyn std::error::Error>> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
+#[tokio::main]
+pub async fn run(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+    info!(
+        logger,
+        "service called to the {} with poll interval {}", config.api_endpoint, config.poll_interval
+    );
+
+    let update_conf = updateprice::UpdateConfig::new(arg)?;
+    let (eloop, http) = web3::transports::Http::new(&update_conf.net)?;
+    eloop.into_remote();
 
-    // Fetch the url...
-    let resp = client.get(url).await?;
-    println!("Response: {}", resp.status());
+    let web3 = web3::Web3::new(http);
+    let oracle = update_conf.oracle(web3.clone());
+    let source = CryptoCompare::new(&config.api_endpoint, &config.api_key);
 
-    // asynchronously aggregate the chunks of the body
-    let body = hyper::body::aggregate(resp).await?;
-    let price = serde_json::from_reader(body.reader())?;
+    let mut service = Service::new(
+        source,
+        oracle,
+        time::Duration::from_secs(config.poll_interval),
+    );
 
-    Ok(price)
+    if let Some(reporter_bind) = config.reporter_bind {
+        let signer = update_conf
+            .signer
+            .clone()
+            .ok_or_else(|| Error::missing("from_addr"))?;
+        let reporter = Arc::new(Reporter::new(
+            update_conf.contract_addr,
+            signer,
+            config.max_deviation,
+        )?);
+        info!(
+            logger,
+            "reporting as {:?} with peers {:?}, leader: {}",
+            reporter.address(),
+            config.peers,
+            config.leader
+        );
+
+        tokio::spawn(report::serve(
+            logger.clone(),
+            reporter_bind,
+            reporter.clone(),
+        ));
+        service = service.with_quorum(Quorum {
+            reporter,
+            peers: Peers::new(config.peers),
+            leader: config.leader,
+        });
+    }
+
+    if let Some(ref lease) = config.lease {
+        if config.lease_ttl <= config.poll_interval {
+            return Err(Error::invalid(
+                "lease_ttl",
+                "must be longer than --poll_interval or the leader loses the lease between polls",
+            ));
+        }
+        let election = Election::new(
+            election::lease(lease)?,
+            config.instance_id.clone(),
+            time::Duration::from_secs(config.lease_ttl),
+        );
+        info!(
+            logger,
+            "{} elects the leader with {} and ttl {}s", config.instance_id, lease, config.lease_ttl
+        );
+        service = service.with_election(election);
+    }
+
+    if let Some(node_bind) = config.node_bind {
+        let signer = update_conf
+            .signer
+            .clone()
+            .ok_or_else(|| Error::missing("from_addr"))?;
+        let node = Arc::new(Node::new(
+            signer,
+            config.trusted_nodes,
+            config.node_peers,
+            config.quote_max_age,
+            config.max_divergence,
+        )?);
+
+        tokio::spawn(server::serve_node(logger.clone(), node_bind, node.clone()));
+        service = service.with_node(node);
+    }
+
+    if let Some(stream_bind) = config.stream_bind {
+        // a client of its own, the one of the service is borrowed by the loop
+        let follower = Arc::new(update_conf.oracle(web3));
+        let feed = Arc::new(Feed::from_args(&follower, arg)?);
+        let (confirmations, poll_interval) = (
+            config.confirmations,
+            time::Duration::from_secs(config.poll_interval),
+        );
+
+        let quota = Arc::new(Quota::from_args(arg)?);
+        if let Some(metrics_bind) = args::socket_addr_opt(arg, "metrics_bind")? {
+            tokio::spawn(server::serve_metrics(
+                logger.clone(),
+                metrics_bind,
+                quota.clone(),
+            ));
+        }
+        tokio::spawn(server::serve_stream(
+            logger.clone(),
+            stream_bind,
+            feed.clone(),
+            quota,
+            Cors::from_args(arg)?,
+            Tls::from_args(arg)?,
+        ));
+        tokio::spawn(
+            feed.clone()
+                .follow(logger.clone(), follower, confirmations, poll_interval),
+        );
+        service = service.with_feed(feed);
+    }
+
+    if let Some(admin_bind) = config.admin_bind {
+        let control = Arc::new(Control::new());
+        let admin = Arc::new(Admin::from_args(control.clone(), arg)?);
+        tokio::spawn(server::serve_admin(logger.clone(), admin_bind, admin));
+        service = service.with_control(control);
+    }
+
+    if let Some(notifier) = Notifier::from_args(arg)? {
+        let notifier = Arc::new(notifier);
+        notifier.clone().spawn(logger.clone());
+        service = service.with_notifier(notifier, config.alarm_deviation);
+    }
+
+    service.run(&logger).await
+}
+
+/// Runs the blocking web3 calls on the blocking threads of tokio.
+async fn blocking<R, F>(f: F) -> Result<R>
+where
+    F: FnOnce() -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .expect("blocking web3 call panicked")
 }
 
-#[derive(Deserialize, Debug)]
-struct OneBtcToEth {
-    ETH: f64,
+/// Retries transient failures with exponential backoff.
+async fn retry<T, F, Fut>(logger: &slog::Logger, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Err(e) if e.is_transient() && attempt < MAX_RETRIES => {
+                attempt += 1;
+                let backoff = time::Duration::from_secs(1 << attempt);
+                warn!(
+                    logger,
+                    "attempt {} failed: {}, retry in {:?}", attempt, e, backoff
+                );
+                delay_for(backoff).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+struct Config {
+    api_endpoint: String,
+    api_key: String,
+    poll_interval: u64,
+    /// Address of the reporter endpoints, enables the quorum mode
+    reporter_bind: Option<SocketAddr>,
+    peers: Vec<String>,
+    leader: bool,
+    max_deviation: f64,
+    /// Address of the node endpoints, enables the quote exchange
+    node_bind: Option<SocketAddr>,
+    node_peers: Vec<String>,
+    trusted_nodes: Vec<Address>,
+    quote_max_age: u64,
+    max_divergence: f64,
+    /// `file://` or `redis://` url of the leader lease, enables the election
+    lease: Option<String>,
+    lease_ttl: u64,
+    instance_id: String,
+    alarm_deviation: Option<f64>,
+    /// Address of `/v1/stream`, enables the push feed
+    stream_bind: Option<SocketAddr>,
+    /// Blocks on top of a `PriceChanged` before it is pushed
+    confirmations: u64,
+    /// Address of the `/admin` endpoints, set when the admin API is enabled by
+    /// `--admin_tokens`
+    admin_bind: Option<SocketAddr>,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        let reporter_bind = args::socket_addr_opt(arg, "reporter_bind")?;
+        let node_bind = args::socket_addr_opt(arg, "node_bind")?;
+        let stream_bind = args::socket_addr_opt(arg, "stream_bind")?;
+        let admin_bind = match arg.value_of("admin_tokens") {
+            Some(_) => Some(args::socket_addr(arg, "admin_bind")?),
+            None => None,
+        };
+
+        Ok(Config {
+            api_endpoint: args::required(arg, "api_endpoint")?.to_string(),
+            api_key: args::required(arg, "api_key")?.to_string(),
+            poll_interval: args::parse(arg, "poll_interval")?,
+            reporter_bind,
+            peers: args::urls(arg, "peers"),
+            leader: arg.is_present("leader"),
+            max_deviation: args::parse(arg, "max_deviation")?,
+            node_bind,
+            node_peers: args::urls(arg, "node_peers"),
+            trusted_nodes: args::addresses(arg, "trusted_nodes")?,
+            quote_max_age: args::parse(arg, "quote_max_age")?,
+            max_divergence: args::parse(arg, "max_divergence")?,
+            lease: arg.value_of("lease").map(str::to_string),
+            lease_ttl: args::parse(arg, "lease_ttl")?,
+            alarm_deviation: args::parse_opt(arg, "alarm_deviation")?,
+            stream_bind,
+            confirmations: args::parse(arg, "confirmations")?,
+            admin_bind,
+            instance_id: match arg.value_of("instance_id") {
+                Some(id) => id.to_string(),
+                None => format!(
+                    "{}-{}",
+                    std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string()),
+                    std::process::id()
+                ),
+            },
+        })
+    }
 }
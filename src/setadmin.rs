@@ -0,0 +1,98 @@
+use crate::cli;
+use clap::ArgMatches;
+use priceoracle::bindings::price_oracle::functions;
+use priceoracle::client::{PriceOracle, Signer};
+use priceoracle::error::Result;
+use priceoracle::gasprice::{self, GasConfig};
+use web3::types::Address;
+use web3::Transport;
+
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, config.oracle(web3::Web3::new(ws)), &config)
+}
+
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, config.oracle(web3::Web3::new(http)), &config)
+}
+
+fn run(logger: &slog::Logger, oracle: PriceOracle<impl Transport>, config: &Config) -> Result<()> {
+    info!(
+        logger,
+        "setadmin called to the {} network for admin {:?} with grant {} and contractaddr {:?}",
+        config.net,
+        config.admin,
+        config.grant,
+        config.contract_addr
+    );
+
+    if config.dry_run {
+        let (gas_limit, gas_price) = oracle.estimate(
+            logger,
+            &functions::set_admin::encode_input(config.admin, config.grant),
+        )?;
+        gasprice::print_dry_run(gas_limit, gas_price);
+        return Ok(());
+    }
+
+    let receipt = oracle.set_admin(logger, config.admin, config.grant)?;
+
+    info!(
+        logger,
+        "tx: {:?} confirmed in block {:?}", receipt.transaction_hash, receipt.block_number
+    );
+    info!(
+        logger,
+        "{:?} is admin: {}",
+        config.admin,
+        oracle.is_admin(config.admin)?
+    );
+
+    Ok(())
+}
+
+struct Config {
+    signer: Option<Signer>,
+    contract_addr: Address,
+    admin: Address,
+    /// `true` for `--grant`, `false` for `--revoke`
+    grant: bool,
+    confirmations: usize,
+    gas: GasConfig,
+    net: String,
+    dry_run: bool,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            signer: Signer::from_args(arg)?,
+            contract_addr: cli::address(arg, "contractaddr")?,
+            admin: cli::address(arg, "admin")?,
+            grant: arg.is_present("grant"),
+            confirmations: cli::parse(arg, "confirmations")?,
+            gas: GasConfig::new(arg)?,
+            net: cli::required(arg, "net")?.to_string(),
+            dry_run: arg.is_present("dry_run"),
+        })
+    }
+
+    fn oracle<T: Transport>(&self, web3: web3::Web3<T>) -> PriceOracle<T> {
+        PriceOracle::new(
+            web3,
+            self.contract_addr,
+            self.signer.clone(),
+            self.gas.clone(),
+        )
+        .with_confirmations(self.confirmations)
+    }
+}
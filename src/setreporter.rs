@@ -0,0 +1,120 @@
+use crate::cli;
+use clap::ArgMatches;
+use priceoracle::bindings::price_oracle::functions;
+use priceoracle::client::{PriceOracle, Signer};
+use priceoracle::error::Result;
+use priceoracle::gasprice::{self, GasConfig};
+use web3::types::{Address, U256};
+use web3::Transport;
+
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, config.oracle(web3::Web3::new(ws)), &config)
+}
+
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, config.oracle(web3::Web3::new(http)), &config)
+}
+
+fn run(logger: &slog::Logger, oracle: PriceOracle<impl Transport>, config: &Config) -> Result<()> {
+    info!(
+        logger,
+        "setreporter called to the {} network for reporter {:?} with grant {} and quorum {:?}",
+        config.net,
+        config.reporter,
+        config.grant,
+        config.quorum
+    );
+
+    // the quorum can't exceed the reporters, so a revoke goes after the quorum
+    // is lowered and a grant goes before it is raised
+    let mut calls = Vec::new();
+    if let Some(reporter) = config.reporter {
+        calls.push(functions::set_reporter::encode_input(
+            reporter,
+            config.grant,
+        ));
+    }
+    if let Some(quorum) = config.quorum {
+        let data = functions::set_quorum::encode_input(quorum);
+        if config.grant {
+            calls.push(data);
+        } else {
+            calls.insert(0, data);
+        }
+    }
+
+    for data in calls {
+        if config.dry_run {
+            let (gas_limit, gas_price) = oracle.estimate(logger, &data)?;
+            gasprice::print_dry_run(gas_limit, gas_price);
+            continue;
+        }
+
+        let receipt = oracle.send(logger, data)?;
+        info!(
+            logger,
+            "tx: {:?} confirmed in block {:?}", receipt.transaction_hash, receipt.block_number
+        );
+    }
+
+    if let Some(reporter) = config.reporter {
+        info!(
+            logger,
+            "{:?} is reporter: {}",
+            reporter,
+            oracle.is_reporter(reporter)?
+        );
+    }
+    info!(logger, "quorum: {}", oracle.quorum()?);
+
+    Ok(())
+}
+
+struct Config {
+    signer: Option<Signer>,
+    contract_addr: Address,
+    reporter: Option<Address>,
+    /// `true` for `--grant`, `false` for `--revoke`
+    grant: bool,
+    quorum: Option<U256>,
+    confirmations: usize,
+    gas: GasConfig,
+    net: String,
+    dry_run: bool,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            signer: Signer::from_args(arg)?,
+            contract_addr: cli::address(arg, "contractaddr")?,
+            reporter: cli::address_opt(arg, "reporter")?,
+            grant: arg.is_present("grant"),
+            quorum: cli::u256_opt(arg, "quorum")?,
+            confirmations: cli::parse(arg, "confirmations")?,
+            gas: GasConfig::new(arg)?,
+            net: cli::required(arg, "net")?.to_string(),
+            dry_run: arg.is_present("dry_run"),
+        })
+    }
+
+    fn oracle<T: Transport>(&self, web3: web3::Web3<T>) -> PriceOracle<T> {
+        PriceOracle::new(
+            web3,
+            self.contract_addr,
+            self.signer.clone(),
+            self.gas.clone(),
+        )
+        .with_confirmations(self.confirmations)
+    }
+}
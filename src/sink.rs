@@ -0,0 +1,292 @@
+//! Destinations of the [`EventRecord`]s of `eventread`.
+
+use crate::error::{Error, Result};
+use crate::notify::{Kind, Notifier};
+use crate::record::EventRecord;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+const WEBHOOK_RETRIES: u32 = 5;
+
+/// Writes the records in the order of the stream.
+pub trait Sink {
+    /// Name of the sink used in the logs.
+    fn name(&self) -> &str;
+
+    fn write(&mut self, record: &EventRecord) -> Result<()>;
+}
+
+/// Parses `--sink`: `stdout`, `csv:<path>`, `sqlite:<path>` or `webhook:<url>`.
+pub fn sink(spec: &str) -> Result<Box<dyn Sink>> {
+    let mut parts = spec.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("stdout"), None) => Ok(Box::new(JsonLines::new(io::stdout()))),
+        (Some("csv"), Some(path)) => Ok(Box::new(CsvFile::open(path)?)),
+        (Some("sqlite"), Some(path)) => Ok(Box::new(Sqlite::open(path)?)),
+        (Some("webhook"), Some(url)) => Ok(Box::new(Webhook::new(url)?)),
+        _ => Err(Error::invalid(
+            "sink",
+            format!(
+                "{} is not one of stdout, csv:<path>, sqlite:<path>, webhook:<url>",
+                spec
+            ),
+        )),
+    }
+}
+
+/// One JSON object per line.
+pub struct JsonLines<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(out: W) -> Self {
+        JsonLines { out }
+    }
+}
+
+impl<W: Write> Sink for JsonLines<W> {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn write(&mut self, record: &EventRecord) -> Result<()> {
+        let line = serde_json::to_string(record).map_err(|e| Error::Sink(e.to_string()))?;
+        writeln!(self.out, "{}", line)
+            .and_then(|()| self.out.flush())
+            .map_err(|e| Error::Sink(e.to_string()))
+    }
+}
+
+/// Rows appended to a CSV file, the decoded fields are a JSON column.
+pub struct CsvFile {
+    path: String,
+    writer: csv::Writer<std::fs::File>,
+}
+
+impl CsvFile {
+    const HEADER: [&'static str; 8] = [
+        "status",
+        "event",
+        "block_number",
+        "block_hash",
+        "transaction_hash",
+        "log_index",
+        "timestamp",
+        "fields",
+    ];
+
+    pub fn open(path: &str) -> Result<Self> {
+        let storage = |e: io::Error| Error::Storage(format!("{}: {}", path, e));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(storage)?;
+        let empty = file.metadata().map_err(storage)?.len() == 0;
+
+        let mut csv = CsvFile {
+            path: path.to_string(),
+            writer: csv::Writer::from_writer(file),
+        };
+        if empty {
+            csv.write_row(&Self::HEADER)?;
+        }
+        Ok(csv)
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Result<()> {
+        self.writer
+            .write_record(row)
+            .map_err(|e| Error::Storage(format!("{}: {}", self.path, e)))?;
+        self.writer
+            .flush()
+            .map_err(|e| Error::Storage(format!("{}: {}", self.path, e)))
+    }
+}
+
+impl Sink for CsvFile {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn write(&mut self, record: &EventRecord) -> Result<()> {
+        let fields =
+            serde_json::to_string(&record.fields).map_err(|e| Error::Sink(e.to_string()))?;
+        let row = [
+            record.status.as_str().to_string(),
+            record.event.to_string(),
+            record.block_number.to_string(),
+            format!("{:?}", record.block_hash),
+            record
+                .transaction_hash
+                .map(|hash| format!("{:?}", hash))
+                .unwrap_or_default(),
+            record.log_index.to_string(),
+            record.timestamp.map(|t| t.to_string()).unwrap_or_default(),
+            fields,
+        ];
+        let row: Vec<&str> = row.iter().map(String::as_str).collect();
+        self.write_row(&row)
+    }
+}
+
+/// Rows inserted into the `events` table, created when missing.
+pub struct Sqlite {
+    path: String,
+    conn: rusqlite::Connection,
+}
+
+impl Sqlite {
+    pub fn open(path: &str) -> Result<Self> {
+        let storage = |e: rusqlite::Error| Error::Storage(format!("{}: {}", path, e));
+        let conn = rusqlite::Connection::open(path).map_err(storage)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                status TEXT NOT NULL,
+                event TEXT NOT NULL,
+                block_number INTEGER NOT NULL,
+                block_hash TEXT NOT NULL,
+                transaction_hash TEXT,
+                log_index INTEGER NOT NULL,
+                timestamp INTEGER,
+                fields TEXT NOT NULL
+            )",
+            rusqlite::NO_PARAMS,
+        )
+        .map_err(storage)?;
+
+        Ok(Sqlite {
+            path: path.to_string(),
+            conn,
+        })
+    }
+}
+
+impl Sink for Sqlite {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn write(&mut self, record: &EventRecord) -> Result<()> {
+        let fields =
+            serde_json::to_string(&record.fields).map_err(|e| Error::Sink(e.to_string()))?;
+        self.conn
+            .execute(
+                "INSERT INTO events (status, event, block_number, block_hash, transaction_hash,
+                    log_index, timestamp, fields)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    record.status.as_str(),
+                    record.event,
+                    record.block_number as i64,
+                    format!("{:?}", record.block_hash),
+                    record.transaction_hash.map(|hash| format!("{:?}", hash)),
+                    record.log_index as i64,
+                    record.timestamp.map(|t| t as i64),
+                    fields,
+                ],
+            )
+            .map_err(|e| Error::Storage(format!("{}: {}", self.path, e)))?;
+        Ok(())
+    }
+}
+
+/// JSON record posted to the url, failures are retried with exponential
+/// backoff.
+pub struct Webhook {
+    url: hyper::Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+    runtime: Runtime,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self> {
+        let url = url.parse().map_err(|e| Error::invalid("sink", e))?;
+        let runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Sink(e.to_string()))?;
+
+        Ok(Webhook {
+            url,
+            client: Client::builder().build(HttpsConnector::new()),
+            runtime,
+        })
+    }
+
+    async fn post(
+        client: &Client<HttpsConnector<HttpConnector>>,
+        url: &hyper::Uri,
+        body: &[u8],
+    ) -> std::result::Result<(), String> {
+        let request = Request::post(url.clone())
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_vec()))
+            .map_err(|e| e.to_string())?;
+        let resp = client.request(request).await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("unexpected response status {}", resp.status()));
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn write(&mut self, record: &EventRecord) -> Result<()> {
+        let body = serde_json::to_vec(record).map_err(|e| Error::Sink(e.to_string()))?;
+        let (client, url) = (&self.client, &self.url);
+
+        self.runtime.block_on(async {
+            let mut attempt = 0;
+            loop {
+                match Webhook::post(client, url, &body).await {
+                    Err(_) if attempt < WEBHOOK_RETRIES => {
+                        attempt += 1;
+                        tokio::time::delay_for(Duration::from_secs(1 << attempt)).await;
+                    }
+                    Err(e) => return Err(Error::Sink(format!("{} posting to {}", e, url))),
+                    Ok(()) => return Ok(()),
+                }
+            }
+        })
+    }
+}
+
+/// `PriceChanged` records queued as notifications of `--notify_url`, the other
+/// events are skipped.
+pub struct Notify {
+    notifier: Arc<Notifier>,
+}
+
+impl Notify {
+    pub fn new(notifier: Arc<Notifier>) -> Self {
+        Notify { notifier }
+    }
+}
+
+impl Sink for Notify {
+    fn name(&self) -> &str {
+        "notify"
+    }
+
+    fn write(&mut self, record: &EventRecord) -> Result<()> {
+        if record.event != "PriceChanged" {
+            return Ok(());
+        }
+        let data = serde_json::to_value(record).map_err(|e| Error::Sink(e.to_string()))?;
+        self.notifier.notify(Kind::PriceChanged, data)
+    }
+}
@@ -0,0 +1,75 @@
+#![allow(non_snake_case)]
+
+use crate::error::{Error, Result};
+use bytes::buf::BufExt as _;
+use futures::future::BoxFuture;
+use hyper::Client;
+use hyper_tls::HttpsConnector;
+use serde::Deserialize;
+
+/// External api the price is polled from.
+pub trait PriceSource {
+    /// Name of the source used in the logs.
+    fn name(&self) -> &str;
+
+    /// Fetches how much ETH one BTC is worth.
+    fn fetch(&self) -> BoxFuture<'_, Result<f64>>;
+}
+
+/// https://min-api.cryptocompare.com/ price api.
+pub struct CryptoCompare {
+    url: String,
+}
+
+impl CryptoCompare {
+    pub fn new(api_endpoint: &str, api_key: &str) -> Self {
+        let mut url = api_endpoint.to_string() + "/data/price?fsym=BTC&tsyms=ETH";
+        url = url + "&api_key=" + api_key;
+        CryptoCompare { url }
+    }
+}
+
+impl PriceSource for CryptoCompare {
+    fn name(&self) -> &str {
+        "cryptocompare"
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Result<f64>> {
+        Box::pin(async move {
+            let price: OneBtcToEth = fetch_json(&self.url).await?;
+            Ok(price.ETH)
+        })
+    }
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T> {
+    let url: hyper::Uri = url.parse().map_err(|e| Error::invalid("api_endpoint", e))?;
+
+    let https = HttpsConnector::new();
+    let client = Client::builder().build::<_, hyper::Body>(https);
+
+    // Fetch the url...
+    let resp = client
+        .get(url)
+        .await
+        .map_err(|e| Error::PriceSource(e.to_string()))?;
+    if !resp.status().is_success() {
+        return Err(Error::PriceSource(format!(
+            "unexpected response status {}",
+            resp.status()
+        )));
+    }
+
+    // asynchronously aggregate the chunks of the body
+    let body = hyper::body::aggregate(resp)
+        .await
+        .map_err(|e| Error::PriceSource(e.to_string()))?;
+
+    serde_json::from_reader(body.reader())
+        .map_err(|e| Error::PriceSource(format!("unexpected response body: {}", e)))
+}
+
+#[derive(Deserialize, Debug)]
+struct OneBtcToEth {
+    ETH: f64,
+}
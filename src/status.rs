@@ -0,0 +1,97 @@
+use crate::cli;
+use clap::ArgMatches;
+use priceoracle::client::{PriceOracle, PRICE_DECIMALS};
+use priceoracle::error::Result;
+use priceoracle::gasprice::GasConfig;
+use priceoracle::web3util;
+use web3::futures::Future;
+use web3::types::Address;
+use web3::Transport;
+
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(ws), &config)
+}
+
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = Config::new(arg)?;
+
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
+
+    run(&logger, web3::Web3::new(http), &config)
+}
+
+fn run(logger: &slog::Logger, web3: web3::Web3<impl Transport>, config: &Config) -> Result<()> {
+    debug!(
+        logger,
+        "status called to the {} network with contractaddr {:?}", config.net, config.contract_addr
+    );
+
+    let oracle = PriceOracle::new(web3, config.contract_addr, None, GasConfig::default());
+
+    println!("contract:    {:?}", oracle.address());
+    println!("code hash:   {:?}", oracle.code_hash()?);
+
+    let (decimals, description) = oracle.feed_info()?;
+    println!("feed:        {} with {} decimals", description, decimals);
+
+    let admins = oracle
+        .admins()?
+        .iter()
+        .map(|admin| format!("{:?}", admin))
+        .collect::<Vec<_>>();
+    println!("admins:      {}", admins.join(", "));
+
+    match oracle.latest_round()? {
+        Some(round) => {
+            println!(
+                "price:       {} ({}) in round {}",
+                web3util::format_units(round.answer, PRICE_DECIMALS),
+                round.answer,
+                round.round_id
+            );
+            let block = oracle
+                .last_price_changed()?
+                .and_then(|log| log.block_number);
+            println!(
+                "last update: block {}, {}s ago",
+                block.unwrap_or_default(),
+                round.age()
+            );
+        }
+        None => println!("price:       never set"),
+    }
+
+    let reporter = match config.reporter {
+        Some(reporter) => reporter,
+        None => oracle.from_addr()?,
+    };
+    let balance = oracle.web3().eth().balance(reporter, None).wait()?;
+    println!("reporter:    {:?}", reporter);
+    println!("balance:     {} ETH", web3util::format_units(balance, 18));
+    println!("nonce:       {}", web3util::nonce(reporter, oracle.web3())?);
+
+    Ok(())
+}
+
+struct Config {
+    contract_addr: Address,
+    /// Reporter account, the first account of the node when unset
+    reporter: Option<Address>,
+    net: String,
+}
+
+impl Config {
+    fn new(arg: &ArgMatches) -> Result<Self> {
+        Ok(Config {
+            contract_addr: cli::address(arg, "contractaddr")?,
+            reporter: cli::address_opt(arg, "from_addr")?,
+            net: cli::required(arg, "net")?.to_string(),
+        })
+    }
+}
@@ -0,0 +1,248 @@
+//! TLS termination of `server` with the PEM certificate and key reloaded when
+//! their files change.
+//!
+//! The handshakes run in their own tasks, so a slow or broken client doesn't
+//! hold the accept loop; the established streams are handed to hyper.
+
+use crate::args;
+use crate::error::{Error, Result};
+use clap::{Arg, ArgMatches};
+use futures::channel::mpsc;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{delay_for, timeout};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Time a client has to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate and key of the handshakes with the modification times of
+/// their files when they were loaded.
+struct Loaded {
+    key: CertifiedKey,
+    modified: (SystemTime, SystemTime),
+}
+
+/// Serves the last loaded certificate to every handshake.
+struct Reloading {
+    cert_path: String,
+    key_path: String,
+    loaded: RwLock<Loaded>,
+}
+
+impl ResolvesServerCert for Reloading {
+    fn resolve(&self, _: ClientHello) -> Option<CertifiedKey> {
+        Some(self.read().key.clone())
+    }
+}
+
+impl Reloading {
+    fn open(cert_path: &str, key_path: &str) -> Result<Self> {
+        let loaded = Reloading::load(cert_path, key_path)?;
+        Ok(Reloading {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    fn load(cert_path: &str, key_path: &str) -> Result<Loaded> {
+        // read before the contents, so a change during the load is seen later
+        let modified = (modified(cert_path)?, modified(key_path)?);
+
+        let certs = pemfile::certs(&mut open(cert_path)?)
+            .map_err(|()| Error::invalid("tls_cert", "is not a PEM certificate"))?;
+        if certs.is_empty() {
+            return Err(Error::invalid("tls_cert", "has no certificate"));
+        }
+        let mut keys = pemfile::pkcs8_private_keys(&mut open(key_path)?)
+            .map_err(|()| Error::invalid("tls_key", "is not a PEM key"))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut open(key_path)?)
+                .map_err(|()| Error::invalid("tls_key", "is not a PEM key"))?;
+        }
+        let key = keys
+            .first()
+            .ok_or_else(|| Error::invalid("tls_key", "has no PKCS#8 or RSA private key"))?;
+        let signing_key = sign::any_supported_type(key)
+            .map_err(|()| Error::invalid("tls_key", "is not an RSA, ECDSA or Ed25519 key"))?;
+
+        Ok(Loaded {
+            key: CertifiedKey::new(certs, Arc::new(signing_key)),
+            modified,
+        })
+    }
+
+    /// Loads the files again if one of them changed, returns whether it did.
+    fn reload(&self) -> Result<bool> {
+        let modified = (modified(&self.cert_path)?, modified(&self.key_path)?);
+        if modified == self.read().modified {
+            return Ok(false);
+        }
+        // a failed load keeps the old certificate, the next poll retries it
+        let loaded = Reloading::load(&self.cert_path, &self.key_path)?;
+        *self
+            .loaded
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = loaded;
+        Ok(true)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Loaded> {
+        self.loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| Error::Storage(format!("{}: {}", path, e)))
+}
+
+fn modified(path: &str) -> Result<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| Error::Storage(format!("{}: {}", path, e)))
+}
+
+/// TLS acceptor of `--tls_cert` and `--tls_key`.
+pub struct Tls {
+    certs: Arc<Reloading>,
+    acceptor: TlsAcceptor,
+    reload_interval: Duration,
+}
+
+impl Tls {
+    pub fn new(cert_path: &str, key_path: &str, reload_interval: Duration) -> Result<Self> {
+        let certs = Arc::new(Reloading::open(cert_path, key_path)?);
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.cert_resolver = certs.clone();
+        // the WebSocket upgrade of /v1/stream needs HTTP/1.1
+        config.set_protocols(&[b"http/1.1".to_vec()]);
+
+        Ok(Tls {
+            certs,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            reload_interval,
+        })
+    }
+
+    /// TLS of the arguments, `None` without `--tls_cert`.
+    pub fn from_args(arg: &ArgMatches) -> Result<Option<Self>> {
+        let cert_path = match arg.value_of("tls_cert") {
+            Some(cert_path) => cert_path,
+            None => return Ok(None),
+        };
+        let reload_interval = match args::parse(arg, "tls_reload_interval")? {
+            0 => return Err(Error::invalid("tls_reload_interval", "must be at least 1")),
+            seconds => Duration::from_secs(seconds),
+        };
+        Tls::new(cert_path, args::required(arg, "tls_key")?, reload_interval).map(Some)
+    }
+
+    /// Polls the certificate and key files and swaps them when they change.
+    pub async fn watch(&self, logger: slog::Logger) {
+        loop {
+            delay_for(self.reload_interval).await;
+            match self.certs.reload() {
+                Ok(true) => info!(logger, "reloaded the tls certificate"),
+                Ok(false) => {}
+                Err(e) => error!(logger, "reloading the tls certificate failed: {}", e),
+            }
+        }
+    }
+
+    /// Accepts the connections of the address and yields the established
+    /// TLS streams.
+    pub async fn incoming(
+        &self,
+        logger: slog::Logger,
+        addr: SocketAddr,
+    ) -> Result<mpsc::UnboundedReceiver<io::Result<TlsStream<TcpStream>>>> {
+        let mut listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| Error::invalid("bind", e))?;
+        let (sender, receiver) = mpsc::unbounded();
+        let acceptor = self.acceptor.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (tcp, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // out of file descriptors, give the open ones time to close
+                        warn!(logger, "accepting a connection failed: {}", e);
+                        delay_for(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let (logger, acceptor, sender) = (logger.clone(), acceptor.clone(), sender.clone());
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.unbounded_send(Ok(stream));
+                        }
+                        Ok(Err(e)) => debug!(logger, "tls handshake with {} failed: {}", peer, e),
+                        Err(_) => debug!(logger, "tls handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+/// TLS arguments of `server` and of the stream of `service`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("tls_cert")
+            .env("PO_TLS_CERT")
+            .long("tls_cert")
+            .requires("tls_key")
+            .help("PEM certificate chain, serves https instead of http"),
+        Arg::with_name("tls_key")
+            .env("PO_TLS_KEY")
+            .long("tls_key")
+            .requires("tls_cert")
+            .help("PEM private key of the certificate, PKCS#8 or RSA"),
+        Arg::with_name("tls_reload_interval")
+            .env("PO_TLS_RELOAD_INTERVAL")
+            .long("tls_reload_interval")
+            .default_value("10")
+            .help("interval in seconds between the checks of the certificate files for changes"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::App;
+
+    #[test]
+    fn rejects_a_zero_reload_interval() {
+        let matches = App::new("test").args(&args()).get_matches_from(vec![
+            "test",
+            "--tls_cert",
+            "cert.pem",
+            "--tls_key",
+            "key.pem",
+            "--tls_reload_interval",
+            "0",
+        ]);
+        match Tls::from_args(&matches) {
+            Err(Error::Config { arg, .. }) => assert_eq!(arg, "tls_reload_interval"),
+            _ => panic!("a zero reload interval is accepted"),
+        }
+    }
+}
@@ -1,254 +1,114 @@
-use crate::web3util;
+use crate::args;
+use crate::bindings::price_oracle::functions;
+use crate::client::{PriceOracle, Round, Signer};
+use crate::error::Result;
+use crate::gasprice::{self, GasConfig};
 use clap::ArgMatches;
 use core::fmt;
-use std::borrow::Cow;
-use std::convert::TryFrom;
-use std::time::Duration;
-use std::vec::Vec;
-use web3::contract::{Contract, Options};
-use web3::futures::Future;
-use web3::types::{Address, H256, U256};
+use web3::types::{Address, U256};
 use web3::Transport;
 
-#[derive(RustEmbed)]
-#[folder = "src/contract/"]
-struct Asset;
+pub fn run_with_ws(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = UpdateConfig::new(arg)?;
 
-pub fn run_with_ws(
-    logger: slog::Logger,
-    arg: &ArgMatches,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = UpdateConfig::new(arg);
-
-    info!(
-        logger,
-        "updateprice called to the {} network with {} price and contractaddr {} and \
-         gas_limit {}",
-        config.net,
-        config.new_price,
-        config.contract_addr.unwrap(),
-        config.gas_limit
-    );
-
-    let (eloop, http) = web3::transports::WebSocket::new(&config.net).unwrap();
+    let (eloop, ws) = web3::transports::WebSocket::new(&config.net)?;
     eloop.into_remote();
 
-    let web3 = web3::Web3::new(http);
+    run(&logger, config.oracle(web3::Web3::new(ws)), &config)
+}
 
-    let tx = match config.from_addr {
-        None => with_own_eth_node(web3, &config),
-        Some(_) => with_existing_wallet(web3, config),
-    };
+pub fn run_with_http(logger: slog::Logger, arg: &ArgMatches) -> Result<()> {
+    let config = UpdateConfig::new(arg)?;
 
-    info!(logger, "tx: {:?}", tx);
+    let (eloop, http) = web3::transports::Http::new(&config.net)?;
+    eloop.into_remote();
 
-    Ok(())
+    run(&logger, config.oracle(web3::Web3::new(http)), &config)
 }
 
-pub fn run_with_http(
-    logger: slog::Logger,
-    arg: &ArgMatches,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = UpdateConfig::new(arg);
-
+fn run(
+    logger: &slog::Logger,
+    oracle: PriceOracle<impl Transport>,
+    config: &UpdateConfig,
+) -> Result<()> {
     info!(
         logger,
         "updateprice called to the {} network with {} price and contractaddr {} and \
-         gas_limit {}",
+         gas_limit {:?}",
         config.net,
         config.new_price,
-        config.contract_addr.unwrap(),
-        config.gas_limit
-    );
-
-    let (eloop, http) = web3::transports::Http::new(&config.net).unwrap();
-    eloop.into_remote();
-
-    let web3 = web3::Web3::new(http);
-
-    let tx = match config.from_addr {
-        None => with_own_eth_node(web3, &config),
-        Some(_) => with_existing_wallet(web3, config),
-    };
-
-    info!(logger, "tx: {:?}", tx);
-
-    Ok(())
-}
-
-fn with_existing_wallet(
-    eth_client: web3::Web3<impl Transport>,
-    conf: UpdateConfig,
-) -> Result<H256, Box<dyn std::error::Error>> {
-    let method_id = ethtxsign::keccak256_hash(b"updatePrice(uint256)");
-    let update_price_abi = format!(
-        "{}{:064x}",
-        &hex::encode(method_id)[..8],
-        &conf.new_price.as_u64()
+        config.contract_addr,
+        config.gas.gas_limit
     );
-    println!("update_price_abi {}", update_price_abi);
-
-    let nonce_cnt = web3util::nonce(conf.from_addr.unwrap(), &eth_client).unwrap();
-
-    let gas_price = match eth_client.eth().gas_price().wait() {
-        Ok(gas_price) => gas_price,
-        Err(e) => return Err(Box::try_from(e).unwrap()),
-    };
-
-    let cdata = hex::decode(update_price_abi.as_bytes());
-
-    let tx_request = ethtxsign::RawTransaction {
-        to: conf.contract_addr,
-        gas: conf.gas_limit,
-        gas_price: gas_price.into(),
-        value: 0.into(),
-        data: cdata.unwrap(),
-        nonce: nonce_cnt,
-    };
-
-    let tx = tx_request.sign(&conf.pvt_key, &conf.chain_id);
 
-    let result =
-        eth_client.send_raw_transaction_with_confirmation(tx.into(), Duration::from_secs(1), 1);
-
-    let receipt = match result.wait() {
-        Ok(receipt) => receipt,
-        Err(e) => return Err(Box::try_from(e).unwrap()),
-    };
+    if config.dry_run {
+        let (gas_limit, gas_price) = oracle.estimate(
+            logger,
+            &functions::update_price::encode_input(config.new_price),
+        )?;
+        gasprice::print_dry_run(gas_limit, gas_price);
+        return Ok(());
+    }
 
-    Ok(receipt.transaction_hash)
-}
+    let receipt = oracle.update_price(logger, config.new_price)?;
 
-fn with_own_eth_node(
-    eth_client: web3::Web3<impl Transport>,
-    conf: &UpdateConfig,
-) -> Result<H256, Box<dyn std::error::Error>> {
-    let contract = Contract::from_json(
-        eth_client.eth(),
-        conf.contract_addr.unwrap(),
-        conf.contract_abi.as_slice(),
-    )
-    .unwrap();
-
-    let accounts = eth_client.eth().accounts().wait().unwrap();
-
-    if accounts.len() == 0 {
-        return Err(
-            Box::try_from(String::from("there is no any accounts for contract deploy")).unwrap(),
+    info!(logger, "tx: {:?}", receipt.transaction_hash);
+    if let Some(round) = Round::from_receipt(&receipt) {
+        info!(
+            logger,
+            "round {} answer {} updated_at {}", round.round_id, round.answer, round.updated_at
         );
     }
 
-    let options = if conf.gas_limit.ne(&U256::zero()) {
-        let gas_price = match eth_client.eth().gas_price().wait() {
-            Ok(gas_price) => gas_price,
-            Err(e) => return Err(Box::try_from(e).unwrap()),
-        };
-
-        Options {
-            gas: Some(conf.gas_limit),
-            gas_price: Some(gas_price),
-            value: None,
-            nonce: None,
-            condition: None,
-        }
-    } else {
-        Options::default()
-    };
-
-    let result = contract.call(
-        "updatePrice",
-        (conf.new_price,),
-        accounts[0].into(),
-        options.into(),
-    );
-
-    let tx = result.wait().unwrap();
-
-    Ok(tx)
-}
-
-pub fn update_price(
-    logger: &slog::Logger,
-    conf: &UpdateConfig,
-) -> Result<(), Box<dyn std::error::Error>> {
-    info!(logger, "update_config: {}", conf);
-
-    let (eloop, http) = web3::transports::Http::new(&conf.net).unwrap();
-    eloop.into_remote();
-
-    let web3 = web3::Web3::new(http);
-
-    let tx = with_own_eth_node(web3, conf);
-
-    info!(logger, "tx: {:?}", tx);
-
     Ok(())
 }
 
+/// Settings of the `updateprice` subcommand.
 pub struct UpdateConfig {
-    from_addr: Option<Address>,
-    contract_addr: Option<Address>,
+    pub signer: Option<Signer>,
+    pub contract_addr: Address,
     pub new_price: U256,
-    pvt_key: H256,
-    gas_limit: U256,
-    contract_abi: Vec<u8>,
-    chain_id: u8,
-    net: String,
+    pub gas: GasConfig,
+    pub net: String,
+    pub dry_run: bool,
 }
 
 impl fmt::Display for UpdateConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "(from_addr: {}, contract_addr: {}, new_price: {})",
-            self.from_addr.unwrap(),
-            self.contract_addr.unwrap(),
+            "(from_addr: {:?}, contract_addr: {:?}, new_price: {})",
+            self.signer.as_ref().map(|s| s.address),
+            self.contract_addr,
             self.new_price
         )
     }
 }
 
 impl UpdateConfig {
-    pub(crate) fn new(arg: &ArgMatches) -> Self {
-        let net = arg.value_of("net").unwrap().to_string();
-
-        let my_account = arg.value_of("from_addr").unwrap();
-        let fr: Address = my_account.parse().unwrap();
-
-        let ca = arg.value_of("contractaddr").unwrap();
-        let contract_address: Address = ca.parse().unwrap();
-
-        let np = match arg.value_of("newprice") {
-            Some(val) => val,
-            None => "10",
-        };
-        let new_price = U256::from_dec_str(np).unwrap();
-
-        let pk = arg.value_of("private_key").unwrap();
-        let pvt_key =
-            ethtxsign::pvt_key_from_slice(hex::decode(pk.as_bytes()).unwrap().as_slice()).unwrap();
-
-        let gl = arg.value_of("gas_limit").unwrap();
-        let gas_limit: U256 = U256::from_dec_str(gl).unwrap();
-
-        let cid = arg.value_of("chain_id").unwrap();
-        let chain_id = cid.parse::<u8>().unwrap();
-
-        let cabi = match Asset::get("PriceOracle.abi") {
-            Some(val) => val,
-            _ => Cow::Borrowed("moo".as_bytes()),
+    pub fn new(arg: &ArgMatches) -> Result<Self> {
+        let new_price = match arg.value_of("newprice") {
+            Some(_) => args::u256(arg, "newprice")?,
+            None => U256::from(10),
         };
-        let contract_abi = cabi.as_ref().to_vec();
 
-        UpdateConfig {
-            from_addr: Some(fr),
-            contract_addr: Some(contract_address),
+        Ok(UpdateConfig {
+            signer: args::signer(arg)?,
+            contract_addr: args::address(arg, "contractaddr")?,
             new_price,
-            pvt_key,
-            gas_limit,
-            contract_abi,
-            chain_id,
-            net,
-        }
+            gas: GasConfig::new(arg)?,
+            net: args::required(arg, "net")?.to_string(),
+            dry_run: arg.is_present("dry_run"),
+        })
+    }
+
+    /// Client of the configured contract over the transport.
+    pub fn oracle<T: Transport>(&self, web3: web3::Web3<T>) -> PriceOracle<T> {
+        PriceOracle::new(
+            web3,
+            self.contract_addr,
+            self.signer.clone(),
+            self.gas.clone(),
+        )
     }
 }
@@ -1,17 +1,44 @@
+use crate::error::Result;
 use web3::futures::Future;
-use web3::types::{Address, U256, H256};
+use web3::types::{Address, BlockNumber, TransactionReceipt, H256, U256};
 use web3::Transport;
 
-pub fn nonce(addr: Address, eth_client: &web3::Web3<impl Transport>) -> Result<U256, String> {
-    let nonce_cnt = match eth_client.eth().transaction_count(addr, None).wait() {
-        Ok(nonce) => nonce,
-        Err(e) => return Err(e.to_string()),
-    };
+/// Next nonce of the address, counting its txs still in the pool.
+pub fn nonce(addr: Address, eth_client: &web3::Web3<impl Transport>) -> Result<U256> {
+    let nonce_cnt = eth_client
+        .eth()
+        .transaction_count(addr, Some(BlockNumber::Pending))
+        .wait()?;
     Ok(nonce_cnt)
 }
 
-pub fn h256_topic(topic: Vec<u8>) -> Option<H256> {
+pub fn h256_topic(topic: Vec<u8>) -> H256 {
     let mut h = H256::zero();
     h.as_bytes_mut().copy_from_slice(topic.as_slice());
-    Some(h)
-}
\ No newline at end of file
+    h
+}
+
+/// Logs the wei spent by the mined tx, used for the gas budget reporting.
+pub fn log_tx_cost(logger: &slog::Logger, receipt: &TransactionReceipt, gas_price: U256) {
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    info!(
+        logger,
+        "tx {:?} gas_used {} gas_price {} cost {} wei",
+        receipt.transaction_hash,
+        gas_used,
+        gas_price,
+        gas_used * gas_price
+    );
+}
+
+/// Formats the integer value scaled down by `10^decimals`, e.g. wei as ether.
+pub fn format_units(value: U256, decimals: usize) -> String {
+    let digits = format!("{:0>width$}", value.to_string(), width = decimals + 1);
+    let (int, frac) = digits.split_at(digits.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int.to_string()
+    } else {
+        format!("{}.{}", int, frac)
+    }
+}
@@ -0,0 +1,70 @@
+//! Local receiver of the webhook notifications, prints the verified ones.
+
+use crate::cli;
+use clap::ArgMatches;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use priceoracle::error::Result;
+use priceoracle::notify::{self, Notification};
+use std::sync::Arc;
+
+/// Plain text answer, the notifier only reads the status.
+fn error(status: StatusCode, message: impl std::fmt::Display) -> Response<Body> {
+    let mut response = Response::new(Body::from(message.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+async fn routes(
+    logger: slog::Logger,
+    secret: Arc<Vec<u8>>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    if req.method() != Method::POST {
+        return Ok(error(StatusCode::METHOD_NOT_ALLOWED, "POST only"));
+    }
+    let signature = req
+        .headers()
+        .get(notify::SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = hyper::body::to_bytes(req).await?;
+
+    if !notify::verify(&secret, &body, &signature) {
+        warn!(logger, "notification with an invalid signature rejected");
+        return Ok(error(StatusCode::UNAUTHORIZED, "invalid signature"));
+    }
+    let notification: Notification = match serde_json::from_slice(&body) {
+        Ok(notification) => notification,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+    };
+
+    info!(
+        logger,
+        "{:?} notification {}", notification.kind, notification.id
+    );
+    println!("{}", String::from_utf8_lossy(&body));
+    Ok(Response::new(Body::empty()))
+}
+
+#[tokio::main]
+pub async fn run(logger: slog::Logger, arg: &ArgMatches<'_>) -> Result<()> {
+    let secret = Arc::new(cli::required(arg, "notify_secret")?.as_bytes().to_vec());
+
+    let service_logger = logger.clone();
+    let service = make_service_fn(move |_| {
+        let (logger, secret) = (service_logger.clone(), secret.clone());
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                routes(logger.clone(), secret.clone(), req)
+            }))
+        }
+    });
+
+    let addr = cli::socket_addr(arg, "bind")?;
+    info!(logger, "receiving notifications on http://{}", addr);
+    Server::bind(&addr).serve(service).await?;
+
+    Ok(())
+}